@@ -1,61 +1,208 @@
 use super::codec::{decode_message, encode_message};
-use crate::fix::{self, Side};
+use crate::fix::{self, OrderType, Side, TimeInForce, TrailingOffset};
 use fix::{FixMessage, MessageField, MessageType};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::TcpStream,
 };
 
+// Seconds the server may go without hearing from this client
+const HEARTBEAT_INTERVAL: u64 = 30;
+
+// A logged on connection to the server, which numbers every message sent over it
+pub struct Session {
+    stream: TcpStream,
+    out_seq: u64,
+}
+
+impl Session {
+    pub async fn logon(
+        stream: TcpStream,
+        comp_id: &str,
+    ) -> Result<Session, Box<dyn std::error::Error>> {
+        let mut session = Session { stream, out_seq: 0 };
+        let fix_msg: FixMessage = (
+            MessageType::Logon,
+            vec![
+                MessageField::SenderCompId(comp_id.to_string()),
+                MessageField::HeartbeatInterval(HEARTBEAT_INTERVAL),
+            ],
+        );
+        session.write(fix_msg).await?;
+        let response_msg = session.read().await?;
+        if response_msg.0 != MessageType::Logon {
+            return Err(format!("Logon refused: {:?}", response_msg).into());
+        }
+        println!("Logged on as {}", comp_id);
+        Ok(session)
+    }
+
+    pub async fn logout(mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.write((MessageType::Logout, vec![])).await?;
+        // Executions pushed meanwhile may arrive before the answer
+        loop {
+            let response_msg = self.read().await?;
+            if response_msg.0 == MessageType::Logout {
+                println!("Logged out: {:?}", response_msg);
+                return Ok(());
+            }
+            println!("Execution: {:?}", response_msg);
+        }
+    }
+
+    async fn write(&mut self, mut fix_msg: FixMessage) -> Result<(), Box<dyn std::error::Error>> {
+        self.out_seq += 1;
+        fix_msg.1.push(MessageField::MsgSeqNum(self.out_seq));
+        let buf = encode_message(&fix_msg);
+        let bytes = buf.as_bytes().to_vec();
+        let len = bytes.len() as u32;
+
+        self.stream.write_u32(len.to_be()).await?;
+        self.stream.write_all(&bytes).await?;
+        Ok(())
+    }
+
+    // Reads the next message that is not session housekeeping, answering any test request
+    async fn read(&mut self) -> Result<FixMessage, Box<dyn std::error::Error>> {
+        loop {
+            let response_len = self.stream.read_u32().await?.to_be();
+            let mut response = vec![0u8; response_len as usize];
+            self.stream.read_exact(&mut response).await?;
+            let response_str = String::from_utf8(response)?;
+
+            let fix_msg = decode_message(&response_str).ok_or_else(|| {
+                eprintln!("Error decoding FixMessage");
+                "Error decoding FixMessage"
+            })?;
+            match fix_msg.0 {
+                MessageType::Heartbeat => {}
+                MessageType::TestRequest => {
+                    let fields = fix_msg
+                        .1
+                        .into_iter()
+                        .filter(|field| matches!(field, MessageField::TestReqId(_)))
+                        .collect();
+                    self.write((MessageType::Heartbeat, fields)).await?;
+                }
+                _ => return Ok(fix_msg),
+            }
+        }
+    }
+}
+
 async fn send_fix_message(
-    fix_msg: FixMessage,
-    stream: &mut TcpStream,
+    mut fix_msg: FixMessage,
+    symbol: &str,
+    session: &mut Session,
 ) -> Result<FixMessage, Box<dyn std::error::Error>> {
-    let buf = encode_message(&fix_msg);
-    let bytes = buf.as_bytes().to_vec();
-    let len = bytes.len() as u32;
-
-    stream.write_u32(len.to_be()).await?;
-    stream.write_all(&bytes).await?;
-
-    let response_len = stream.read_u32().await?.to_be();
-    let mut response = vec![0u8; response_len as usize];
-    stream.read_exact(&mut response).await?;
-    let response_str = String::from_utf8(response)?;
-
-    decode_message(&response_str).ok_or_else(|| {
-        eprintln!("Error decoding FixMessage");
-        "Error decoding FixMessage".into()
-    })
+    fix_msg.1.push(MessageField::Symbol(symbol.to_string()));
+    session.write(fix_msg).await?;
+    session.read().await
+}
+
+// Optional order attributes given alongside --buy or --sell
+pub struct OrderOptions<'a> {
+    pub is_market: bool,
+    pub time_in_force: Option<&'a str>,
+    pub stop_price: Option<&'a str>,
+    pub trailing_offset: Option<&'a str>,
+    pub display_quantity: Option<&'a str>,
+    pub is_post_only: bool,
+    pub is_hidden: bool,
+    // Keep printing the executions the server pushes for the order
+    pub follow: bool,
 }
 
 pub async fn send_order(
     order: &str,
-    stream: &mut TcpStream,
+    symbol: &str,
+    session: &mut Session,
     is_buy: bool,
+    options: &OrderOptions<'_>,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let OrderOptions {
+        is_market,
+        time_in_force,
+        stop_price,
+        trailing_offset,
+        display_quantity,
+        is_post_only,
+        is_hidden,
+        follow,
+    } = *options;
     let args: Vec<&str> = order.split(",").collect();
     let quantity = args[0].parse::<u64>().unwrap();
-    let price = args[1].parse::<f64>().unwrap();
     let side = if is_buy { Side::Buy } else { Side::Sell };
 
     println!("Sending order: {}", order);
-    let fix_msg = (
-        MessageType::NewOrder,
-        vec![
-            MessageField::Side(side),
-            MessageField::Quantity(quantity),
-            MessageField::Price(price),
-        ],
-    );
+    let mut fields = vec![MessageField::Side(side), MessageField::Quantity(quantity)];
+    let order_type = match (is_market, stop_price.is_some(), trailing_offset.is_some()) {
+        (_, _, true) => OrderType::TrailingStop,
+        (true, false, _) => OrderType::Market,
+        (true, true, _) => OrderType::Stop,
+        (false, true, _) => OrderType::StopLimit,
+        (false, false, _) => OrderType::Limit,
+    };
+    if order_type != OrderType::Limit {
+        fields.push(MessageField::OrderType(order_type));
+    }
+    if let Some(offset) = trailing_offset {
+        fields.push(MessageField::TrailingOffset(
+            match offset.strip_suffix('%') {
+                Some(percent) => TrailingOffset::Percent(percent.parse::<f64>()?),
+                None => TrailingOffset::Amount(offset.parse::<f64>()?),
+            },
+        ));
+    } else if !is_market {
+        let price = args[1].parse::<f64>().unwrap();
+        fields.push(MessageField::Price(price));
+    }
+    if let Some(stop_price) = stop_price {
+        fields.push(MessageField::StopPrice(stop_price.parse::<f64>()?));
+    }
+    if let Some(display_quantity) = display_quantity {
+        fields.push(MessageField::DisplayQuantity(
+            display_quantity.parse::<u64>()?,
+        ));
+    }
+    if is_post_only {
+        fields.push(MessageField::PostOnly(true));
+    }
+    if is_hidden {
+        fields.push(MessageField::Hidden(true));
+    }
+    if let Some(time_in_force) = time_in_force {
+        // GTD orders carry their expiry as a unix timestamp, e.g. GTD:1700000000
+        let (time_in_force, expire_at) =
+            time_in_force.split_once(':').unwrap_or((time_in_force, ""));
+        fields.push(MessageField::TimeInForce(match time_in_force {
+            "IOC" => TimeInForce::Ioc,
+            "FOK" => TimeInForce::Fok,
+            "GTD" => TimeInForce::Gtd,
+            "DAY" => TimeInForce::Day,
+            _ => TimeInForce::Gtc,
+        }));
+        if !expire_at.is_empty() {
+            fields.push(MessageField::ExpireTime(expire_at.parse::<i64>()?));
+        }
+    }
+    let fix_msg = (MessageType::NewOrder, fields);
 
-    let response_msg = send_fix_message(fix_msg, stream).await?;
+    let response_msg = send_fix_message(fix_msg, symbol, session).await?;
     println!("Response: {:?}", response_msg);
+    if follow {
+        loop {
+            let execution_msg = session.read().await?;
+            println!("Execution: {:?}", execution_msg);
+        }
+    }
     Ok(())
 }
 
 pub async fn amend_order(
     order: &str,
-    stream: &mut TcpStream,
+    symbol: &str,
+    session: &mut Session,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<&str> = order.split(",").collect();
     let id = args[0].parse::<u64>().unwrap();
@@ -71,14 +218,15 @@ pub async fn amend_order(
         ],
     );
 
-    let response_msg = send_fix_message(fix_msg, stream).await?;
+    let response_msg = send_fix_message(fix_msg, symbol, session).await?;
     println!("Response: {:?}", response_msg);
     Ok(())
 }
 
 pub async fn cancel_order(
     id: &str,
-    stream: &mut TcpStream,
+    symbol: &str,
+    session: &mut Session,
 ) -> Result<(), Box<dyn std::error::Error>> {
     println!("Cancelling order with ID: {}", id);
     let fix_msg: FixMessage = (
@@ -86,14 +234,15 @@ pub async fn cancel_order(
         vec![MessageField::OrderId(id.parse::<u64>().unwrap())],
     );
 
-    let response_msg = send_fix_message(fix_msg, stream).await?;
+    let response_msg = send_fix_message(fix_msg, symbol, session).await?;
     println!("Response: {:?}", response_msg);
     Ok(())
 }
 
 pub async fn status_report(
     id: &str,
-    stream: &mut TcpStream,
+    symbol: &str,
+    session: &mut Session,
 ) -> Result<(), Box<dyn std::error::Error>> {
     println!("Getting status report for order with ID: {}", id);
     let fix_msg: FixMessage = (
@@ -101,14 +250,15 @@ pub async fn status_report(
         vec![MessageField::OrderId(id.parse::<u64>().unwrap())],
     );
 
-    let response_msg = send_fix_message(fix_msg, stream).await?;
+    let response_msg = send_fix_message(fix_msg, symbol, session).await?;
     println!("Response: {:?}", response_msg);
     Ok(())
 }
 
 pub async fn volume_at_limit(
     price: &str,
-    stream: &mut TcpStream,
+    symbol: &str,
+    session: &mut Session,
 ) -> Result<(), Box<dyn std::error::Error>> {
     println!("Getting volume at limit: {}", price);
     let price = price.parse::<f64>().unwrap();
@@ -117,43 +267,75 @@ pub async fn volume_at_limit(
         vec![MessageField::VolumeAtLimit(price)],
     );
 
-    let response_msg = send_fix_message(fix_msg, stream).await?;
+    let response_msg = send_fix_message(fix_msg, symbol, session).await?;
     println!("Response: {:?}", response_msg);
     Ok(())
 }
 
-pub async fn market_price(stream: &mut TcpStream) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn market_price(
+    symbol: &str,
+    session: &mut Session,
+) -> Result<(), Box<dyn std::error::Error>> {
     println!("Getting market price...");
     let fix_msg: FixMessage = (
         MessageType::MarketDataRequest,
         vec![MessageField::MarketPrice(true)],
     );
 
-    let response_msg = send_fix_message(fix_msg, stream).await?;
+    let response_msg = send_fix_message(fix_msg, symbol, session).await?;
     println!("Response: {:?}", response_msg);
     Ok(())
 }
 
-pub async fn trades(stream: &mut TcpStream) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn trades(symbol: &str, session: &mut Session) -> Result<(), Box<dyn std::error::Error>> {
     println!("Getting recent trades...");
     let fix_msg: FixMessage = (
         MessageType::MarketDataRequest,
         vec![MessageField::MarketTrades(true)],
     );
 
-    let response_msg = send_fix_message(fix_msg, stream).await?;
+    let response_msg = send_fix_message(fix_msg, symbol, session).await?;
+    println!("Response: {:?}", response_msg);
+    Ok(())
+}
+
+// Gets the top `levels` price levels on each side, then with `follow` prints each
+// change to them as the server pushes it
+pub async fn depth(
+    levels: &str,
+    follow: bool,
+    symbol: &str,
+    session: &mut Session,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("Getting market depth...");
+    let levels = levels.parse::<u64>()?;
+    let fix_msg: FixMessage = (
+        MessageType::MarketDataRequest,
+        vec![
+            MessageField::MarketDepth(levels),
+            MessageField::Subscribe(follow),
+        ],
+    );
+
+    let response_msg = send_fix_message(fix_msg, symbol, session).await?;
     println!("Response: {:?}", response_msg);
+    if follow {
+        loop {
+            let update_msg = session.read().await?;
+            println!("Update: {:?}", update_msg);
+        }
+    }
     Ok(())
 }
 
-pub async fn book(stream: &mut TcpStream) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn book(symbol: &str, session: &mut Session) -> Result<(), Box<dyn std::error::Error>> {
     println!("Getting order book...");
     let fix_msg: FixMessage = (
         MessageType::MarketDataRequest,
         vec![MessageField::MarketBook(true)],
     );
 
-    let response_msg = send_fix_message(fix_msg, stream).await?;
+    let response_msg = send_fix_message(fix_msg, symbol, session).await?;
     println!("Response: {:?}", response_msg);
     Ok(())
 }
@@ -3,7 +3,7 @@
 2: side (buy, sell)
 3: quantity
 4: price
-5: status (new, filled, rejected)
+5: status (new, partially filled, filled, cancelled, replaced, rejected, expired)
 6: volume at limit
 7: trades
 8: book
@@ -11,6 +11,30 @@
 10: market trades
 11: market book
 12: reason
+13: fills
+14: cumulative quantity
+15: leaves quantity
+16: order type (limit, market, stop, stop limit, trailing stop)
+17: time in force (GTC, IOC, FOK, GTD, DAY)
+18: expire time (unix seconds)
+19: stop price
+20: trailing offset (amount, or percentage with a % suffix)
+21: display quantity
+22: post only
+23: hidden
+24: symbol
+25: sequence number (per symbol, from 1)
+26: market depth (levels per side)
+27: depth (volume@price levels, bids then asks)
+28: level updates (action@side@volume@price, action A, C or D)
+29: subscribe
+35: sender comp id
+36: heartbeat interval (seconds)
+37: test request id
+38: message sequence number (per session and direction, from 1)
+39: exec type (trade, cancelled, expired)
+40: last quantity
+41: last price
 */
 
 use time::OffsetDateTime;
@@ -29,7 +53,12 @@ pub fn encode_message(message: &FixMessage) -> String {
         MessageType::OrderStatusRequest => "S",
         MessageType::MarketDataRequest => "M",
         MessageType::MarketData => "D",
+        MessageType::MarketDataIncremental => "I",
         MessageType::Reject => "R",
+        MessageType::Logon => "A",
+        MessageType::Heartbeat => "0",
+        MessageType::TestRequest => "1",
+        MessageType::Logout => "5",
     };
 
     let fields_str = fields
@@ -49,8 +78,12 @@ pub fn encode_message(message: &FixMessage) -> String {
                 "5={}",
                 match status {
                     OrderStatus::New => "N",
+                    OrderStatus::PartiallyFilled => "P",
                     OrderStatus::Filled => "F",
+                    OrderStatus::Cancelled => "C",
+                    OrderStatus::Replaced => "O",
                     OrderStatus::Rejected => "R",
+                    OrderStatus::Expired => "X",
                 }
             ),
             MessageField::VolumeAtLimit(volume_at_limit) => format!("6={}", volume_at_limit),
@@ -82,6 +115,97 @@ pub fn encode_message(message: &FixMessage) -> String {
             MessageField::MarketTrades(market_trades) => format!("10={}", market_trades),
             MessageField::MarketBook(market_book) => format!("11={}", market_book),
             MessageField::Reason(reason) => format!("12={}", reason),
+            MessageField::Fills(fills) => {
+                let fills_str = fills
+                    .iter()
+                    .map(|(quantity, price)| format!("{}@{}", quantity, price))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                format!("13={}", fills_str)
+            }
+            MessageField::CumQuantity(quantity) => format!("14={}", quantity),
+            MessageField::LeavesQuantity(quantity) => format!("15={}", quantity),
+            MessageField::OrderType(order_type) => format!(
+                "16={}",
+                match order_type {
+                    OrderType::Limit => "L",
+                    OrderType::Market => "M",
+                    OrderType::Stop => "S",
+                    OrderType::StopLimit => "T",
+                    OrderType::TrailingStop => "R",
+                }
+            ),
+            MessageField::TimeInForce(time_in_force) => format!(
+                "17={}",
+                match time_in_force {
+                    TimeInForce::Gtc => "GTC",
+                    TimeInForce::Ioc => "IOC",
+                    TimeInForce::Fok => "FOK",
+                    TimeInForce::Gtd => "GTD",
+                    TimeInForce::Day => "DAY",
+                }
+            ),
+            MessageField::ExpireTime(expire_at) => format!("18={}", expire_at),
+            MessageField::StopPrice(stop_price) => format!("19={}", stop_price),
+            MessageField::TrailingOffset(offset) => match offset {
+                TrailingOffset::Amount(amount) => format!("20={}", amount),
+                TrailingOffset::Percent(percent) => format!("20={}%", percent),
+            },
+            MessageField::DisplayQuantity(quantity) => format!("21={}", quantity),
+            MessageField::PostOnly(post_only) => format!("22={}", post_only),
+            MessageField::Hidden(hidden) => format!("23={}", hidden),
+            MessageField::Symbol(symbol) => format!("24={}", symbol),
+            MessageField::SeqNum(seq) => format!("25={}", seq),
+            MessageField::MarketDepth(depth) => format!("26={}", depth),
+            MessageField::Depth(bids, asks) => {
+                let levels_str = |levels: &[PriceLevel]| {
+                    levels
+                        .iter()
+                        .map(|level| format!("{}@{}", level.volume, level.price))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                };
+                format!("27={}:{}", levels_str(bids), levels_str(asks))
+            }
+            MessageField::LevelUpdates(updates) => {
+                let updates_str = updates
+                    .iter()
+                    .map(|update| {
+                        format!(
+                            "{}@{}@{}@{}",
+                            match update.action {
+                                LevelAction::Add => "A",
+                                LevelAction::Change => "C",
+                                LevelAction::Delete => "D",
+                            },
+                            match update.side {
+                                Side::Buy => "B",
+                                Side::Sell => "S",
+                            },
+                            update.volume,
+                            update.price
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",");
+                format!("28={}", updates_str)
+            }
+            MessageField::Subscribe(subscribe) => format!("29={}", subscribe),
+            MessageField::SenderCompId(comp_id) => format!("35={}", comp_id),
+            MessageField::HeartbeatInterval(seconds) => format!("36={}", seconds),
+            MessageField::TestReqId(id) => format!("37={}", id),
+            MessageField::MsgSeqNum(seq) => format!("38={}", seq),
+            MessageField::ExecType(exec_type) => format!(
+                "39={}",
+                match exec_type {
+                    ExecType::Trade => "F",
+                    ExecType::Cancelled => "4",
+                    ExecType::Expired => "C",
+                    ExecType::StopMoved => "D",
+                }
+            ),
+            MessageField::LastQuantity(quantity) => format!("40={}", quantity),
+            MessageField::LastPrice(price) => format!("41={}", price),
         })
         .collect::<Vec<String>>()
         .join(&DELIMITER.to_string());
@@ -103,7 +227,12 @@ pub fn decode_message(data: &str) -> Option<FixMessage> {
         "S" => MessageType::OrderStatusRequest,
         "M" => MessageType::MarketDataRequest,
         "D" => MessageType::MarketData,
+        "I" => MessageType::MarketDataIncremental,
         "R" => MessageType::Reject,
+        "A" => MessageType::Logon,
+        "0" => MessageType::Heartbeat,
+        "1" => MessageType::TestRequest,
+        "5" => MessageType::Logout,
         _ => return None,
     };
 
@@ -127,8 +256,12 @@ pub fn decode_message(data: &str) -> Option<FixMessage> {
             "4" => MessageField::Price(value.parse::<f64>().ok()?),
             "5" => MessageField::Status(match value {
                 "N" => OrderStatus::New,
+                "P" => OrderStatus::PartiallyFilled,
                 "F" => OrderStatus::Filled,
+                "C" => OrderStatus::Cancelled,
+                "O" => OrderStatus::Replaced,
                 "R" => OrderStatus::Rejected,
+                "X" => OrderStatus::Expired,
                 _ => return None,
             }),
             "6" => MessageField::VolumeAtLimit(value.parse::<f64>().ok()?),
@@ -160,6 +293,87 @@ pub fn decode_message(data: &str) -> Option<FixMessage> {
             "10" => MessageField::MarketTrades(value.parse::<bool>().ok()?),
             "11" => MessageField::MarketBook(value.parse::<bool>().ok()?),
             "12" => MessageField::Reason(value.to_owned()),
+            "13" => {
+                let mut fills = Vec::new();
+                for fill_str in value.split(',').filter(|s| !s.is_empty()) {
+                    let (quantity, price) = fill_str.split_once('@')?;
+                    fills.push((quantity.parse::<u64>().ok()?, price.parse::<f64>().ok()?));
+                }
+                MessageField::Fills(fills)
+            }
+            "14" => MessageField::CumQuantity(value.parse::<u64>().ok()?),
+            "15" => MessageField::LeavesQuantity(value.parse::<u64>().ok()?),
+            "16" => MessageField::OrderType(match value {
+                "L" => OrderType::Limit,
+                "M" => OrderType::Market,
+                "S" => OrderType::Stop,
+                "T" => OrderType::StopLimit,
+                "R" => OrderType::TrailingStop,
+                _ => return None,
+            }),
+            "17" => MessageField::TimeInForce(match value {
+                "GTC" => TimeInForce::Gtc,
+                "IOC" => TimeInForce::Ioc,
+                "FOK" => TimeInForce::Fok,
+                "GTD" => TimeInForce::Gtd,
+                "DAY" => TimeInForce::Day,
+                _ => return None,
+            }),
+            "18" => MessageField::ExpireTime(value.parse::<i64>().ok()?),
+            "19" => MessageField::StopPrice(value.parse::<f64>().ok()?),
+            "20" => MessageField::TrailingOffset(match value.strip_suffix('%') {
+                Some(percent) => TrailingOffset::Percent(percent.parse::<f64>().ok()?),
+                None => TrailingOffset::Amount(value.parse::<f64>().ok()?),
+            }),
+            "21" => MessageField::DisplayQuantity(value.parse::<u64>().ok()?),
+            "22" => MessageField::PostOnly(value.parse::<bool>().ok()?),
+            "23" => MessageField::Hidden(value.parse::<bool>().ok()?),
+            "24" => MessageField::Symbol(value.to_owned()),
+            "25" => MessageField::SeqNum(value.parse::<u64>().ok()?),
+            "26" => MessageField::MarketDepth(value.parse::<u64>().ok()?),
+            "27" => {
+                let (bids, asks) = value.split_once(':')?;
+                MessageField::Depth(parse_levels(bids)?, parse_levels(asks)?)
+            }
+            "28" => {
+                let mut updates = Vec::new();
+                for update_str in value.split(',').filter(|s| !s.is_empty()) {
+                    let update_parts: Vec<&str> = update_str.split('@').collect();
+                    if update_parts.len() != 4 {
+                        return None;
+                    }
+                    updates.push(LevelUpdate {
+                        action: match update_parts[0] {
+                            "A" => LevelAction::Add,
+                            "C" => LevelAction::Change,
+                            "D" => LevelAction::Delete,
+                            _ => return None,
+                        },
+                        side: match update_parts[1] {
+                            "B" => Side::Buy,
+                            "S" => Side::Sell,
+                            _ => return None,
+                        },
+                        volume: update_parts[2].parse::<u64>().ok()?,
+                        price: update_parts[3].parse::<f64>().ok()?,
+                    });
+                }
+                MessageField::LevelUpdates(updates)
+            }
+            "29" => MessageField::Subscribe(value.parse::<bool>().ok()?),
+            "35" => MessageField::SenderCompId(value.to_owned()),
+            "36" => MessageField::HeartbeatInterval(value.parse::<u64>().ok()?),
+            "37" => MessageField::TestReqId(value.to_owned()),
+            "38" => MessageField::MsgSeqNum(value.parse::<u64>().ok()?),
+            "39" => MessageField::ExecType(match value {
+                "F" => ExecType::Trade,
+                "4" => ExecType::Cancelled,
+                "C" => ExecType::Expired,
+                "D" => ExecType::StopMoved,
+                _ => return None,
+            }),
+            "40" => MessageField::LastQuantity(value.parse::<u64>().ok()?),
+            "41" => MessageField::LastPrice(value.parse::<f64>().ok()?),
             _ => return None,
         };
         fields.push(field);
@@ -168,6 +382,19 @@ pub fn decode_message(data: &str) -> Option<FixMessage> {
     Some((message_type, fields))
 }
 
+fn parse_levels(levels_str: &str) -> Option<Vec<PriceLevel>> {
+    let mut levels = Vec::new();
+    for level_str in levels_str.split(',').filter(|s| !s.is_empty()) {
+        let (volume, price) = level_str.split_once('@')?;
+        levels.push(PriceLevel {
+            price: price.parse::<f64>().ok()?,
+            volume: volume.parse::<u64>().ok()?,
+        });
+    }
+    Some(levels)
+}
+
+#[allow(clippy::explicit_counter_loop)]
 fn parse_orders(orders_str: &str, is_buy: bool) -> Option<[OrderData; 10]> {
     let mut orders = [OrderData {
         id: 0,
@@ -12,7 +12,12 @@ pub enum MessageType {
     OrderStatusRequest,
     MarketDataRequest,
     MarketData,
+    MarketDataIncremental,
     Reject,
+    Logon,
+    Heartbeat,
+    TestRequest,
+    Logout,
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -21,6 +26,7 @@ pub enum Side {
     Sell,
 }
 
+#[allow(clippy::to_string_trait_impl)]
 impl ToString for Side {
     fn to_string(&self) -> String {
         match self {
@@ -30,23 +36,63 @@ impl ToString for Side {
     }
 }
 
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ExecType {
+    Trade,
+    Cancelled,
+    Expired,
+    StopMoved,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum OrderStatus {
     New,
+    PartiallyFilled,
     Filled,
+    Cancelled,
+    Replaced,
     Rejected,
+    Expired,
 }
 
 impl fmt::Display for OrderStatus {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             OrderStatus::New => write!(f, "New"),
+            OrderStatus::PartiallyFilled => write!(f, "PartiallyFilled"),
             OrderStatus::Filled => write!(f, "Filled"),
+            OrderStatus::Cancelled => write!(f, "Cancelled"),
+            OrderStatus::Replaced => write!(f, "Replaced"),
             OrderStatus::Rejected => write!(f, "Rejected"),
+            OrderStatus::Expired => write!(f, "Expired"),
         }
     }
 }
 
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum OrderType {
+    Limit,
+    Market,
+    Stop,
+    StopLimit,
+    TrailingStop,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum TrailingOffset {
+    Amount(f64),
+    Percent(f64),
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum TimeInForce {
+    Gtc,
+    Ioc,
+    Fok,
+    Gtd,
+    Day,
+}
+
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub struct OrderData {
     pub id: u64,
@@ -68,6 +114,28 @@ impl fmt::Display for OrderData {
     }
 }
 
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct PriceLevel {
+    pub price: f64,
+    pub volume: u64,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum LevelAction {
+    Add,
+    Change,
+    Delete,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct LevelUpdate {
+    pub action: LevelAction,
+    pub side: Side,
+    pub price: f64,
+    pub volume: u64,
+}
+
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, PartialEq)]
 pub enum MessageField {
     OrderId(u64),
@@ -82,6 +150,30 @@ pub enum MessageField {
     MarketTrades(bool),
     MarketBook(bool),
     Reason(String),
+    Fills(Vec<(u64, f64)>),
+    CumQuantity(u64),
+    LeavesQuantity(u64),
+    OrderType(OrderType),
+    TimeInForce(TimeInForce),
+    ExpireTime(i64),
+    StopPrice(f64),
+    TrailingOffset(TrailingOffset),
+    DisplayQuantity(u64),
+    PostOnly(bool),
+    Hidden(bool),
+    Symbol(String),
+    SeqNum(u64),
+    MarketDepth(u64),
+    Depth(Vec<PriceLevel>, Vec<PriceLevel>),
+    LevelUpdates(Vec<LevelUpdate>),
+    Subscribe(bool),
+    SenderCompId(String),
+    HeartbeatInterval(u64),
+    TestReqId(String),
+    MsgSeqNum(u64),
+    ExecType(ExecType),
+    LastQuantity(u64),
+    LastPrice(f64),
 }
 
 pub type FixMessage = (MessageType, Vec<MessageField>);
@@ -3,8 +3,8 @@ mod codec;
 mod fix;
 
 use crate::app::{
-    amend_order, book, cancel_order, market_price, send_order, status_report, trades,
-    volume_at_limit,
+    amend_order, book, cancel_order, depth, market_price, send_order, status_report, trades,
+    volume_at_limit, OrderOptions, Session,
 };
 
 use clap::{App, Arg};
@@ -32,6 +32,67 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .help("Send an ask order to the trading server")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("market")
+                .short("m")
+                .long("market")
+                .help("Send the buy or sell order as a market order (ORDER is just a quantity)")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("tif")
+                .long("tif")
+                .value_name("TIF")
+                .help("Time in force for the buy or sell order: GTC, IOC, FOK, DAY or GTD:<unix time>")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("stop")
+                .long("stop")
+                .value_name("PRICE")
+                .help("Hold the buy or sell order until the market trades at PRICE (a stop-limit order unless --market)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("trail")
+                .long("trail")
+                .value_name("OFFSET")
+                .help("Send the buy or sell order as a trailing stop OFFSET behind the best price (e.g. 0.5 or 1%; ORDER is just a quantity)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("display")
+                .long("display")
+                .value_name("QUANTITY")
+                .help("Only show QUANTITY of the buy or sell order on the book at a time (iceberg)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("post-only")
+                .long("post-only")
+                .help("Only let the buy or sell order rest on the book, never take liquidity")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("hidden")
+                .long("hidden")
+                .help("Keep the buy or sell order out of market data while it rests")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("symbol")
+                .long("symbol")
+                .value_name("SYMBOL")
+                .help("Instrument to trade or query (defaults to XYZ)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("comp-id")
+                .long("comp-id")
+                .value_name("ID")
+                .help("Identity to log on to the server with (defaults to CLI)")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("amend")
                 .short("a")
@@ -62,6 +123,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .help("Displays the order book")
                 .takes_value(false),
         )
+        .arg(
+            Arg::with_name("depth")
+                .short("d")
+                .long("depth")
+                .value_name("LEVELS")
+                .help("Displays the order book aggregated into LEVELS price levels per side")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("follow")
+                .short("f")
+                .long("follow")
+                .help("Keeps printing changes to the --depth levels, or executions of the --buy or --sell order, as they happen")
+                .takes_value(false),
+        )
         .arg(
             Arg::with_name("volume")
                 .short("v")
@@ -95,46 +171,65 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         )
         .get_matches();
 
-    let mut stream = TcpStream::connect("127.0.0.1:6379").await?;
+    let stream = TcpStream::connect("127.0.0.1:6379").await?;
     println!("Connected to server at {}", stream.peer_addr()?);
+    let mut session = Session::logon(stream, matches.value_of("comp-id").unwrap_or("CLI")).await?;
     let mut result = Ok(());
+    let symbol = matches.value_of("symbol").unwrap_or("XYZ");
+
+    let options = OrderOptions {
+        is_market: matches.is_present("market"),
+        time_in_force: matches.value_of("tif"),
+        stop_price: matches.value_of("stop"),
+        trailing_offset: matches.value_of("trail"),
+        display_quantity: matches.value_of("display"),
+        is_post_only: matches.is_present("post-only"),
+        is_hidden: matches.is_present("hidden"),
+        follow: matches.is_present("follow"),
+    };
 
     if let Some(order) = matches.value_of("buy") {
-        result = send_order(order, &mut stream, true).await;
+        result = send_order(order, symbol, &mut session, true, &options).await;
     }
 
     if let Some(order) = matches.value_of("sell") {
-        result = send_order(order, &mut stream, false).await;
+        result = send_order(order, symbol, &mut session, false, &options).await;
     }
 
     if let Some(amend) = matches.value_of("amend") {
-        result = amend_order(amend, &mut stream).await;
+        result = amend_order(amend, symbol, &mut session).await;
     }
 
     if let Some(id) = matches.value_of("cancel") {
-        result = cancel_order(id, &mut stream).await;
+        result = cancel_order(id, symbol, &mut session).await;
     }
 
     if let Some(id) = matches.value_of("report") {
-        result = status_report(id, &mut stream).await;
+        result = status_report(id, symbol, &mut session).await;
     }
 
     if let Some(volume) = matches.value_of("volume") {
-        result = volume_at_limit(volume, &mut stream).await;
+        result = volume_at_limit(volume, symbol, &mut session).await;
     }
 
     if matches.is_present("price") {
-        result = market_price(&mut stream).await;
+        result = market_price(symbol, &mut session).await;
     }
 
     if matches.is_present("book") {
-        result = book(&mut stream).await;
+        result = book(symbol, &mut session).await;
+    }
+
+    if let Some(levels) = matches.value_of("depth") {
+        result = depth(levels, matches.is_present("follow"), symbol, &mut session).await;
     }
 
     if matches.is_present("trades") {
-        result = trades(&mut stream).await;
+        result = trades(symbol, &mut session).await;
     }
 
+    session.logout().await?;
+
     if matches.is_present("verbose") {
         if result.is_ok() {
             println!("OK");
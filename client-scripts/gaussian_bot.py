@@ -12,15 +12,17 @@ while True:
         i = random.randint(1, 10)
         if i % 2 == 0:
             data = {
+                "symbol": "XYZ",
                 "side": "Sell",
                 "quantity": random.randint(5, 15),
-                "price": abs(random.normalvariate(MEAN, 10)),
+                "price": round(abs(random.normalvariate(MEAN, 10)), 2),
             }
         else:
             data = {
+                "symbol": "XYZ",
                 "side": "Buy",
                 "quantity": random.randint(5, 15),
-                "price": abs(random.normalvariate(MEAN, 10)),
+                "price": round(abs(random.normalvariate(MEAN, 10)), 2),
             }
         response = requests.post(url, json=data)
         print(response.text)
@@ -19,12 +19,14 @@ async def make_request(session, i):
     url = "http://localhost:3000/order"
     if i % 2 == 0:
         data = {
+            "symbol": "XYZ",
             "side": "Sell",
             "quantity": 10,
             "price": random.randint(MEAN - 10, MEAN + 20),
         }
     else:
         data = {
+            "symbol": "XYZ",
             "side": "Buy",
             "quantity": 10,
             "price": random.randint(MEAN - 20, MEAN + 10),
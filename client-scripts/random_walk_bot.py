@@ -16,12 +16,14 @@ while True:
         i = random.randint(1, 10)
         if i % 2 == 0:
             data = {
+                "symbol": "XYZ",
                 "side": "Sell",
                 "quantity": random.randint(10, 50),
                 "price": MEAN + DRIFT,
             }
         else:
             data = {
+                "symbol": "XYZ",
                 "side": "Buy",
                 "quantity": random.randint(10, 50),
                 "price": MEAN + DRIFT,
@@ -5,12 +5,14 @@ import Order from "./components/Order"
 import Trades from "./components/Trades"
 import Depth from "./components/Depth"
 import Positions from "./components/Positions"
+import { SYMBOL } from "./config"
 
 interface PriceDatum {
     open: number
     close: number
     high: number
     low: number
+    volume: number
     timestamp: number
 }
 
@@ -36,13 +38,7 @@ const useFetch = (
 
 function App() {
     const [book, setBook] = useState({ bids: [], asks: [] })
-    const [price, setPrice] = useState<PriceDatum>({
-        open: 0,
-        close: 0,
-        high: 0,
-        low: 0,
-        timestamp: 0,
-    })
+    const [candles, setCandles] = useState<PriceDatum[]>([])
     const [trades, setTrades] = useState([])
     const [positions, setPositions] = useState(() => {
         const storedPositions = localStorage.getItem("positions")
@@ -50,15 +46,19 @@ function App() {
     })
 
     // Fetch data
-    useFetch("http://localhost:3000/book", 5000, setBook)
-    useFetch("http://localhost:3000/price", 5000, setPrice)
-    useFetch("http://localhost:3000/trades", 5000, setTrades)
+    useFetch(`http://localhost:3000/book/${SYMBOL}`, 5000, setBook)
+    useFetch(
+        `http://localhost:3000/candles/${SYMBOL}?interval=1m`,
+        5000,
+        setCandles
+    )
+    useFetch(`http://localhost:3000/trades/${SYMBOL}`, 5000, setTrades)
 
     // Update positions
     useEffect(() => {
         const intervalId = setInterval(() => {
             positions.forEach((position: { id: any }) => {
-                fetch(`http://localhost:3000/report/${position.id}`)
+                fetch(`http://localhost:3000/report/${SYMBOL}/${position.id}`)
                     .then((res) => res.json())
                     .then((data) => {
                         if (data.hasOwnProperty("status")) {
@@ -89,7 +89,7 @@ function App() {
     return (
         <div className="flex space-x-8 p-6">
             <div>
-                <Price price={price} />
+                <Price candles={candles} />
                 <Depth book={book} />
             </div>
             <div>
@@ -3,7 +3,7 @@ import React from "react"
 interface Order {
     id: number
     side: string
-    quantity: number
+    leaves_quantity: number
     price: number
 }
 
@@ -21,7 +21,7 @@ const OrderTable: React.FC<{ orders: Order[]; isBid?: boolean }> = ({
     isBid,
 }) => {
     const validOrders = orders.filter(
-        (order) => order.price !== 0 && order.quantity !== 0
+        (order) => order.price !== 0 && order.leaves_quantity !== 0
     )
 
     return (
@@ -36,10 +36,14 @@ const OrderTable: React.FC<{ orders: Order[]; isBid?: boolean }> = ({
                 {validOrders.map((order) => (
                     <tr key={order.id} className="border-y">
                         <td className={isBid ? "" : "text-red-400"}>
-                            {isBid ? order.quantity : order.price.toFixed(2)}
+                            {isBid
+                                ? order.leaves_quantity
+                                : order.price.toFixed(2)}
                         </td>
                         <td className={isBid ? "text-green-400" : ""}>
-                            {isBid ? order.price.toFixed(2) : order.quantity}
+                            {isBid
+                                ? order.price.toFixed(2)
+                                : order.leaves_quantity}
                         </td>
                     </tr>
                 ))}
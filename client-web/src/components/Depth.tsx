@@ -6,7 +6,7 @@ import isEqual from "lodash/isEqual"
 interface Order {
     id: number
     side: string
-    quantity: number
+    leaves_quantity: number
     price: number
 }
 
@@ -31,7 +31,7 @@ const Depth: FC<Props> = ({ book }) => {
             if (!acc[order.price]) {
                 acc[order.price] = 0
             }
-            acc[order.price] += order.quantity
+            acc[order.price] += order.leaves_quantity
             return acc
         }, {} as Record<number, number>)
 
@@ -54,7 +54,7 @@ const Depth: FC<Props> = ({ book }) => {
             if (!acc[order.price]) {
                 acc[order.price] = 0
             }
-            acc[order.price] += order.quantity
+            acc[order.price] += order.leaves_quantity
             return acc
         }, {} as Record<number, number>)
 
@@ -1,4 +1,5 @@
 import React, { useState } from "react"
+import { SYMBOL } from "../config"
 
 interface Position {
     id: number
@@ -45,7 +46,7 @@ const Order: React.FC<Props> = ({ positions, updatePositions }) => {
         const response = await fetch("http://localhost:3000/order", {
             method: "POST",
             headers: { "Content-Type": "application/json" },
-            body: JSON.stringify(order),
+            body: JSON.stringify({ symbol: SYMBOL, ...order }),
         })
         return await response.json()
     }
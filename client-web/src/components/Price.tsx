@@ -1,4 +1,4 @@
-import React, { useEffect, useState, useRef } from "react"
+import React, { useEffect, useRef } from "react"
 import { init, dispose, Chart } from "klinecharts"
 
 interface PriceDatum {
@@ -6,23 +6,17 @@ interface PriceDatum {
     close: number
     high: number
     low: number
+    volume: number
     timestamp: number
 }
 
 interface Props {
-    price: PriceDatum
+    candles: PriceDatum[]
 }
 
-const Price: React.FC<Props> = ({ price }) => {
-    const [priceData, setPriceData] = useState<PriceDatum[]>([])
+const Price: React.FC<Props> = ({ candles }) => {
     const chartRef = useRef<Chart | null>(null)
 
-    useEffect(() => {
-        if (price && price.timestamp !== 0) {
-            setPriceData((prevPriceData) => [...prevPriceData, price])
-        }
-    }, [price])
-
     useEffect(() => {
         chartRef.current = init("simple_chart")
         chartRef.current?.createIndicator(
@@ -36,10 +30,10 @@ const Price: React.FC<Props> = ({ price }) => {
             }
         )
 
-        chartRef.current?.applyNewData(priceData)
+        chartRef.current?.applyNewData(Array.isArray(candles) ? candles : [])
 
         return () => dispose("simple_chart")
-    }, [priceData])
+    }, [candles])
 
     return (
         <div className="p-4 text-center w-[800px] h-[450px] border">
@@ -0,0 +1,2 @@
+// Instrument the web client trades and displays
+export const SYMBOL = "XYZ"
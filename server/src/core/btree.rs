@@ -1,129 +1,406 @@
-use std::collections::BTreeMap;
+use super::order_book::{Order, OrderBook, PriceLevel, Side};
+use super::price::Price;
+use std::cmp::min;
+use std::collections::{BTreeMap, HashMap};
+
+// A single price level: a doubly linked FIFO queue of order ids
+struct Limit {
+    volume: u64,
+    // The part of `volume` belonging to hidden orders
+    hidden_volume: u64,
+    head: Option<u64>,
+    tail: Option<u64>,
+}
+
+struct Entry {
+    order: Order,
+    prev: Option<u64>,
+    next: Option<u64>,
+}
 
 pub struct BTreeOrderBook {
-    bids: BTreeMap<f64, Vec<Order>>,
-    asks: BTreeMap<f64, Vec<Order>>,
+    bids: BTreeMap<Price, Limit>,
+    asks: BTreeMap<Price, Limit>,
+    orders: HashMap<u64, Entry>,
+    best_bid: Option<Price>,
+    best_ask: Option<Price>,
 }
 
-impl OrderBook {
-    pub fn new() -> BTreeOrderBook {
-        BTreeOrderBook {
-            bids: BTreeMap::new(),
-            asks: BTreeMap::new(),
+impl BTreeOrderBook {
+    fn push_back(&mut self, order: Order) {
+        let key = order.price;
+        let levels = match order.side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        let limit = levels.entry(key).or_insert(Limit {
+            volume: 0,
+            hidden_volume: 0,
+            head: None,
+            tail: None,
+        });
+        let prev = limit.tail;
+        limit.volume += order.leaves_quantity;
+        if order.hidden {
+            limit.hidden_volume += order.leaves_quantity;
+        }
+        limit.tail = Some(order.id);
+        if limit.head.is_none() {
+            limit.head = Some(order.id);
+        }
+        if let Some(prev_id) = prev {
+            if let Some(prev_entry) = self.orders.get_mut(&prev_id) {
+                prev_entry.next = Some(order.id);
+            }
+        }
+        self.orders.insert(
+            order.id,
+            Entry {
+                order,
+                prev,
+                next: None,
+            },
+        );
+
+        match order.side {
+            Side::Buy => {
+                if self.best_bid.is_none_or(|best| key > best) {
+                    self.best_bid = Some(key);
+                }
+            }
+            Side::Sell => {
+                if self.best_ask.is_none_or(|best| key < best) {
+                    self.best_ask = Some(key);
+                }
+            }
         }
     }
 
-    fn add_bid_order(&mut self, order: Order) {
-        // insert the new order into the bids map, sorted by price
-        let price = order.price;
-        self.bids.entry(price).or_insert(Vec::new()).push(order);
+    fn unlink(&mut self, id: u64) -> Option<Order> {
+        let entry = self.orders.remove(&id)?;
+        let key = entry.order.price;
+
+        if let Some(prev_id) = entry.prev {
+            if let Some(prev_entry) = self.orders.get_mut(&prev_id) {
+                prev_entry.next = entry.next;
+            }
+        }
+        if let Some(next_id) = entry.next {
+            if let Some(next_entry) = self.orders.get_mut(&next_id) {
+                next_entry.prev = entry.prev;
+            }
+        }
+
+        let levels = match entry.order.side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        if let Some(limit) = levels.get_mut(&key) {
+            limit.volume -= entry.order.leaves_quantity;
+            if entry.order.hidden {
+                limit.hidden_volume -= entry.order.leaves_quantity;
+            }
+            if limit.head == Some(id) {
+                limit.head = entry.next;
+            }
+            if limit.tail == Some(id) {
+                limit.tail = entry.prev;
+            }
+            if limit.head.is_none() {
+                levels.remove(&key);
+                match entry.order.side {
+                    Side::Buy => self.best_bid = self.bids.keys().next_back().copied(),
+                    Side::Sell => self.best_ask = self.asks.keys().next().copied(),
+                }
+            }
+        }
+
+        Some(entry.order)
     }
 
-    fn add_ask_order(&mut self, order: Order) {
-        // insert the new order into the asks map, sorted by price
-        let price = order.price;
-        self.asks.entry(price).or_insert(Vec::new()).push(order);
-    }
-
-    fn remove_bid_order(&mut self, order: &Order) {
-        // remove the specified order from the bids map
-        let price = order.price;
-        if let Some(orders) = self.bids.get_mut(&price) {
-            let index = orders.iter().position(|x| x == order).unwrap();
-            orders.remove(index);
-        }
-    }
-
-    fn remove_ask_order(&mut self, order: &Order) {
-        // remove the specified order from the asks map
-        let price = order.price;
-        if let Some(orders) = self.asks.get_mut(&price) {
-            let index = orders.iter().position(|x| x == order).unwrap();
-            orders.remove(index);
-        }
-    }
-
-    fn amend_bid_order(&mut self, order: &Order, price: f64, size: u64) {
-        // amend the specified order in the bids map
-        self.remove_bid_order(order);
-        let mut order = order.clone();
-        order.price = price;
-        order.size = size;
-        self.add_bid_order(order);
-    }
-
-    fn amend_ask_order(&mut self, order: &Order, price: f64, size: u64) {
-        // amend the specified order in the asks map
-        self.remove_ask_order(order);
-        let mut order = order.clone();
-        order.price = price;
-        order.size = size;
-        self.add_ask_order(order);
-    }
-
-    fn match_orders(&mut self) -> Result<(), &'static str> {
-        // get the best bid and ask prices
-        let best_bid = self.bids.keys().last().unwrap();
-        let best_ask = self.asks.keys().next().unwrap();
-
-        // if the best bid is greater than or equal to the best ask, execute a match
-        if best_bid >= best_ask {
-            // get the best bid and ask orders
-            let best_bid_orders = self.bids.get_mut(best_bid).unwrap();
-            let best_ask_orders = self.asks.get_mut(best_ask).unwrap();
-
-            // get the first bid and ask orders
-            let best_bid_order = best_bid_orders.first().unwrap();
-            let best_ask_order = best_ask_orders.first().unwrap();
-
-            // if the bid order size is greater than or equal to the ask order size, execute a match
-            if best_bid_order.size >= best_ask_order.size {
-                // remove the ask order from the asks map
-                self.remove_ask_order(best_ask_order);
-
-                // if the bid order size is greater than the ask order size, amend the bid order in the bids map
-                if best_bid_order.size > best_ask_order.size {
-                    self.amend_bid_order(
-                        best_bid_order,
-                        best_bid_order.price,
-                        best_bid_order.size - best_ask_order.size,
-                    );
+    fn remove_order(&mut self, id: u64, side: Side) -> Result<&'static str, &'static str> {
+        match self.orders.get(&id) {
+            Some(entry) if entry.order.side == side => {
+                self.unlink(id);
+                Ok("Success: order removed")
+            }
+            _ => Err("Failure: order not found"),
+        }
+    }
+
+    fn amend_order(
+        &mut self,
+        id: u64,
+        quantity: u64,
+        price: Price,
+        side: Side,
+    ) -> Result<&'static str, &'static str> {
+        let entry = match self.orders.get_mut(&id) {
+            Some(entry) if entry.order.side == side => entry,
+            _ => return Err("Failure: order not found"),
+        };
+
+        let mut amended = entry.order;
+        amended.amend(quantity, price)?;
+
+        // Reducing quantity at the same price keeps time priority
+        if price == entry.order.price && amended.leaves_quantity <= entry.order.leaves_quantity {
+            let reduction = entry.order.leaves_quantity - amended.leaves_quantity;
+            entry.order = amended;
+            let levels = match side {
+                Side::Buy => &mut self.bids,
+                Side::Sell => &mut self.asks,
+            };
+            if let Some(limit) = levels.get_mut(&price) {
+                limit.volume -= reduction;
+                if amended.hidden {
+                    limit.hidden_volume -= reduction;
                 }
-                // if the bid order size is equal to the ask order size, remove the bid order from the bids map
-                else {
-                    self.remove_bid_order(best_bid_order);
+            }
+        } else {
+            self.unlink(id);
+            self.push_back(amended);
+        }
+        Ok("Success: order amended")
+    }
+
+    fn fill_order(&mut self, id: u64, quantity: u64) {
+        if let Some(entry) = self.orders.get_mut(&id) {
+            if entry.order.leaves_quantity <= quantity {
+                self.unlink(id);
+                return;
+            }
+            entry.order.fill(quantity);
+            let levels = match entry.order.side {
+                Side::Buy => &mut self.bids,
+                Side::Sell => &mut self.asks,
+            };
+            if let Some(limit) = levels.get_mut(&entry.order.price) {
+                limit.volume -= quantity;
+                if entry.order.hidden {
+                    limit.hidden_volume -= quantity;
                 }
             }
-            // if the bid order size is less than the ask order size, execute a match
-            else {
-                // remove the bid order from the bids map
-                self.remove_bid_order(best_bid_order);
-
-                // amend the ask order in the asks map
-                self.amend_ask_order(
-                    best_ask_order,
-                    best_ask_order.price,
-                    best_ask_order.size - best_bid_order.size,
-                );
+        }
+    }
+
+    fn best_order(&self, side: Side) -> Option<&Order> {
+        let (levels, best) = match side {
+            Side::Buy => (&self.bids, self.best_bid),
+            Side::Sell => (&self.asks, self.best_ask),
+        };
+        let head = levels.get(&best?)?.head?;
+        self.orders.get(&head).map(|entry| &entry.order)
+    }
+
+    fn collect_orders<'a>(&self, levels: impl Iterator<Item = &'a Limit>) -> [Order; 10] {
+        let mut array: [Order; 10] = Default::default();
+        let mut orders = levels
+            .flat_map(|limit| LevelIter {
+                book: self,
+                next: limit.head,
+            })
+            .filter(|order| !order.hidden);
+        for slot in array.iter_mut() {
+            match orders.next() {
+                Some(order) => *slot = order,
+                None => break,
             }
         }
+        array
+    }
+}
+
+struct LevelIter<'a> {
+    book: &'a BTreeOrderBook,
+    next: Option<u64>,
+}
+
+impl<'a> Iterator for LevelIter<'a> {
+    type Item = Order;
+
+    fn next(&mut self) -> Option<Order> {
+        let entry = self.book.orders.get(&self.next?)?;
+        self.next = entry.next;
+        Some(entry.order)
+    }
+}
+
+impl OrderBook for BTreeOrderBook {
+    fn new() -> BTreeOrderBook {
+        BTreeOrderBook {
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            orders: HashMap::new(),
+            best_bid: None,
+            best_ask: None,
+        }
+    }
+
+    fn add_bid_order(&mut self, order: Order) {
+        self.push_back(order);
+    }
+
+    fn add_ask_order(&mut self, order: Order) {
+        self.push_back(order);
+    }
+
+    fn remove_bid_order(&mut self, id: u64) -> Result<&'static str, &'static str> {
+        self.remove_order(id, Side::Buy)
+    }
 
-        Ok(())
+    fn remove_ask_order(&mut self, id: u64) -> Result<&'static str, &'static str> {
+        self.remove_order(id, Side::Sell)
+    }
+
+    fn amend_bid_order(
+        &mut self,
+        id: u64,
+        quantity: u64,
+        price: Price,
+    ) -> Result<&'static str, &'static str> {
+        self.amend_order(id, quantity, price, Side::Buy)
+    }
+
+    fn amend_ask_order(
+        &mut self,
+        id: u64,
+        quantity: u64,
+        price: Price,
+    ) -> Result<&'static str, &'static str> {
+        self.amend_order(id, quantity, price, Side::Sell)
+    }
+
+    fn match_orders(&mut self, aggressor: Side) -> Result<(u64, u64, u64, Price), &'static str> {
+        let (best_bid, best_ask) = match (self.best_order(Side::Buy), self.best_order(Side::Sell)) {
+            (Some(bid), Some(ask)) => (*bid, *ask),
+            _ => return Err("No matching orders"),
+        };
+
+        if best_bid.price >= best_ask.price {
+            let match_quantity = min(best_bid.leaves_quantity, best_ask.leaves_quantity);
+            let match_price = match aggressor {
+                Side::Buy => best_ask.price,
+                Side::Sell => best_bid.price,
+            };
+            self.fill_order(best_bid.id, match_quantity);
+            self.fill_order(best_ask.id, match_quantity);
+            Ok((best_bid.id, best_ask.id, match_quantity, match_price))
+        } else {
+            Err("No matching orders")
+        }
     }
 
     fn display(&self) {
-        // display the bids and asks
         println!("Bids:");
-        for (price, orders) in self.bids.iter().rev() {
-            for order in orders {
-                println!("{} {}", price, order.size);
-            }
+        for order in self.bids.values().rev().flat_map(|limit| LevelIter {
+            book: self,
+            next: limit.head,
+        }) {
+            println!(
+                "  {} @ {} ({})",
+                order.leaves_quantity, order.price, order.id
+            );
         }
+
         println!("Asks:");
-        for (price, orders) in self.asks.iter() {
-            for order in orders {
-                println!("{} {}", price, order.size);
-            }
+        for order in self.asks.values().flat_map(|limit| LevelIter {
+            book: self,
+            next: limit.head,
+        }) {
+            println!(
+                "  {} @ {} ({})",
+                order.leaves_quantity, order.price, order.id
+            );
+        }
+    }
+
+    fn get_bids(&self) -> [Order; 10] {
+        self.collect_orders(self.bids.values().rev())
+    }
+
+    fn get_asks(&self) -> [Order; 10] {
+        self.collect_orders(self.asks.values())
+    }
+
+    fn get_orders(&self, side: Side) -> Vec<Order> {
+        let levels: Box<dyn Iterator<Item = &Limit>> = match side {
+            Side::Buy => Box::new(self.bids.values().rev()),
+            Side::Sell => Box::new(self.asks.values()),
+        };
+        levels
+            .flat_map(|limit| LevelIter {
+                book: self,
+                next: limit.head,
+            })
+            .collect()
+    }
+
+    fn get_levels(&self, side: Side, depth: usize) -> Vec<PriceLevel> {
+        let levels: Box<dyn Iterator<Item = (&Price, &Limit)>> = match side {
+            Side::Buy => Box::new(self.bids.iter().rev()),
+            Side::Sell => Box::new(self.asks.iter()),
+        };
+        levels
+            .map(|(&price, limit)| PriceLevel {
+                price,
+                volume: limit.volume - limit.hidden_volume,
+            })
+            .filter(|level| level.volume > 0)
+            .take(depth)
+            .collect()
+    }
+
+    fn get_volume_at_limit(&self, price: Price) -> u64 {
+        let visible_volume = |limit: &Limit| limit.volume - limit.hidden_volume;
+        let bid_volume = self.bids.get(&price).map_or(0, visible_volume);
+        let ask_volume = self.asks.get(&price).map_or(0, visible_volume);
+        bid_volume + ask_volume
+    }
+
+    fn get_level_volume(&self, side: Side, price: Price) -> u64 {
+        let limits = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        limits
+            .get(&price)
+            .map_or(0, |limit| limit.volume - limit.hidden_volume)
+    }
+
+    fn get_crossing_volume(&self, aggressor: Side, price: Price) -> u64 {
+        match aggressor {
+            Side::Buy => self
+                .asks
+                .range(..=price)
+                .map(|(_, limit)| limit.volume)
+                .sum(),
+            Side::Sell => self
+                .bids
+                .range(price..)
+                .map(|(_, limit)| limit.volume)
+                .sum(),
+        }
+    }
+
+    fn get_best_price(&self, side: Side) -> Option<Price> {
+        match side {
+            Side::Buy => self.best_bid,
+            Side::Sell => self.best_ask,
+        }
+    }
+
+    fn get_order_by_id(&self, id: u64) -> Result<Order, &'static str> {
+        match self.orders.get(&id) {
+            Some(entry) => Ok(entry.order),
+            None => Err("Order not found"),
+        }
+    }
+
+    fn get_market_price(&self) -> Result<Price, &'static str> {
+        match (self.best_bid, self.best_ask) {
+            (Some(best_bid), Some(best_ask)) => Ok(best_bid.midpoint(best_ask)),
+            _ => Err("No price available"),
         }
     }
 }
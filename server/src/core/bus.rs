@@ -0,0 +1,57 @@
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+use super::engine::Engine;
+use super::execution::{executions, Execution};
+use super::order_book::{OrderBook, PriceLevel, Side};
+use super::sequencer::Output;
+
+// Updates a subscriber has not yet received beyond this are dropped, and it is told how many
+const BUS_CAPACITY: usize = 4096;
+
+// A command's output together with the top of book it left behind and the executions
+// it reported
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Update {
+    pub output: Output,
+    pub best_bid: Option<PriceLevel>,
+    pub best_ask: Option<PriceLevel>,
+    pub executions: Vec<Execution>,
+}
+
+// Fans the output of every matching thread out to any number of subscribers, so that
+// market data and execution streams are pushed as commands are applied
+pub struct EventBus {
+    sender: broadcast::Sender<Arc<Update>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> EventBus {
+        EventBus {
+            sender: broadcast::channel(BUS_CAPACITY).0,
+        }
+    }
+
+    // Called on the matching thread, straight after the engine applies a command
+    pub fn publish<T: OrderBook>(&self, engine: &Engine<T>, output: &Output) {
+        let update = Update {
+            output: output.clone(),
+            best_bid: engine.get_top_of_book(Side::Buy),
+            best_ask: engine.get_top_of_book(Side::Sell),
+            executions: executions(engine, output),
+        };
+        // Nobody listening is not an error
+        let _ = self.sender.send(Arc::new(update));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Update>> {
+        self.sender.subscribe()
+    }
+}
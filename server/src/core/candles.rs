@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::str::FromStr;
+use time::OffsetDateTime;
+
+use super::price::Price;
+
+// Bars kept per interval, a day of one minute bars and less of the rest
+const HISTORY: usize = 1440;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Interval {
+    #[serde(rename = "1s")]
+    OneSecond,
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+}
+
+impl Interval {
+    pub const ALL: [Interval; 4] = [
+        Interval::OneSecond,
+        Interval::OneMinute,
+        Interval::FiveMinutes,
+        Interval::OneHour,
+    ];
+
+    pub fn millis(self) -> i64 {
+        match self {
+            Interval::OneSecond => 1_000,
+            Interval::OneMinute => 60_000,
+            Interval::FiveMinutes => 300_000,
+            Interval::OneHour => 3_600_000,
+        }
+    }
+}
+
+impl FromStr for Interval {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Interval, &'static str> {
+        match s {
+            "1s" => Ok(Interval::OneSecond),
+            "1m" => Ok(Interval::OneMinute),
+            "5m" => Ok(Interval::FiveMinutes),
+            "1h" => Ok(Interval::OneHour),
+            _ => Err("Interval must be one of 1s, 1m, 5m or 1h"),
+        }
+    }
+}
+
+// One OHLCV bar, stamped with the unix time in milliseconds its interval starts at
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PriceDatum {
+    pub open: Price,
+    pub close: Price,
+    pub high: Price,
+    pub low: Price,
+    pub volume: u64,
+    pub timestamp: i64,
+}
+
+// Executed trades aggregated into bars at every interval, oldest first. Intervals
+// without trades have no bar.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CandleHistory {
+    bars: [VecDeque<PriceDatum>; 4],
+}
+
+impl Default for CandleHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CandleHistory {
+    pub fn new() -> CandleHistory {
+        CandleHistory {
+            bars: Default::default(),
+        }
+    }
+
+    // Rebuilds a history from every bar of each interval, in the order of `Interval::ALL`
+    pub fn from_bars(bars: [Vec<PriceDatum>; 4]) -> CandleHistory {
+        CandleHistory {
+            bars: bars.map(VecDeque::from),
+        }
+    }
+
+    pub fn record(&mut self, quantity: u64, price: Price, time: OffsetDateTime) {
+        let millis = (time.unix_timestamp_nanos() / 1_000_000) as i64;
+        for (interval, bars) in Interval::ALL.iter().zip(&mut self.bars) {
+            let start = millis - millis.rem_euclid(interval.millis());
+            match bars.back_mut() {
+                // A clock that steps back a little adds to the latest bar rather than reopening one
+                Some(bar) if bar.timestamp >= start => {
+                    bar.high = bar.high.max(price);
+                    bar.low = bar.low.min(price);
+                    bar.close = price;
+                    bar.volume += quantity;
+                }
+                _ => {
+                    if bars.len() == HISTORY {
+                        bars.pop_front();
+                    }
+                    bars.push_back(PriceDatum {
+                        open: price,
+                        close: price,
+                        high: price,
+                        low: price,
+                        volume: quantity,
+                        timestamp: start,
+                    });
+                }
+            }
+        }
+    }
+
+    // Bars starting between `from` and `to` inclusive, in unix milliseconds
+    pub fn get(&self, interval: Interval, from: i64, to: i64) -> Vec<PriceDatum> {
+        let index = Interval::ALL.iter().position(|i| *i == interval).unwrap();
+        self.bars[index]
+            .iter()
+            .filter(|bar| bar.timestamp >= from && bar.timestamp <= to)
+            .copied()
+            .collect()
+    }
+}
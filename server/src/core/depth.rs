@@ -0,0 +1,131 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use super::order_book::{PriceLevel, Side};
+use super::price::Price;
+use super::sequencer::{Event, Output};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LevelAction {
+    // A level entered the top of the book
+    Add,
+    Change,
+    // A level emptied or fell out of the top of the book
+    Delete,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct LevelUpdate {
+    pub action: LevelAction,
+    pub side: Side,
+    pub price: Price,
+    // Zero for deletes
+    pub volume: u64,
+}
+
+// One subscriber's view of the top `depth` price levels on each side. Every visible level
+// is tracked, seeded from the book as of `seq` and kept up to date from the book deltas
+// in later outputs, so that a level moving into view can be reported when one above it goes.
+pub struct DepthBook {
+    depth: usize,
+    seq: u64,
+    bids: BTreeMap<Price, u64>,
+    asks: BTreeMap<Price, u64>,
+}
+
+impl DepthBook {
+    pub fn new(depth: usize, seq: u64, bids: &[PriceLevel], asks: &[PriceLevel]) -> DepthBook {
+        let levels = |levels: &[PriceLevel]| {
+            levels
+                .iter()
+                .map(|level| (level.price, level.volume))
+                .collect()
+        };
+        DepthBook {
+            depth,
+            seq,
+            bids: levels(bids),
+            asks: levels(asks),
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    // Sequence number of the last output reflected
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    // The top levels on one side, best first
+    pub fn levels(&self, side: Side) -> Vec<PriceLevel> {
+        let levels: Box<dyn Iterator<Item = (&Price, &u64)>> = match side {
+            Side::Buy => Box::new(self.bids.iter().rev()),
+            Side::Sell => Box::new(self.asks.iter()),
+        };
+        levels
+            .take(self.depth)
+            .map(|(&price, &volume)| PriceLevel { price, volume })
+            .collect()
+    }
+
+    // Applies an output's book deltas, returning how the top levels changed: deletes
+    // first, then adds and changes best first. Outputs the book already reflects are ignored.
+    pub fn apply(&mut self, output: &Output) -> Vec<LevelUpdate> {
+        if output.seq <= self.seq {
+            return Vec::new();
+        }
+        self.seq = output.seq;
+        let before = [self.levels(Side::Buy), self.levels(Side::Sell)];
+        for event in &output.events {
+            if let Event::BookDelta {
+                side,
+                price,
+                volume,
+            } = *event
+            {
+                let levels = match side {
+                    Side::Buy => &mut self.bids,
+                    Side::Sell => &mut self.asks,
+                };
+                if volume == 0 {
+                    levels.remove(&price);
+                } else {
+                    levels.insert(price, volume);
+                }
+            }
+        }
+        let mut deletes = Vec::new();
+        let mut updates = Vec::new();
+        for (side, before) in [Side::Buy, Side::Sell].into_iter().zip(before) {
+            let after = self.levels(side);
+            for level in &before {
+                if !after.iter().any(|other| other.price == level.price) {
+                    deletes.push(LevelUpdate {
+                        action: LevelAction::Delete,
+                        side,
+                        price: level.price,
+                        volume: 0,
+                    });
+                }
+            }
+            for level in after {
+                let action = match before.iter().find(|other| other.price == level.price) {
+                    None => LevelAction::Add,
+                    Some(other) if other.volume != level.volume => LevelAction::Change,
+                    Some(_) => continue,
+                };
+                updates.push(LevelUpdate {
+                    action,
+                    side,
+                    price: level.price,
+                    volume: level.volume,
+                });
+            }
+        }
+        deletes.extend(updates);
+        deletes
+    }
+}
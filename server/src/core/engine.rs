@@ -1,22 +1,118 @@
-use crate::net::fix::OrderStatus;
-
-use super::order_book::{Order, OrderBook, Side};
+use super::candles::CandleHistory;
+use super::instrument::Instrument;
+use super::order_book::{
+    Fill, MarketRemainder, NewOrder, Order, OrderBook, OrderType, PostOnlyPolicy, PriceLevel, Side,
+    TimeInForce,
+};
+use super::price::Price;
+use super::registry::{OrderRecord, OrderRegistry, OrderStatus};
+use super::sequencer::{Command, Event, OrderAction};
+use super::snapshot::Snapshot;
+use super::stops::StopBook;
+use super::tape::{Trade, TradeRing};
+use std::collections::BTreeSet;
 use time::OffsetDateTime;
 
+const SECONDS_PER_DAY: i64 = 86_400;
+
 pub struct Engine<T: OrderBook> {
     pub book: T,
     pub id: u64,
-    pub history: Vec<u64>,
-    pub trade_history: Vec<(u64, f64, OffsetDateTime)>,
+    pub instrument: Instrument,
+    pub market_remainder: MarketRemainder,
+    pub post_only_policy: PostOnlyPolicy,
+    pub orders: OrderRegistry,
+    pub trades: TradeRing,
+    pub candles: CandleHistory,
+    pub stops: StopBook,
+    // Trailing stop trigger moves not yet reported to their owners
+    pub trigger_updates: Vec<(u64, Price)>,
+    // Resting GTD and DAY orders keyed by expiry time
+    expiries: BTreeSet<(i64, u64)>,
+    // Fills, and price levels with their visible volume beforehand, since events were last taken
+    fills: Vec<Fill>,
+    touched_levels: Vec<(Side, Price, u64)>,
+    // Changes to displayed orders since events were last taken, and the order being
+    // entered, which is only shown once it has finished matching
+    book_orders: Vec<Event>,
+    entering: Option<u64>,
+    // Time the command being applied was received, so that replaying it gives the same state
+    clock: Option<OffsetDateTime>,
+}
+
+impl<T: OrderBook> Default for Engine<T> {
+    fn default() -> Self {
+        Engine::new()
+    }
 }
 
 impl<T: OrderBook> Engine<T> {
     pub fn new() -> Engine<T> {
+        Engine::with_tick_size(Price::from_raw(1_000_000))
+    }
+
+    pub fn with_tick_size(tick_size: Price) -> Engine<T> {
+        Engine::with_instrument(Instrument::new("", tick_size))
+    }
+
+    pub fn with_instrument(instrument: Instrument) -> Engine<T> {
         Engine {
             book: T::new(),
             id: 0,
-            history: Vec::new(),
-            trade_history: Vec::new(),
+            instrument,
+            market_remainder: MarketRemainder::Cancel,
+            post_only_policy: PostOnlyPolicy::Reject,
+            orders: OrderRegistry::new(),
+            trades: TradeRing::new(),
+            candles: CandleHistory::new(),
+            stops: StopBook::new(),
+            trigger_updates: Vec::new(),
+            expiries: BTreeSet::new(),
+            fills: Vec::new(),
+            touched_levels: Vec::new(),
+            book_orders: Vec::new(),
+            entering: None,
+            clock: None,
+        }
+    }
+
+    fn now(&self) -> OffsetDateTime {
+        self.clock.unwrap_or_else(OffsetDateTime::now_utc)
+    }
+
+    fn validate_price(&self, price: Price) -> Result<(), &'static str> {
+        if price <= Price::ZERO {
+            Err("Invalid order price")
+        } else if !price.is_on_tick(self.instrument.tick_size) {
+            Err("Price is not a multiple of the tick size")
+        } else {
+            Ok(())
+        }
+    }
+
+    fn last_price(&self) -> Option<Price> {
+        self.trades.last().map(|trade| trade.price)
+    }
+
+    fn validate_limit_price(&self, price: Price) -> Result<(), &'static str> {
+        self.validate_price(price)?;
+        let last_price = self.last_price();
+        if let (Some(band), Some(last_price)) = (self.instrument.price_band, last_price) {
+            let width = last_price.percent(band);
+            if price < last_price - width || price > last_price + width {
+                return Err("Price is outside the price band");
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_quantity(&self, quantity: u64) -> Result<(), &'static str> {
+        if quantity == 0 {
+            Err("Invalid order quantity")
+        } else if !quantity.is_multiple_of(self.instrument.lot_size) {
+            Err("Quantity is not a multiple of the lot size")
+        } else {
+            Ok(())
         }
     }
 
@@ -24,63 +120,655 @@ impl<T: OrderBook> Engine<T> {
         &mut self,
         side: String,
         quantity: u64,
-        price: f64,
-    ) -> Result<u64, &'static str> {
+        price: Price,
+    ) -> Result<(u64, Vec<Fill>), &'static str> {
         let side = match side.as_str() {
             "Buy" => Side::Buy,
             "Sell" => Side::Sell,
             _ => return Err("Invalid order side"),
         };
+        self.submit_order(NewOrder::limit(side, quantity, price))
+    }
+
+    pub fn submit_order(&mut self, request: NewOrder) -> Result<(u64, Vec<Fill>), &'static str> {
+        self.validate_quantity(request.quantity)?;
+        if matches!(request.order_type, OrderType::Limit | OrderType::StopLimit) {
+            self.validate_limit_price(request.price)?;
+        }
+        let last_price = self.last_price();
+        let stop_price = match request.order_type {
+            OrderType::Stop | OrderType::StopLimit => Some(
+                request
+                    .stop_price
+                    .ok_or("Stop order requires a stop price")?,
+            ),
+            // Trailing stops start out trailing the last trade
+            OrderType::TrailingStop => {
+                let offset = request
+                    .trailing_offset
+                    .filter(|offset| offset.is_valid())
+                    .ok_or("Trailing stop requires a positive offset")?;
+                let last_price = last_price.ok_or("No trade price to trail")?;
+                let trigger = offset.trigger(request.side, last_price, self.instrument.tick_size);
+                Some(trigger.ok_or("Trailing offset out of range")?)
+            }
+            _ => None,
+        };
+        if let Some(stop_price) = stop_price {
+            self.validate_price(stop_price)?;
+            // Only a trade after entry through the stop price sets it off
+            let triggered = match (request.side, last_price) {
+                (Side::Buy, Some(last_price)) => last_price >= stop_price,
+                (Side::Sell, Some(last_price)) => last_price <= stop_price,
+                (_, None) => false,
+            };
+            if triggered {
+                return Err("Stop price has already been reached");
+            }
+        }
+        match request.display_quantity {
+            Some(display_quantity) if self.validate_quantity(display_quantity).is_err() => {
+                return Err("Invalid display quantity")
+            }
+            Some(_) if request.order_type != OrderType::Limit => {
+                return Err("Only limit orders can have a display quantity")
+            }
+            _ => {}
+        }
+        if request.post_only && request.order_type != OrderType::Limit {
+            return Err("Only limit orders can be post-only");
+        }
+        if request.hidden && request.display_quantity.is_some() {
+            return Err("Hidden orders cannot have a display quantity");
+        }
+        if request.hidden && !matches!(request.order_type, OrderType::Limit | OrderType::StopLimit)
+        {
+            return Err("Only limit orders can be hidden");
+        }
+        let price = if request.post_only {
+            self.post_only_price(request.side, request.price)?
+        } else {
+            request.price
+        };
+        let now = self.now();
+        let expire_at = match request.time_in_force {
+            TimeInForce::Gtd => match request.expire_at {
+                Some(expire_at) if expire_at > now.unix_timestamp() => Some(expire_at),
+                Some(_) => return Err("Expire time is in the past"),
+                None => return Err("GTD order requires an expire time"),
+            },
+            // Day orders expire at the end of the current UTC day
+            TimeInForce::Day => {
+                Some((now.unix_timestamp() / SECONDS_PER_DAY + 1) * SECONDS_PER_DAY)
+            }
+            _ => None,
+        };
         self.id += 1;
-        let price_2dp = format!("{:.2}", price).parse().unwrap();
-        let order = Order::new(self.id, side, quantity, price_2dp);
+        let id = self.id;
+        let mut order = Order::new(id, request.side, request.quantity, price);
+        order.order_type = request.order_type;
+        order.time_in_force = request.time_in_force;
+        order.expire_at = expire_at;
+        order.stop_price = stop_price;
+        order.trailing_offset = request.trailing_offset;
+        order.display_quantity = request.display_quantity;
+        order.post_only = request.post_only;
+        order.hidden = request.hidden;
+        self.orders.insert(order, OrderStatus::New, now);
+
+        let mut fills = if order.order_type.is_stop() {
+            match (order.order_type, last_price) {
+                (OrderType::TrailingStop, Some(last_price)) => {
+                    self.stops.insert_trailing(order, last_price)
+                }
+                _ => self.stops.insert(order),
+            }
+            if let Some(expire_at) = expire_at {
+                self.expiries.insert((expire_at, id));
+            }
+            Vec::new()
+        } else {
+            self.enter_order(order)
+        };
+        // Stops set off by the order's trades are entered after it
+        let triggered = self.trigger_stops();
+        fills.extend(
+            triggered
+                .into_iter()
+                .filter(|fill| fill.bid_id == id || fill.ask_id == id),
+        );
+        Ok((id, fills))
+    }
+
+    // Where a post-only order can rest without trading, following the post-only policy
+    fn post_only_price(&self, side: Side, price: Price) -> Result<Price, &'static str> {
+        let opposite = match self.crossing_price(side, price) {
+            Some(opposite) => opposite,
+            None => return Ok(price),
+        };
+        match self.post_only_policy {
+            PostOnlyPolicy::Reject => Err("Post-only order would trade on entry"),
+            PostOnlyPolicy::Reprice => {
+                let price = match side {
+                    Side::Buy => opposite - self.instrument.tick_size,
+                    Side::Sell => opposite + self.instrument.tick_size,
+                };
+                self.validate_price(price)?;
+                Ok(price)
+            }
+        }
+    }
+
+    // Rests a new or newly triggered order and matches it against the book
+    fn enter_order(&mut self, mut order: Order) -> Vec<Fill> {
+        let id = order.id;
+        if order.order_type == OrderType::Market {
+            // Priced through the whole opposite side so that it sweeps every level
+            order.price = match order.side {
+                Side::Buy => Price::MAX,
+                Side::Sell => Price::ZERO,
+            };
+        }
+        if order.time_in_force == TimeInForce::Fok
+            && self.crossing_volume(order.side, order.price) < order.quantity
+        {
+            let _ = self
+                .orders
+                .transition(id, OrderStatus::Cancelled, self.now());
+            return Vec::new();
+        }
+        self.entering = Some(id);
+        self.rest_order(order);
+        let fills = self.match_orders(order.side);
+        if order.order_type == OrderType::Market {
+            self.settle_market_remainder(id, fills.last().map(|fill| fill.price));
+        } else if order.time_in_force == TimeInForce::Ioc {
+            self.cancel_remainder(id);
+        } else if order.display_quantity.is_some() {
+            self.slice_iceberg(id);
+        }
+        self.entering = None;
+        if let Ok(order) = self.book.get_order_by_id(id) {
+            self.record_book_order(OrderAction::Add, &order, 0);
+        }
+        if let Some(expire_at) = order.expire_at {
+            if self.book.get_order_by_id(id).is_ok() {
+                self.expiries.insert((expire_at, id));
+            }
+        }
+        fills
+    }
+
+    // Volume an aggressor at `price` could trade with, counting the reserve that resting
+    // icebergs hold back behind their displayed slice
+    fn crossing_volume(&self, aggressor: Side, price: Price) -> u64 {
+        let opposite = match aggressor {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        };
+        let reserve: u64 = self
+            .book
+            .get_orders(opposite)
+            .iter()
+            .filter(|order| order.display_quantity.is_some())
+            .filter(|order| match aggressor {
+                Side::Buy => order.price <= price,
+                Side::Sell => order.price >= price,
+            })
+            .filter_map(|order| {
+                let leaves_quantity = self.orders.get(order.id)?.order.leaves_quantity;
+                Some(leaves_quantity.saturating_sub(order.leaves_quantity))
+            })
+            .sum();
+        self.book.get_crossing_volume(aggressor, price) + reserve
+    }
+
+    // An iceberg aggresses with its full quantity, and then only its display
+    // quantity rests on the book with the rest held back in reserve
+    fn slice_iceberg(&mut self, id: u64) {
+        if let Ok(order) = self.book.get_order_by_id(id) {
+            if order.peak().leaves_quantity < order.leaves_quantity {
+                let _ = self.remove_order(&order);
+                self.rest_order(order.peak());
+            }
+        }
+    }
+
+    // Once its visible slice is filled an iceberg shows a new one from its reserve,
+    // which joins the back of the queue at its price
+    fn replenish_iceberg(&mut self, id: u64) {
+        let order = match self.orders.get(id) {
+            Some(record) if record.order.display_quantity.is_some() => record.order,
+            _ => return,
+        };
+        if order.leaves_quantity > 0 && self.book.get_order_by_id(id).is_err() {
+            self.rest_order(order.peak());
+        }
+    }
+
+    // Enters every stop the last trade has printed through, one at a time after the
+    // fill that triggered it, so each can move the price and trigger further stops
+    fn trigger_stops(&mut self) -> Vec<Fill> {
+        let mut fills = Vec::new();
+        while let Some(last_price) = self.last_price() {
+            let mut order = match self.stops.pop_triggered(last_price) {
+                Some(order) => order,
+                None => break,
+            };
+            order.order_type = match order.order_type {
+                OrderType::StopLimit => OrderType::Limit,
+                _ => OrderType::Market,
+            };
+            fills.extend(self.enter_order(order));
+        }
+        fills
+    }
+
+    // Best opposite price that an order at `price` would trade against, if any
+    fn crossing_price(&self, side: Side, price: Price) -> Option<Price> {
+        match side {
+            Side::Buy => self
+                .book
+                .get_best_price(Side::Sell)
+                .filter(|ask| price >= *ask),
+            Side::Sell => self
+                .book
+                .get_best_price(Side::Buy)
+                .filter(|bid| price <= *bid),
+        }
+    }
+
+    // Notes a price level's volume before it first changes, to report a delta later
+    fn touch_level(&mut self, side: Side, price: Price) {
+        if !self
+            .touched_levels
+            .iter()
+            .any(|&(s, p, _)| s == side && p == price)
+        {
+            let volume = self.book.get_level_volume(side, price);
+            self.touched_levels.push((side, price, volume));
+        }
+    }
+
+    // Notes a change to an order for the market-by-order feed, unless it is hidden or
+    // still being entered
+    fn record_book_order(&mut self, action: OrderAction, order: &Order, quantity: u64) {
+        if order.hidden || self.entering == Some(order.id) {
+            return;
+        }
+        self.book_orders.push(Event::BookOrder {
+            action,
+            id: order.id,
+            side: order.side,
+            price: order.price,
+            quantity,
+            remaining: match action {
+                OrderAction::Cancel => 0,
+                _ => order.leaves_quantity,
+            },
+        });
+    }
+
+    // Number of orders queued ahead of an order at its price, hidden ones included
+    fn queue_position(&self, order: &Order) -> Option<usize> {
+        self.book
+            .get_orders(order.side)
+            .iter()
+            .filter(|resting| resting.price == order.price)
+            .position(|resting| resting.id == order.id)
+    }
+
+    fn rest_order(&mut self, order: Order) {
+        self.touch_level(order.side, order.price);
+        self.record_book_order(OrderAction::Add, &order, 0);
         match order.side {
             Side::Buy => self.book.add_bid_order(order),
             Side::Sell => self.book.add_ask_order(order),
         };
-        Ok(self.id)
     }
 
-    pub fn amend_order(&mut self, id: u64, quantity: u64, price: f64) -> Result<u64, String> {
-        let price_2dp = format!("{:.2}", price).parse().unwrap();
-        let result = self.book.amend_bid_order(id, quantity, price_2dp);
-        if result.is_ok() {
-            return Ok(id);
+    fn remove_order(&mut self, order: &Order) -> Result<&'static str, &'static str> {
+        self.touch_level(order.side, order.price);
+        self.record_book_order(OrderAction::Cancel, order, 0);
+        match order.side {
+            Side::Buy => self.book.remove_bid_order(order.id),
+            Side::Sell => self.book.remove_ask_order(order.id),
+        }
+    }
+
+    // Once the book is exhausted a market order either rests as a limit order at
+    // its last fill price or has its remainder cancelled
+    fn settle_market_remainder(&mut self, id: u64, last_price: Option<Price>) {
+        match (self.market_remainder, last_price) {
+            (MarketRemainder::Limit, Some(price)) => {
+                let mut order = match self.book.get_order_by_id(id) {
+                    Ok(order) => order,
+                    Err(_) => return,
+                };
+                let _ = self.remove_order(&order);
+                let now = self.now();
+                order.price = price;
+                order.order_type = OrderType::Limit;
+                self.rest_order(order);
+                if let Some(record) = self.orders.get_mut(id) {
+                    record.order.price = price;
+                    record.order.order_type = OrderType::Limit;
+                    record.updated_at = now;
+                }
+            }
+            _ => self.cancel_remainder(id),
+        }
+    }
+
+    // Pulls whatever is left of an order off the book and cancels it
+    fn cancel_remainder(&mut self, id: u64) {
+        if let Ok(order) = self.book.get_order_by_id(id) {
+            let _ = self.remove_order(&order);
+            let _ = self
+                .orders
+                .transition(id, OrderStatus::Cancelled, self.now());
+        }
+    }
+
+    // Earliest expiry time of any resting GTD or DAY order
+    pub fn next_expiry(&self) -> Option<i64> {
+        self.expiries.first().map(|&(expire_at, _)| expire_at)
+    }
+
+    // Removes every GTD and DAY order whose expiry time has passed, returning their ids
+    pub fn expire_orders(&mut self, now: OffsetDateTime) -> Vec<u64> {
+        let mut expired = Vec::new();
+        while let Some(&(expire_at, id)) = self.expiries.first() {
+            if expire_at > now.unix_timestamp() {
+                break;
+            }
+            self.expiries.remove(&(expire_at, id));
+            // Orders that have since been filled or cancelled are no longer on the book
+            let resting = match self.book.get_order_by_id(id) {
+                Ok(order) => self.remove_order(&order).is_ok(),
+                Err(_) => self.stops.remove(id).is_some(),
+            };
+            if resting
+                && self
+                    .orders
+                    .transition(id, OrderStatus::Expired, now)
+                    .is_ok()
+            {
+                expired.push(id);
+            }
+        }
+        expired
+    }
+
+    pub fn amend_order(
+        &mut self,
+        id: u64,
+        quantity: u64,
+        price: Price,
+    ) -> Result<(u64, Vec<Fill>), String> {
+        self.validate_quantity(quantity)?;
+        // A stop order has no limit price to change until it is triggered
+        let stop_type = self.stops.get(id).map(|stop| stop.order_type);
+        if matches!(stop_type, None | Some(OrderType::StopLimit)) {
+            self.validate_limit_price(price)?;
+        }
+        if let Some(stop) = self.stops.get_mut(id) {
+            let price = match stop.order_type {
+                OrderType::StopLimit => price,
+                _ => stop.price,
+            };
+            stop.amend(quantity, price)?;
+            self.orders.replace(id, quantity, price, self.now())?;
+            return Ok((id, Vec::new()));
+        }
+        let resting = match self.book.get_order_by_id(id) {
+            Ok(order) => order,
+            Err(_) => return Err(format!("Order not found for id: {}", id)),
+        };
+        let price = if resting.post_only {
+            self.post_only_price(resting.side, price)?
+        } else {
+            price
+        };
+        let mut fills = if resting.display_quantity.is_some() {
+            // Icebergs are re-entered in full and sliced again, losing time priority
+            let mut amended = self.get_order_record(id)?.order;
+            amended.amend(quantity, price)?;
+            let _ = self.remove_order(&resting);
+            self.orders.replace(id, quantity, price, self.now())?;
+            self.enter_order(amended)
         } else {
-            let result = self.book.amend_ask_order(id, quantity, price);
-            if result.is_ok() {
-                return Ok(id);
+            self.touch_level(resting.side, resting.price);
+            self.touch_level(resting.side, price);
+            let position = self.queue_position(&resting);
+            let result = match resting.side {
+                Side::Buy => self.book.amend_bid_order(id, quantity, price),
+                Side::Sell => self.book.amend_ask_order(id, quantity, price),
+            };
+            result?;
+            self.orders.replace(id, quantity, price, self.now())?;
+            // The feed follows whatever the book did with the order's place in the queue
+            let amended = self.book.get_order_by_id(id)?;
+            if amended.price == resting.price && self.queue_position(&amended) == position {
+                self.record_book_order(OrderAction::Modify, &amended, 0);
             } else {
-                return Err(format!("Order not found for id: {}", id));
+                self.record_book_order(OrderAction::Cancel, &resting, 0);
+                self.record_book_order(OrderAction::Add, &amended, 0);
             }
-        }
+            self.match_orders(resting.side)
+        };
+        let triggered = self.trigger_stops();
+        fills.extend(
+            triggered
+                .into_iter()
+                .filter(|fill| fill.bid_id == id || fill.ask_id == id),
+        );
+        Ok((id, fills))
     }
 
     pub fn cancel_order(&mut self, id: u64) -> Result<(), String> {
-        let result = self.book.remove_bid_order(id);
-        if result.is_ok() {
-            return Ok(());
+        let removed = match self.book.get_order_by_id(id) {
+            Ok(order) => self.remove_order(&order).is_ok(),
+            Err(_) => self.stops.remove(id).is_some(),
+        };
+        if removed {
+            self.orders
+                .transition(id, OrderStatus::Cancelled, self.now())?;
+            Ok(())
         } else {
-            let result = self.book.remove_ask_order(id);
-            if result.is_ok() {
-                return Ok(());
-            } else {
-                return Err(format!("Order not found for id: {}", id));
+            Err(format!("Order not found for id: {}", id))
+        }
+    }
+
+    // Sweeps the opposite side for as long as the incoming order crosses
+    fn match_orders(&mut self, aggressor: Side) -> Vec<Fill> {
+        let mut fills = Vec::new();
+        loop {
+            for side in [Side::Buy, Side::Sell] {
+                if let Some(price) = self.book.get_best_price(side) {
+                    self.touch_level(side, price);
+                }
+            }
+            let (bid_id, ask_id, quantity, price) = match self.book.match_orders(aggressor) {
+                Ok(trade) => trade,
+                Err(_) => break,
+            };
+            let now = self.now();
+            let fill = Fill {
+                bid_id,
+                ask_id,
+                quantity,
+                price,
+            };
+            // Both ids were registered on entry, so these cannot fail
+            let _ = self.orders.apply_fill(bid_id, fill, now);
+            let _ = self.orders.apply_fill(ask_id, fill, now);
+            self.trades.push(Trade {
+                id: self.trades.last().map_or(1, |trade| trade.id + 1),
+                bid_id,
+                ask_id,
+                aggressor,
+                quantity,
+                price,
+                timestamp: now.unix_timestamp_nanos() as i64,
+            });
+            self.candles.record(quantity, price, now);
+            self.fills.push(fill);
+            for id in [bid_id, ask_id] {
+                if let Some(mut order) = self.orders.get(id).map(|record| record.order) {
+                    // Fully filled orders have already left the book
+                    order.leaves_quantity = self
+                        .book
+                        .get_order_by_id(id)
+                        .map_or(0, |order| order.leaves_quantity);
+                    self.record_book_order(OrderAction::Execute, &order, quantity);
+                }
+            }
+            fills.push(fill);
+            self.replenish_iceberg(bid_id);
+            self.replenish_iceberg(ask_id);
+            self.ratchet_trailing_stops(price, now);
+        }
+        fills
+    }
+
+    fn ratchet_trailing_stops(&mut self, price: Price, now: OffsetDateTime) {
+        for (id, trigger) in self.stops.ratchet(price, self.instrument.tick_size) {
+            if let Some(record) = self.orders.get_mut(id) {
+                record.order.stop_price = Some(trigger);
+                record.updated_at = now;
             }
+            self.trigger_updates.push((id, trigger));
         }
     }
 
-    pub fn match_orders(&mut self) -> Result<(u64, f64), &'static str> {
-        let result = self.book.match_orders();
-        match result {
-            Ok((bid_id, ask_id, quantity, price)) => {
-                self.history.push(bid_id);
-                self.history.push(ask_id);
-                self.trade_history.push((quantity, price, OffsetDateTime::now_utc()));
-                Ok((quantity, price))
+    pub fn take_trigger_updates(&mut self) -> Vec<(u64, Price)> {
+        std::mem::take(&mut self.trigger_updates)
+    }
+
+    // Applies a sequenced command received at `now`, acknowledging or rejecting it ahead
+    // of the events it caused
+    pub fn apply(&mut self, command: Command, now: OffsetDateTime) -> Vec<Event> {
+        self.clock = Some(now);
+        let events = self.apply_command(command);
+        self.clock = None;
+        events
+    }
+
+    // Copies everything needed to rebuild this engine, as of the command with sequence number `seq`
+    pub fn snapshot(&self, seq: u64) -> Snapshot {
+        Snapshot {
+            seq,
+            symbol: self.instrument.symbol.clone(),
+            id: self.id,
+            bids: self.book.get_orders(Side::Buy),
+            asks: self.book.get_orders(Side::Sell),
+            stops: self.stops.entries(),
+            records: self.orders.records().into_iter().cloned().collect(),
+            trades: self.trades.iter().copied().collect(),
+            candles: self.candles.clone(),
+            expiries: self.expiries.iter().copied().collect(),
+        }
+    }
+
+    // Replaces the engine's orders, trades and ids with those in a snapshot
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.book = T::new();
+        for order in snapshot.bids {
+            self.book.add_bid_order(order);
+        }
+        for order in snapshot.asks {
+            self.book.add_ask_order(order);
+        }
+        self.stops = StopBook::new();
+        for (order, peak) in snapshot.stops {
+            match peak {
+                Some(peak) => self.stops.insert_trailing(order, peak),
+                None => self.stops.insert(order),
+            }
+        }
+        self.orders = OrderRegistry::new();
+        for record in snapshot.records {
+            self.orders.restore(record);
+        }
+        self.id = snapshot.id;
+        self.trades = TradeRing::new();
+        for trade in snapshot.trades {
+            self.trades.push(trade);
+        }
+        self.candles = snapshot.candles;
+        self.expiries = snapshot.expiries.into_iter().collect();
+        self.trigger_updates.clear();
+        self.fills.clear();
+        self.touched_levels.clear();
+        self.book_orders.clear();
+    }
+
+    fn apply_command(&mut self, command: Command) -> Vec<Event> {
+        let ack = match command {
+            Command::New(request) => self
+                .submit_order(request)
+                .map(|(id, _)| id)
+                .map_err(String::from),
+            Command::Amend {
+                id,
+                quantity,
+                price,
+            } => self.amend_order(id, quantity, price).map(|(id, _)| id),
+            Command::Cancel { id } => self.cancel_order(id).map(|_| id),
+            Command::Expire { now } => {
+                self.expire_orders(OffsetDateTime::from_unix_timestamp(now));
+                return self.take_events();
+            }
+        };
+        let mut events = vec![match ack {
+            Ok(id) => Event::Accepted { id },
+            Err(reason) => Event::Rejected { reason },
+        }];
+        events.extend(self.take_events());
+        events
+    }
+
+    // Everything that has happened since the last call: fills, trailing stop moves,
+    // order updates, changes to displayed orders and then the price levels whose
+    // visible volume changed
+    pub fn take_events(&mut self) -> Vec<Event> {
+        let mut events: Vec<Event> = self.fills.drain(..).map(Event::Fill).collect();
+        for (id, stop_price) in self.take_trigger_updates() {
+            events.push(Event::StopMoved { id, stop_price });
+        }
+        let changed = self.orders.take_changed();
+        for &id in &changed {
+            if let Some(record) = self.orders.get(id) {
+                events.push(Event::OrderUpdate {
+                    id,
+                    status: record.status,
+                    filled_quantity: record.order.filled_quantity,
+                    leaves_quantity: record.order.leaves_quantity,
+                });
+                // Orders that have left the book are not due to expire any more
+                let expiry = record
+                    .order
+                    .expire_at
+                    .filter(|_| record.status.is_terminal());
+                if let Some(expire_at) = expiry {
+                    self.expiries.remove(&(expire_at, id));
+                }
+            }
+        }
+        self.orders.retire(&changed);
+        events.append(&mut self.book_orders);
+        for (side, price, before) in std::mem::take(&mut self.touched_levels) {
+            let volume = self.book.get_level_volume(side, price);
+            if volume != before {
+                events.push(Event::BookDelta {
+                    side,
+                    price,
+                    volume,
+                });
             }
-            Err(e) => Err(e),
         }
+        events
     }
 
     #[allow(dead_code)]
@@ -94,68 +782,76 @@ impl<T: OrderBook> Engine<T> {
         (bids, asks)
     }
 
-    pub fn get_volume_at_limit(&self, price: f64) -> u64 {
+    // Best visible price on one side and the volume shown there
+    pub fn get_top_of_book(&self, side: Side) -> Option<PriceLevel> {
+        let best = match side {
+            Side::Buy => self.book.get_bids()[0],
+            Side::Sell => self.book.get_asks()[0],
+        };
+        if best.quantity == 0 {
+            return None;
+        }
+        Some(PriceLevel {
+            price: best.price,
+            volume: self.book.get_level_volume(side, best.price),
+        })
+    }
+
+    // Resting orders other than hidden ones, in priority order, as the market-by-order feed shows them
+    pub fn get_displayed_orders(&self, side: Side) -> Vec<Order> {
+        let mut orders = self.book.get_orders(side);
+        orders.retain(|order| !order.hidden);
+        orders
+    }
+
+    pub fn get_depth(&self, side: Side, depth: usize) -> Vec<PriceLevel> {
+        self.book.get_levels(side, depth)
+    }
+
+    pub fn get_volume_at_limit(&self, price: Price) -> u64 {
         self.book.get_volume_at_limit(price)
     }
 
-    pub fn get_order_status(&self, id: u64) -> Result<String, &str> {
-        let result = self.book.get_order_by_id(id);
-        match result {
-            Ok(order) => {
-                let status = format!(
-                    "New -- {} {} @ {} ({})",
-                    order.side.to_string(),
-                    order.quantity,
-                    order.price,
-                    order.id
-                );
-                Ok(status)
-            }
-            Err(e) => {
-                let result = self.history.contains(&id);
-                if result {
-                    Ok("Filled".to_string())
-                } else {
-                    Err(e)
-                }
-            }
-        }
+    pub fn get_order_record(&self, id: u64) -> Result<&OrderRecord, &'static str> {
+        self.orders.get(id).ok_or("Order not found")
     }
 
-    pub fn get_execution_status(&self, id: u64) -> Result<OrderStatus, &str> {
-        let result = self.book.get_order_by_id(id);
-        match result {
-            Ok(_) => Ok(OrderStatus::New),
-            Err(_) => {
-                let result = self.history.contains(&id);
-                if result {
-                    Ok(OrderStatus::Filled)
-                } else {
-                    Ok(OrderStatus::Rejected)
-                }
-            }
-        }
+    pub fn get_order_status(&self, id: u64) -> Result<String, &'static str> {
+        let record = self.get_order_record(id)?;
+        let order = record.order;
+        Ok(format!(
+            "{} -- {} {}/{} @ {} ({})",
+            record.status, order.side, order.leaves_quantity, order.quantity, order.price, order.id
+        ))
     }
 
-    pub fn get_market_price(&self) -> Result<f64, &str> {
+    pub fn get_execution_status(&self, id: u64) -> Result<OrderStatus, &'static str> {
+        self.get_order_record(id).map(|record| record.status)
+    }
+
+    pub fn get_market_price(&self) -> Result<Price, &'static str> {
         self.book.get_market_price()
     }
 
-    pub fn get_trade_history(&self) -> Vec<(u64, f64, String)> {
-        let n = self.trade_history.len();
-        let m = std::cmp::min(10, n);
-    
-        let mut trades_vec: Vec<(u64, f64, String)> = Vec::with_capacity(10);
-        
-        for i in 0..m {
-            let (quantity, price, datetime) = self.trade_history[n - 1 - i].clone();
-            trades_vec.push((quantity, price, datetime.format("%Y-%m-%dT%H:%M:%SZ").to_string()));
-        }
-    
+    // The last ten trades, newest first, padded with empty rows
+    pub fn get_trade_history(&self) -> Vec<(u64, Price, String)> {
+        let mut trades_vec: Vec<(u64, Price, String)> = self
+            .trades
+            .iter()
+            .rev()
+            .take(10)
+            .map(|trade| {
+                let datetime = OffsetDateTime::from_unix_timestamp_nanos(trade.timestamp as i128);
+                (
+                    trade.quantity,
+                    trade.price,
+                    datetime.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+                )
+            })
+            .collect();
         while trades_vec.len() < 10 {
-            trades_vec.push((0, 0.0, "".to_string()));
+            trades_vec.push((0, Price::ZERO, "".to_string()));
         }
-        
         trades_vec
     }
 }
@@ -0,0 +1,119 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::engine::Engine;
+use super::order_book::OrderBook;
+use super::price::Price;
+use super::registry::OrderStatus;
+use super::sequencer::{Event, Output};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecType {
+    Trade,
+    Cancelled,
+    Expired,
+    // A trailing stop's trigger followed the market
+    StopMoved,
+}
+
+// What one trade, cancellation or trailing stop move did to one order, as pushed to
+// whoever entered it
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Execution {
+    pub id: u64,
+    pub exec_type: ExecType,
+    pub status: OrderStatus,
+    // Quantity and price of this trade, zero and none for a cancellation
+    pub last_quantity: u64,
+    pub last_price: Option<Price>,
+    pub cum_quantity: u64,
+    pub leaves_quantity: u64,
+    // The trigger a trailing stop has moved to, none for anything else
+    pub stop_price: Option<Price>,
+}
+
+// Every execution in a command's output, in the order it happened. Called on the matching
+// thread, as the order quantities needed to tell what was left after each of several
+// fills of one order are not in the output.
+pub fn executions<T: OrderBook>(engine: &Engine<T>, output: &Output) -> Vec<Execution> {
+    let mut executions = Vec::new();
+    // Fills come first, so each order's quantity filled before this command is what it has
+    // filled now less every fill of it in the output
+    let mut filled = HashMap::new();
+    for event in &output.events {
+        if let Event::Fill(fill) = event {
+            for id in [fill.bid_id, fill.ask_id] {
+                *filled.entry(id).or_insert(0) += fill.quantity;
+            }
+        }
+    }
+    let mut cum: HashMap<u64, u64> = filled
+        .iter()
+        .filter_map(|(&id, &quantity)| {
+            let record = engine.get_order_record(id).ok()?;
+            Some((id, record.order.filled_quantity - quantity))
+        })
+        .collect();
+    for event in &output.events {
+        match event {
+            Event::Fill(fill) => {
+                for id in [fill.bid_id, fill.ask_id] {
+                    let (Some(cum), Ok(record)) = (cum.get_mut(&id), engine.get_order_record(id))
+                    else {
+                        continue;
+                    };
+                    *cum += fill.quantity;
+                    executions.push(Execution {
+                        id,
+                        exec_type: ExecType::Trade,
+                        status: if *cum < record.order.quantity {
+                            OrderStatus::PartiallyFilled
+                        } else {
+                            OrderStatus::Filled
+                        },
+                        last_quantity: fill.quantity,
+                        last_price: Some(fill.price),
+                        cum_quantity: *cum,
+                        leaves_quantity: record.order.quantity - *cum,
+                        stop_price: None,
+                    });
+                }
+            }
+            Event::OrderUpdate {
+                id,
+                status: status @ (OrderStatus::Cancelled | OrderStatus::Expired),
+                filled_quantity,
+                ..
+            } => executions.push(Execution {
+                id: *id,
+                exec_type: match status {
+                    OrderStatus::Expired => ExecType::Expired,
+                    _ => ExecType::Cancelled,
+                },
+                status: *status,
+                last_quantity: 0,
+                last_price: None,
+                cum_quantity: *filled_quantity,
+                leaves_quantity: 0,
+                stop_price: None,
+            }),
+            Event::StopMoved { id, stop_price } => {
+                if let Ok(record) = engine.get_order_record(*id) {
+                    executions.push(Execution {
+                        id: *id,
+                        exec_type: ExecType::StopMoved,
+                        status: record.status,
+                        last_quantity: 0,
+                        last_price: None,
+                        cum_quantity: record.order.filled_quantity,
+                        leaves_quantity: record.order.leaves_quantity,
+                        stop_price: Some(*stop_price),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    executions
+}
@@ -0,0 +1,114 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use super::engine::Engine;
+use super::order_book::OrderBook;
+use super::price::Price;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Instrument {
+    pub symbol: String,
+    pub tick_size: Price,
+    // Order quantities must be whole multiples of the lot size
+    pub lot_size: u64,
+    // Limit prices more than this percentage away from the last trade are rejected
+    pub price_band: Option<Price>,
+}
+
+impl Instrument {
+    pub fn new(symbol: &str, tick_size: Price) -> Instrument {
+        Instrument {
+            symbol: symbol.to_string(),
+            tick_size,
+            lot_size: 1,
+            price_band: None,
+        }
+    }
+}
+
+// Parses `SYMBOL[:tick size[:lot size[:price band %]]]`, e.g. `XYZ:0.01:10:5`
+impl FromStr for Instrument {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Instrument, &'static str> {
+        let mut parts = s.split(':');
+        let symbol = parts.next().filter(|symbol| !symbol.is_empty());
+        let mut instrument =
+            Instrument::new(symbol.ok_or("Missing symbol")?, Price::from_raw(1_000_000));
+        if let Some(tick_size) = parts.next() {
+            instrument.tick_size = tick_size.parse().map_err(|_| "Invalid tick size")?;
+        }
+        if let Some(lot_size) = parts.next() {
+            instrument.lot_size = lot_size.parse().map_err(|_| "Invalid lot size")?;
+        }
+        if let Some(price_band) = parts.next() {
+            instrument.price_band = Some(price_band.parse().map_err(|_| "Invalid price band")?);
+        }
+        if instrument.tick_size <= Price::ZERO || instrument.lot_size == 0 {
+            return Err("Tick and lot sizes must be positive");
+        }
+        Ok(instrument)
+    }
+}
+
+// Every tradeable instrument by symbol, each with its own engine and order book
+pub struct InstrumentRegistry<T: OrderBook> {
+    engines: BTreeMap<String, Engine<T>>,
+}
+
+impl<T: OrderBook> Default for InstrumentRegistry<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: OrderBook> InstrumentRegistry<T> {
+    pub fn new() -> InstrumentRegistry<T> {
+        InstrumentRegistry {
+            engines: BTreeMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, instrument: Instrument) -> Result<&mut Engine<T>, &'static str> {
+        if self.engines.contains_key(&instrument.symbol) {
+            return Err("Instrument already listed");
+        }
+        let symbol = instrument.symbol.clone();
+        Ok(self
+            .engines
+            .entry(symbol)
+            .or_insert(Engine::with_instrument(instrument)))
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&Engine<T>> {
+        self.engines.get(symbol)
+    }
+
+    pub fn get_mut(&mut self, symbol: &str) -> Option<&mut Engine<T>> {
+        self.engines.get_mut(symbol)
+    }
+
+    pub fn instruments(&self) -> Vec<&Instrument> {
+        self.engines
+            .values()
+            .map(|engine| &engine.instrument)
+            .collect()
+    }
+
+    pub fn into_engines(self) -> impl Iterator<Item = Engine<T>> {
+        self.engines.into_values()
+    }
+
+    pub fn engines_mut(&mut self) -> impl Iterator<Item = &mut Engine<T>> {
+        self.engines.values_mut()
+    }
+
+    pub fn len(&self) -> usize {
+        self.engines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.engines.is_empty()
+    }
+}
@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::sequencer::{Command, Event};
+
+// Larger lengths can only come from a corrupt header
+const MAX_RECORD_LEN: u32 = 16 * 1024 * 1024;
+
+// When appended records are forced to disk
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    Always,
+    // After every n records
+    Every(u64),
+    // Left to the operating system
+    Never,
+}
+
+impl FromStr for FsyncPolicy {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<FsyncPolicy, &'static str> {
+        match s {
+            "always" => Ok(FsyncPolicy::Always),
+            "never" => Ok(FsyncPolicy::Never),
+            _ => match s.parse::<u64>() {
+                Ok(n) if n > 0 => Ok(FsyncPolicy::Every(n)),
+                _ => Err("Invalid fsync policy"),
+            },
+        }
+    }
+}
+
+// Where each engine's journal, snapshot and trade tape live, `<dir>/<SYMBOL>.journal`,
+// `<dir>/<SYMBOL>.snapshot` and `<dir>/<SYMBOL>.tape`, and how the journal is synced
+#[derive(Clone, Debug)]
+pub struct JournalConfig {
+    pub dir: PathBuf,
+    pub policy: FsyncPolicy,
+}
+
+impl JournalConfig {
+    pub fn path(&self, symbol: &str) -> PathBuf {
+        self.dir.join(format!("{}.journal", symbol))
+    }
+
+    pub fn snapshot_path(&self, symbol: &str) -> PathBuf {
+        self.dir.join(format!("{}.snapshot", symbol))
+    }
+
+    pub fn tape_path(&self, symbol: &str) -> PathBuf {
+        self.dir.join(format!("{}.tape", symbol))
+    }
+}
+
+// A sequenced command together with everything it produced
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub seq: u64,
+    // Unix time in nanoseconds at which the command was applied
+    pub timestamp: i64,
+    pub command: Command,
+    pub events: Vec<Event>,
+}
+
+// Append-only log of one engine's entries. Each record is a little-endian u32 length
+// and CRC-32 of the payload followed by the payload, an entry as JSON.
+pub struct Journal {
+    file: File,
+    policy: FsyncPolicy,
+    unsynced: u64,
+}
+
+impl Journal {
+    // Opens a journal for appending, first cutting off any record left incomplete
+    // by a crash so that new records follow the last good one
+    pub fn open(path: &Path, policy: FsyncPolicy) -> io::Result<Journal> {
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let (_, valid_len) = read_records(&file)?;
+        if valid_len < file.metadata()?.len() {
+            file.set_len(valid_len)?;
+            file.sync_all()?;
+        }
+        Ok(Journal {
+            file,
+            policy,
+            unsynced: 0,
+        })
+    }
+
+    pub fn append(&mut self, entry: &JournalEntry) -> io::Result<()> {
+        let payload = serde_json::to_vec(entry)?;
+        let mut record = Vec::with_capacity(payload.len() + 8);
+        record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        record.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        record.extend_from_slice(&payload);
+        self.file.write_all(&record)?;
+        self.unsynced += 1;
+        let due = match self.policy {
+            FsyncPolicy::Always => true,
+            FsyncPolicy::Every(n) => self.unsynced >= n,
+            FsyncPolicy::Never => false,
+        };
+        if due {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.unsynced = 0;
+        self.file.sync_data()
+    }
+
+    // Drops every record, once a snapshot holds everything they did
+    pub fn truncate(&mut self) -> io::Result<()> {
+        self.unsynced = 0;
+        self.file.set_len(0)?;
+        self.file.sync_all()
+    }
+}
+
+// Reads every intact entry in a journal, stopping at the first truncated or corrupt record
+pub fn read_journal(path: &Path) -> io::Result<Vec<JournalEntry>> {
+    match File::open(path) {
+        Ok(file) => read_records(&file).map(|(entries, _)| entries),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+// Entries up to the first bad record, and the length in bytes of the good ones
+fn read_records(file: &File) -> io::Result<(Vec<JournalEntry>, u64)> {
+    let mut reader = BufReader::new(file);
+    let mut entries = Vec::new();
+    let mut valid_len = 0;
+    let mut header = [0u8; 8];
+    loop {
+        if reader.read_exact(&mut header).is_err() {
+            break;
+        }
+        let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if len > MAX_RECORD_LEN {
+            break;
+        }
+        let mut payload = vec![0u8; len as usize];
+        if reader.read_exact(&mut payload).is_err() || crc32fast::hash(&payload) != crc {
+            break;
+        }
+        match serde_json::from_slice(&payload) {
+            Ok(entry) => entries.push(entry),
+            Err(_) => break,
+        }
+        valid_len += 8 + len as u64;
+    }
+    Ok((entries, valid_len))
+}
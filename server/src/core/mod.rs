@@ -1,3 +1,18 @@
+pub mod btree;
+pub mod bus;
+pub mod candles;
+pub mod depth;
 pub mod engine;
+pub mod execution;
+pub mod instrument;
+pub mod journal;
 pub mod order_book;
+pub mod price;
+pub mod registry;
+pub mod replay;
+pub mod sequencer;
+pub mod shard;
+pub mod snapshot;
+pub mod stops;
+pub mod tape;
 pub mod vector;
@@ -1,4 +1,7 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use super::price::Price;
 
 pub trait OrderBook {
     fn new() -> Self;
@@ -10,38 +13,103 @@ pub trait OrderBook {
         &mut self,
         id: u64,
         quantity: u64,
-        price: f64,
+        price: Price,
     ) -> Result<&'static str, &'static str>;
     fn amend_ask_order(
         &mut self,
         id: u64,
         quantity: u64,
-        price: f64,
+        price: Price,
     ) -> Result<&'static str, &'static str>;
-    fn match_orders(&mut self) -> Result<(u64, u64, u64, f64), &'static str>;
+    // Trades the best bid against the best ask at the resting (non-aggressor) price
+    fn match_orders(&mut self, aggressor: Side) -> Result<(u64, u64, u64, Price), &'static str>;
     fn display(&self);
     fn get_bids(&self) -> [Order; 10];
     fn get_asks(&self) -> [Order; 10];
-    fn get_volume_at_limit(&self, price: f64) -> u64;
+    // Every order resting on one side, hidden ones included, best priority first
+    fn get_orders(&self, side: Side) -> Vec<Order>;
+    // Visible volume aggregated by price on one side, best first, for up to `depth` levels
+    fn get_levels(&self, side: Side, depth: usize) -> Vec<PriceLevel>;
+    fn get_volume_at_limit(&self, price: Price) -> u64;
+    // Visible volume resting at `price` on one side of the book
+    fn get_level_volume(&self, side: Side, price: Price) -> u64;
+    // Volume resting on the opposite side at prices an aggressor at `price` would trade with
+    fn get_crossing_volume(&self, aggressor: Side, price: Price) -> u64;
+    // Best resting price on one side, hidden orders included
+    fn get_best_price(&self, side: Side) -> Option<Price>;
     fn get_order_by_id(&self, id: u64) -> Result<Order, &'static str>;
-    fn get_market_price(&self) -> Result<f64, &'static str>;
+    fn get_market_price(&self) -> Result<Price, &'static str>;
 }
 
-#[derive(Clone, Serialize, Deserialize, Copy)]
+#[derive(Clone, Serialize, Deserialize, Copy, Debug, PartialEq)]
 pub struct Order {
     pub id: u64,
     pub side: Side,
     pub quantity: u64,
-    pub price: f64,
+    pub price: Price,
+    pub filled_quantity: u64,
+    pub leaves_quantity: u64,
+    pub order_type: OrderType,
+    pub time_in_force: TimeInForce,
+    // Unix timestamp (seconds) after which a GTD or DAY order expires
+    pub expire_at: Option<i64>,
+    // Last trade price that activates a stop or stop-limit order
+    pub stop_price: Option<Price>,
+    pub trailing_offset: Option<TrailingOffset>,
+    // Iceberg orders only show this much of their leaves quantity on the book
+    pub display_quantity: Option<u64>,
+    pub post_only: bool,
+    // Hidden orders rest and trade as normal but are left out of market data
+    pub hidden: bool,
 }
 
 impl Order {
-    pub fn new(id: u64, side: Side, quantity: u64, price: f64) -> Order {
+    pub fn new(id: u64, side: Side, quantity: u64, price: Price) -> Order {
         Order {
             id,
             side,
             quantity,
             price,
+            filled_quantity: 0,
+            leaves_quantity: quantity,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            expire_at: None,
+            stop_price: None,
+            trailing_offset: None,
+            display_quantity: None,
+            post_only: false,
+            hidden: false,
+        }
+    }
+
+    pub fn fill(&mut self, quantity: u64) {
+        self.filled_quantity += quantity;
+        self.leaves_quantity -= quantity;
+    }
+
+    // Changes the total order quantity, keeping what has already been filled
+    pub fn amend(&mut self, quantity: u64, price: Price) -> Result<(), &'static str> {
+        if quantity <= self.filled_quantity {
+            return Err("Failure: quantity already filled");
+        }
+        self.quantity = quantity;
+        self.leaves_quantity = quantity - self.filled_quantity;
+        self.price = price;
+        Ok(())
+    }
+
+    // The visible slice of an iceberg order, taken from its leaves quantity
+    pub fn peak(&self) -> Order {
+        let quantity = match self.display_quantity {
+            Some(display_quantity) => display_quantity.min(self.leaves_quantity),
+            None => self.leaves_quantity,
+        };
+        Order {
+            quantity,
+            filled_quantity: 0,
+            leaves_quantity: quantity,
+            ..*self
         }
     }
 }
@@ -52,31 +120,240 @@ impl Default for Order {
             id: 0,
             side: Side::Buy,
             quantity: 0,
-            price: 0.0,
+            price: Price::ZERO,
+            filled_quantity: 0,
+            leaves_quantity: 0,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            expire_at: None,
+            stop_price: None,
+            trailing_offset: None,
+            display_quantity: None,
+            post_only: false,
+            hidden: false,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OrderType {
+    #[default]
+    Limit,
+    Market,
+    Stop,
+    StopLimit,
+    TrailingStop,
+}
+
+impl OrderType {
+    pub fn is_stop(&self) -> bool {
+        matches!(
+            self,
+            OrderType::Stop | OrderType::StopLimit | OrderType::TrailingStop
+        )
+    }
+}
+
+// How far a trailing stop's trigger sits behind the best price seen since entry
+#[derive(Clone, Serialize, Deserialize, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrailingOffset {
+    Amount(Price),
+    Percent(Price),
+}
+
+// Largest trailing amount accepted, in whole units, far beyond any price traded at
+const MAX_TRAILING_AMOUNT: i64 = 1_000_000_000;
+
+impl TrailingOffset {
+    pub fn is_valid(&self) -> bool {
+        match self {
+            TrailingOffset::Amount(amount) => {
+                *amount > Price::ZERO && *amount <= Price::from_units(MAX_TRAILING_AMOUNT)
+            }
+            TrailingOffset::Percent(percent) => {
+                *percent > Price::ZERO && *percent < Price::from_units(100)
+            }
         }
     }
+
+    // Sell stops trail below the best price and buy stops above it, rounded away
+    // from the market onto the tick grid. None if that is beyond the range of a price.
+    pub fn trigger(&self, side: Side, best_price: Price, tick_size: Price) -> Option<Price> {
+        let offset = match self {
+            TrailingOffset::Amount(amount) => *amount,
+            TrailingOffset::Percent(percent) => best_price.percent(*percent),
+        };
+        match side {
+            Side::Sell => Some(best_price.checked_sub(offset)?.floor_to_tick(tick_size)),
+            Side::Buy => {
+                let price = best_price.checked_add(offset)?;
+                // Leaves room to round up onto the tick grid
+                price.checked_add(tick_size)?;
+                Some(price.ceil_to_tick(tick_size))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TimeInForce {
+    #[default]
+    Gtc,
+    Ioc,
+    Fok,
+    Gtd,
+    Day,
+}
+
+impl fmt::Display for TimeInForce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeInForce::Gtc => write!(f, "GTC"),
+            TimeInForce::Ioc => write!(f, "IOC"),
+            TimeInForce::Fok => write!(f, "FOK"),
+            TimeInForce::Gtd => write!(f, "GTD"),
+            TimeInForce::Day => write!(f, "DAY"),
+        }
+    }
+}
+
+// What to do with the part of a market order the book could not fill
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarketRemainder {
+    Cancel,
+    Limit,
+}
+
+// What to do with a post-only order that would trade on entry
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PostOnlyPolicy {
+    Reject,
+    // Move it one tick behind the best opposite price
+    Reprice,
 }
 
-#[derive(Clone, Serialize, Deserialize, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NewOrder {
+    pub side: Side,
+    pub quantity: u64,
+    pub price: Price,
+    pub order_type: OrderType,
+    pub time_in_force: TimeInForce,
+    pub expire_at: Option<i64>,
+    pub stop_price: Option<Price>,
+    pub trailing_offset: Option<TrailingOffset>,
+    pub display_quantity: Option<u64>,
+    pub post_only: bool,
+    pub hidden: bool,
+}
+
+impl NewOrder {
+    pub fn limit(side: Side, quantity: u64, price: Price) -> NewOrder {
+        NewOrder {
+            side,
+            quantity,
+            price,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            expire_at: None,
+            stop_price: None,
+            trailing_offset: None,
+            display_quantity: None,
+            post_only: false,
+            hidden: false,
+        }
+    }
+
+    pub fn market(side: Side, quantity: u64) -> NewOrder {
+        NewOrder {
+            side,
+            quantity,
+            price: Price::ZERO,
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            expire_at: None,
+            stop_price: None,
+            trailing_offset: None,
+            display_quantity: None,
+            post_only: false,
+            hidden: false,
+        }
+    }
+
+    // A limit order showing at most `display_quantity` on the book at a time
+    pub fn iceberg(side: Side, quantity: u64, price: Price, display_quantity: u64) -> NewOrder {
+        NewOrder {
+            display_quantity: Some(display_quantity),
+            ..NewOrder::limit(side, quantity, price)
+        }
+    }
+
+    // Enters a market order once the last trade reaches the stop price
+    pub fn stop(side: Side, quantity: u64, stop_price: Price) -> NewOrder {
+        NewOrder {
+            order_type: OrderType::Stop,
+            stop_price: Some(stop_price),
+            ..NewOrder::market(side, quantity)
+        }
+    }
+
+    // Enters a limit order at `price` once the last trade reaches the stop price
+    pub fn stop_limit(side: Side, quantity: u64, stop_price: Price, price: Price) -> NewOrder {
+        NewOrder {
+            order_type: OrderType::StopLimit,
+            stop_price: Some(stop_price),
+            ..NewOrder::limit(side, quantity, price)
+        }
+    }
+
+    // Enters a market order once the price retraces by `offset` from its best since entry
+    pub fn trailing_stop(side: Side, quantity: u64, offset: TrailingOffset) -> NewOrder {
+        NewOrder {
+            order_type: OrderType::TrailingStop,
+            trailing_offset: Some(offset),
+            ..NewOrder::market(side, quantity)
+        }
+    }
+
+    pub fn with_time_in_force(
+        mut self,
+        time_in_force: TimeInForce,
+        expire_at: Option<i64>,
+    ) -> NewOrder {
+        self.time_in_force = time_in_force;
+        self.expire_at = expire_at;
+        self
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Copy, Debug, PartialEq)]
+pub struct Fill {
+    pub bid_id: u64,
+    pub ask_id: u64,
+    pub quantity: u64,
+    pub price: Price,
+}
+
+// Visible volume resting at one price
+#[derive(Clone, Serialize, Deserialize, Copy, Debug, PartialEq)]
+pub struct PriceLevel {
+    pub price: Price,
+    pub volume: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Copy, PartialEq, Eq, Debug)]
 pub enum Side {
     Buy,
     Sell,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
-pub struct PriceDatum {
-    pub open: f64,
-    pub close: f64,
-    pub high: f64,
-    pub low: f64,
-    pub timestamp: u64,
-}
-
-impl ToString for Side {
-    fn to_string(&self) -> String {
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Side::Buy => "Buy".to_string(),
-            Side::Sell => "Sell".to_string(),
+            Side::Buy => write!(f, "Buy"),
+            Side::Sell => write!(f, "Sell"),
         }
     }
 }
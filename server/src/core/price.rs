@@ -0,0 +1,190 @@
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::ops::{Add, Sub};
+use std::str::FromStr;
+
+const DECIMALS: u32 = 8;
+const SCALE: i64 = 10_i64.pow(DECIMALS);
+
+// Fixed-point price stored as an integer number of 1e-8 units, so that price
+// levels compare and hash exactly. Valid prices for an instrument are whole
+// multiples of its tick size.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Price(i64);
+
+impl Price {
+    pub const ZERO: Price = Price(0);
+    pub const MAX: Price = Price(i64::MAX);
+
+    pub fn from_raw(raw: i64) -> Price {
+        Price(raw)
+    }
+
+    pub fn from_units(units: i64) -> Price {
+        Price(units * SCALE)
+    }
+
+    pub fn raw(self) -> i64 {
+        self.0
+    }
+
+    // Only for values that arrive as floating point (e.g. JSON numbers)
+    pub fn from_f64(value: f64) -> Result<Price, &'static str> {
+        let scaled = (value * SCALE as f64).round();
+        if !scaled.is_finite() || scaled.abs() >= i64::MAX as f64 {
+            return Err("Invalid price");
+        }
+        Ok(Price(scaled as i64))
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / SCALE as f64
+    }
+
+    pub fn is_on_tick(self, tick_size: Price) -> bool {
+        tick_size.0 > 0 && self.0 % tick_size.0 == 0
+    }
+
+    pub fn ticks(self, tick_size: Price) -> i64 {
+        self.0 / tick_size.0
+    }
+
+    pub fn midpoint(self, other: Price) -> Price {
+        Price(((self.0 as i128 + other.0 as i128) / 2) as i64)
+    }
+
+    // `percent` of this price, e.g. 1.5 for 1.5%
+    pub fn percent(self, percent: Price) -> Price {
+        Price((self.0 as i128 * percent.0 as i128 / (100 * SCALE as i128)) as i64)
+    }
+
+    pub fn checked_add(self, other: Price) -> Option<Price> {
+        self.0.checked_add(other.0).map(Price)
+    }
+
+    pub fn checked_sub(self, other: Price) -> Option<Price> {
+        self.0.checked_sub(other.0).map(Price)
+    }
+
+    pub fn floor_to_tick(self, tick_size: Price) -> Price {
+        Price(self.0.div_euclid(tick_size.0) * tick_size.0)
+    }
+
+    pub fn ceil_to_tick(self, tick_size: Price) -> Price {
+        Price(-(-self.0).div_euclid(tick_size.0) * tick_size.0)
+    }
+}
+
+impl Add for Price {
+    type Output = Price;
+
+    fn add(self, other: Price) -> Price {
+        Price(self.0 + other.0)
+    }
+}
+
+impl Sub for Price {
+    type Output = Price;
+
+    fn sub(self, other: Price) -> Price {
+        Price(self.0 - other.0)
+    }
+}
+
+impl FromStr for Price {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Price, &'static str> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return Err("Invalid price");
+        }
+        if fraction.len() > DECIMALS as usize
+            || !whole
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return Err("Invalid price");
+        }
+
+        let whole: i64 = if whole.is_empty() {
+            0
+        } else {
+            whole.parse().map_err(|_| "Invalid price")?
+        };
+        let fraction: i64 = if fraction.is_empty() {
+            0
+        } else {
+            let padded = format!("{:0<width$}", fraction, width = DECIMALS as usize);
+            padded.parse().map_err(|_| "Invalid price")?
+        };
+        let raw = whole
+            .checked_mul(SCALE)
+            .and_then(|w| w.checked_add(fraction))
+            .ok_or("Invalid price")?;
+        Ok(Price(if negative { -raw } else { raw }))
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        let whole = abs / SCALE as u64;
+        let fraction = abs % SCALE as u64;
+        if fraction == 0 {
+            return write!(f, "{}{}", sign, whole);
+        }
+        let fraction = format!("{:0width$}", fraction, width = DECIMALS as usize);
+        write!(f, "{}{}.{}", sign, whole, fraction.trim_end_matches('0'))
+    }
+}
+
+impl Serialize for Price {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.to_f64())
+    }
+}
+
+impl<'de> Deserialize<'de> for Price {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Price, D::Error> {
+        deserializer.deserialize_any(PriceVisitor)
+    }
+}
+
+struct PriceVisitor;
+
+impl<'de> Visitor<'de> for PriceVisitor {
+    type Value = Price;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a price as a number or decimal string")
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Price, E> {
+        Price::from_f64(value).map_err(E::custom)
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Price, E> {
+        value
+            .checked_mul(SCALE)
+            .map(Price)
+            .ok_or_else(|| E::custom("Invalid price"))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Price, E> {
+        i64::try_from(value)
+            .map_err(|_| E::custom("Invalid price"))
+            .and_then(|value| self.visit_i64(value))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Price, E> {
+        value.parse().map_err(E::custom)
+    }
+}
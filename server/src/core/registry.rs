@@ -0,0 +1,260 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use time::OffsetDateTime;
+
+use super::order_book::{Fill, Order};
+use super::price::Price;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum OrderStatus {
+    New,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Replaced,
+    Rejected,
+    Expired,
+}
+
+impl OrderStatus {
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            OrderStatus::Filled
+                | OrderStatus::Cancelled
+                | OrderStatus::Rejected
+                | OrderStatus::Expired
+        )
+    }
+
+    pub fn can_transition_to(&self, next: OrderStatus) -> bool {
+        match self {
+            OrderStatus::New | OrderStatus::PartiallyFilled | OrderStatus::Replaced => matches!(
+                next,
+                OrderStatus::PartiallyFilled
+                    | OrderStatus::Filled
+                    | OrderStatus::Cancelled
+                    | OrderStatus::Replaced
+                    | OrderStatus::Expired
+            ),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderStatus::New => write!(f, "New"),
+            OrderStatus::PartiallyFilled => write!(f, "PartiallyFilled"),
+            OrderStatus::Filled => write!(f, "Filled"),
+            OrderStatus::Cancelled => write!(f, "Cancelled"),
+            OrderStatus::Replaced => write!(f, "Replaced"),
+            OrderStatus::Rejected => write!(f, "Rejected"),
+            OrderStatus::Expired => write!(f, "Expired"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrderRecord {
+    pub order: Order,
+    pub status: OrderStatus,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+    pub fills: Vec<Fill>,
+}
+
+impl OrderRecord {
+    pub fn average_price(&self) -> Option<Price> {
+        let quantity: u64 = self.fills.iter().map(|fill| fill.quantity).sum();
+        if quantity == 0 {
+            return None;
+        }
+        let notional: i128 = self
+            .fills
+            .iter()
+            .map(|fill| fill.price.raw() as i128 * fill.quantity as i128)
+            .sum();
+        Some(Price::from_raw((notional / quantity as i128) as i64))
+    }
+}
+
+// Finished orders kept for status queries, older ones are forgotten
+const FINISHED_KEPT: usize = 10_000;
+
+// Every working order, and the latest finished ones, by id with their lifecycle state
+pub struct OrderRegistry {
+    orders: HashMap<u64, OrderRecord>,
+    // Orders in a terminal state once they have been reported, and their ids in the order
+    // they finished
+    finished: HashMap<u64, OrderRecord>,
+    finish_order: VecDeque<u64>,
+    // Ids of records changed since they were last taken, in order of first change
+    changed: Vec<u64>,
+}
+
+impl Default for OrderRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OrderRegistry {
+    pub fn new() -> OrderRegistry {
+        OrderRegistry {
+            orders: HashMap::new(),
+            finished: HashMap::new(),
+            finish_order: VecDeque::new(),
+            changed: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, id: u64) -> Option<&OrderRecord> {
+        self.orders.get(&id).or_else(|| self.finished.get(&id))
+    }
+
+    pub fn get_mut(&mut self, id: u64) -> Option<&mut OrderRecord> {
+        match self.orders.get_mut(&id) {
+            Some(record) => Some(record),
+            None => self.finished.get_mut(&id),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.orders.len() + self.finished.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty() && self.finished.is_empty()
+    }
+
+    // Every working record in id order, then the finished ones in the order they finished
+    pub fn records(&self) -> Vec<&OrderRecord> {
+        let mut records: Vec<&OrderRecord> = self.orders.values().collect();
+        records.sort_unstable_by_key(|record| record.order.id);
+        records.extend(self.finish_order.iter().map(|id| &self.finished[id]));
+        records
+    }
+
+    // Puts back a record exactly as it was, without reporting it as changed
+    pub fn restore(&mut self, record: OrderRecord) {
+        if record.status.is_terminal() {
+            self.finish(record);
+        } else {
+            self.orders.insert(record.order.id, record);
+        }
+    }
+
+    fn finish(&mut self, record: OrderRecord) {
+        self.finish_order.push_back(record.order.id);
+        self.finished.insert(record.order.id, record);
+    }
+
+    // Moves the orders among `ids` that have reached a terminal state out of the working
+    // set. Only orders finished by earlier calls are forgotten to make room, so these stay
+    // until they have been reported.
+    pub fn retire(&mut self, ids: &[u64]) {
+        while self.finish_order.len() > FINISHED_KEPT {
+            if let Some(id) = self.finish_order.pop_front() {
+                self.finished.remove(&id);
+            }
+        }
+        for id in ids {
+            if !self.orders.get(id).is_some_and(|r| r.status.is_terminal()) {
+                continue;
+            }
+            if let Some(record) = self.orders.remove(id) {
+                self.finish(record);
+            }
+        }
+    }
+
+    pub fn take_changed(&mut self) -> Vec<u64> {
+        std::mem::take(&mut self.changed)
+    }
+
+    pub fn insert(&mut self, order: Order, status: OrderStatus, now: OffsetDateTime) {
+        if !self.changed.contains(&order.id) {
+            self.changed.push(order.id);
+        }
+        self.orders.insert(
+            order.id,
+            OrderRecord {
+                order,
+                status,
+                created_at: now,
+                updated_at: now,
+                fills: Vec::new(),
+            },
+        );
+    }
+
+    pub fn transition(
+        &mut self,
+        id: u64,
+        status: OrderStatus,
+        now: OffsetDateTime,
+    ) -> Result<&OrderRecord, &'static str> {
+        let record = self.orders.get_mut(&id).ok_or("Order not found")?;
+        if !record.status.can_transition_to(status) {
+            return Err("Invalid order status transition");
+        }
+        if !self.changed.contains(&id) {
+            self.changed.push(id);
+        }
+        if status.is_terminal() {
+            record.order.leaves_quantity = 0;
+        }
+        record.status = status;
+        record.updated_at = now;
+        Ok(record)
+    }
+
+    pub fn replace(
+        &mut self,
+        id: u64,
+        quantity: u64,
+        price: Price,
+        now: OffsetDateTime,
+    ) -> Result<&OrderRecord, &'static str> {
+        let record = self.orders.get_mut(&id).ok_or("Order not found")?;
+        if !record.status.can_transition_to(OrderStatus::Replaced) {
+            return Err("Invalid order status transition");
+        }
+        record.order.amend(quantity, price)?;
+        if !self.changed.contains(&id) {
+            self.changed.push(id);
+        }
+        // An amend leaves what has already been filled in place
+        record.status = if record.order.filled_quantity > 0 {
+            OrderStatus::PartiallyFilled
+        } else {
+            OrderStatus::Replaced
+        };
+        record.updated_at = now;
+        Ok(record)
+    }
+
+    pub fn apply_fill(
+        &mut self,
+        id: u64,
+        fill: Fill,
+        now: OffsetDateTime,
+    ) -> Result<&OrderRecord, &'static str> {
+        let record = self.orders.get_mut(&id).ok_or("Order not found")?;
+        if !self.changed.contains(&id) {
+            self.changed.push(id);
+        }
+        record.order.fill(fill.quantity);
+        record.status = if record.order.leaves_quantity == 0 {
+            OrderStatus::Filled
+        } else {
+            OrderStatus::PartiallyFilled
+        };
+        record.fills.push(fill);
+        record.updated_at = now;
+        Ok(record)
+    }
+}
@@ -0,0 +1,76 @@
+use std::io;
+use time::OffsetDateTime;
+
+use super::engine::Engine;
+use super::journal::{read_journal, JournalConfig, JournalEntry};
+use super::order_book::OrderBook;
+use super::sequencer::Event;
+use super::snapshot::read_snapshot;
+
+// A journaled command whose events came out differently when it was applied again
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    pub seq: u64,
+    pub recorded: Vec<Event>,
+    pub replayed: Vec<Event>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Recovery {
+    // Sequence number of the snapshot recovery started from
+    pub snapshot: Option<u64>,
+    pub commands: usize,
+    pub last_seq: u64,
+    pub divergences: Vec<Divergence>,
+}
+
+// Re-applies journaled commands to an engine at the times they were first applied,
+// returning every command whose events differ from the recorded ones
+pub fn replay<T: OrderBook>(engine: &mut Engine<T>, entries: &[JournalEntry]) -> Vec<Divergence> {
+    let mut divergences = Vec::new();
+    for entry in entries {
+        let now = OffsetDateTime::from_unix_timestamp_nanos(entry.timestamp as i128);
+        let replayed = engine.apply(entry.command, now);
+        if replayed != entry.events {
+            divergences.push(Divergence {
+                seq: entry.seq,
+                recorded: entry.events.clone(),
+                replayed,
+            });
+        }
+    }
+    divergences
+}
+
+// Rebuilds an engine from its latest snapshot and the journaled commands applied after
+// it, ids and trades included
+pub fn recover<T: OrderBook>(
+    engine: &mut Engine<T>,
+    config: &JournalConfig,
+) -> io::Result<Recovery> {
+    let symbol = engine.instrument.symbol.clone();
+    let snapshot = read_snapshot(&config.snapshot_path(&symbol))?;
+    let snapshot_seq = snapshot.as_ref().map(|snapshot| snapshot.seq);
+    if let Some(snapshot) = snapshot {
+        if snapshot.symbol != symbol {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Snapshot is for another symbol",
+            ));
+        }
+        engine.restore(snapshot);
+    }
+    let mut entries = read_journal(&config.path(&symbol))?;
+    // A crash between writing a snapshot and truncating the journal leaves entries it covers
+    entries.retain(|entry| Some(entry.seq) > snapshot_seq);
+    Ok(Recovery {
+        snapshot: snapshot_seq,
+        commands: entries.len(),
+        last_seq: entries
+            .last()
+            .map(|entry| entry.seq)
+            .or(snapshot_seq)
+            .unwrap_or(0),
+        divergences: replay(engine, &entries),
+    })
+}
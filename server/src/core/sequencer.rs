@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+
+use super::order_book::{Fill, NewOrder, Side};
+use super::price::Price;
+use super::registry::OrderStatus;
+
+// Inbound requests that change engine state, applied in sequence number order
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Command {
+    New(NewOrder),
+    Amend {
+        id: u64,
+        quantity: u64,
+        price: Price,
+    },
+    Cancel {
+        id: u64,
+    },
+    // Expires GTD and DAY orders due at `now` (unix seconds)
+    Expire {
+        now: i64,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderAction {
+    // A displayed order joined the back of the queue at its price
+    Add,
+    // Its visible quantity went down in place, keeping its time priority
+    Modify,
+    Cancel,
+    Execute,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Event {
+    Accepted {
+        id: u64,
+    },
+    Rejected {
+        reason: String,
+    },
+    OrderUpdate {
+        id: u64,
+        status: OrderStatus,
+        filled_quantity: u64,
+        leaves_quantity: u64,
+    },
+    Fill(Fill),
+    StopMoved {
+        id: u64,
+        stop_price: Price,
+    },
+    // New visible volume at a price level, zero once the level is gone
+    BookDelta {
+        side: Side,
+        price: Price,
+        volume: u64,
+    },
+    // A displayed order entering, changing on or leaving the book, in the order it
+    // happened. `remaining` is its visible quantity afterwards and `quantity` the amount
+    // traded by an execution.
+    BookOrder {
+        action: OrderAction,
+        id: u64,
+        side: Side,
+        price: Price,
+        quantity: u64,
+        remaining: u64,
+    },
+}
+
+// Every event produced by one command, stamped with that command's sequence number
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Output {
+    pub seq: u64,
+    pub symbol: String,
+    pub events: Vec<Event>,
+}
+
+// Numbers the commands applied to one instrument's engine. It is owned by that engine's
+// matching thread, the only writer, so each symbol's outputs are numbered 1, 2, 3... in
+// the order they were applied, and a consumer that skips a number has missed an output.
+#[derive(Default)]
+pub struct Sequencer {
+    last: u64,
+}
+
+impl Sequencer {
+    pub fn new() -> Sequencer {
+        Sequencer::starting_after(0)
+    }
+
+    pub fn starting_after(seq: u64) -> Sequencer {
+        Sequencer { last: seq }
+    }
+
+    pub fn next_seq(&mut self) -> u64 {
+        self.last += 1;
+        self.last
+    }
+
+    pub fn last(&self) -> u64 {
+        self.last
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use time::OffsetDateTime;
+use tokio::sync::oneshot;
+
+use super::engine::Engine;
+use super::instrument::{Instrument, InstrumentRegistry};
+use super::journal::{Journal, JournalConfig, JournalEntry};
+use super::order_book::OrderBook;
+use super::sequencer::{Command, Output, Sequencer};
+use super::snapshot::write_snapshot;
+use super::tape::{read_tape, Trade, TradeQuery, TradeTape};
+
+// Requests waiting on a matching thread beyond this are turned away rather than queued
+const RING_CAPACITY: usize = 4096;
+
+// Sees the output of every command a matching thread applies, on that thread
+pub type Listener<T> = Arc<dyn Fn(&Engine<T>, &Output) + Send + Sync>;
+
+// Everything a matching thread owns
+struct Shard<T: OrderBook> {
+    engine: Engine<T>,
+    sequencer: Sequencer,
+    listener: Listener<T>,
+    journal: Option<Journal>,
+    snapshot_path: Option<PathBuf>,
+    tape: Option<TradeTape>,
+    // Set once a command could not be journaled, after which the thread takes no more jobs
+    stopped: bool,
+}
+
+impl<T: OrderBook> Shard<T> {
+    // Sequences and applies a command, journaling it with its events before anyone sees them
+    fn apply(&mut self, command: Command) -> Result<Output, &'static str> {
+        let seq = self.sequencer.next_seq();
+        let now = OffsetDateTime::now_utc();
+        let output = Output {
+            seq,
+            symbol: self.engine.instrument.symbol.clone(),
+            events: self.engine.apply(command, now),
+        };
+        if let Some(journal) = &mut self.journal {
+            let entry = JournalEntry {
+                seq,
+                timestamp: now.unix_timestamp_nanos() as i64,
+                command,
+                events: output.events.clone(),
+            };
+            if let Err(e) = journal.append(&entry) {
+                // The engine is now ahead of what a replay would rebuild, so the command is
+                // neither published nor acknowledged and nothing further is applied
+                eprintln!("Error journaling command {}, stopping engine: {}", seq, e);
+                self.stopped = true;
+                return Err("Journal write failed");
+            }
+        }
+        self.sync_tape();
+        (self.listener)(&self.engine, &output);
+        Ok(output)
+    }
+
+    // Appends any trades the tape is missing. Those lost in a crash are made again when
+    // the journal is replayed, and written here once the engine is running.
+    fn sync_tape(&mut self) {
+        if let Some(tape) = &mut self.tape {
+            if let Err(e) = tape.sync(&self.engine.trades) {
+                eprintln!("Error writing trade tape: {}", e);
+            }
+        }
+    }
+
+    // Writes the engine's state to disk and starts its journal afresh, returning the
+    // sequence number the snapshot is as of
+    fn snapshot(&mut self) -> Result<u64, String> {
+        let path = self
+            .snapshot_path
+            .as_ref()
+            .ok_or("Journaling is disabled")?;
+        // Commands for this engine are only ever sequenced on this thread, so every
+        // number handed out so far has already been applied
+        let seq = self.sequencer.last();
+        write_snapshot(path, &self.engine.snapshot(seq)).map_err(|e| e.to_string())?;
+        if let Some(journal) = &mut self.journal {
+            journal.truncate().map_err(|e| e.to_string())?;
+        }
+        Ok(seq)
+    }
+}
+
+type Job<T> = Box<dyn FnOnce(&mut Shard<T>) + Send>;
+
+// Gateway side of a matching thread that owns a single engine. Requests are closures
+// run against the engine in the order they arrive, with results sent back to the caller.
+// The inbound queue is a bounded ring buffer (std's array-backed `sync_channel`).
+pub struct EngineHandle<T: OrderBook> {
+    pub instrument: Instrument,
+    sender: mpsc::SyncSender<Job<T>>,
+    tape_path: Option<PathBuf>,
+}
+
+impl<T: OrderBook> Clone for EngineHandle<T> {
+    fn clone(&self) -> Self {
+        EngineHandle {
+            instrument: self.instrument.clone(),
+            sender: self.sender.clone(),
+            tape_path: self.tape_path.clone(),
+        }
+    }
+}
+
+impl<T: OrderBook + Send + 'static> EngineHandle<T> {
+    pub fn spawn(
+        engine: Engine<T>,
+        sequencer: Sequencer,
+        listener: Listener<T>,
+        journals: Option<&JournalConfig>,
+    ) -> io::Result<EngineHandle<T>> {
+        let instrument = engine.instrument.clone();
+        let journal = match journals {
+            Some(config) => Some(Journal::open(
+                &config.path(&instrument.symbol),
+                config.policy,
+            )?),
+            None => None,
+        };
+        let tape_path = journals.map(|config| config.tape_path(&instrument.symbol));
+        let tape = match &tape_path {
+            Some(path) => Some(TradeTape::open(path)?),
+            None => None,
+        };
+        let mut shard = Shard {
+            engine,
+            sequencer,
+            listener,
+            journal,
+            snapshot_path: journals.map(|config| config.snapshot_path(&instrument.symbol)),
+            tape,
+            stopped: false,
+        };
+        shard.sync_tape();
+        let (sender, receiver) = mpsc::sync_channel::<Job<T>>(RING_CAPACITY);
+        thread::Builder::new()
+            .name(format!("engine-{}", instrument.symbol))
+            .spawn(move || {
+                // Runs until every handle has been dropped or the journal fails
+                for job in receiver {
+                    job(&mut shard);
+                    if shard.stopped {
+                        break;
+                    }
+                }
+            })
+            .expect("Failed to spawn engine thread");
+        Ok(EngineHandle {
+            instrument,
+            sender,
+            tape_path,
+        })
+    }
+
+    fn enqueue<R: Send + 'static>(
+        &self,
+        job: impl FnOnce(&mut Shard<T>) -> R + Send + 'static,
+    ) -> Result<oneshot::Receiver<R>, &'static str> {
+        let (reply, response) = oneshot::channel();
+        self.sender
+            .try_send(Box::new(move |shard| {
+                let _ = reply.send(job(shard));
+            }))
+            .map_err(|e| match e {
+                mpsc::TrySendError::Full(_) => "Engine busy",
+                mpsc::TrySendError::Disconnected(_) => "Engine stopped",
+            })?;
+        Ok(response)
+    }
+
+    // Runs a read-only or otherwise unsequenced request against the engine
+    pub async fn execute<R, F>(&self, f: F) -> Result<R, &'static str>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Engine<T>) -> R + Send + 'static,
+    {
+        let response = self.enqueue(move |shard| f(&mut shard.engine))?;
+        response.await.map_err(|_| "Engine stopped")
+    }
+
+    // Runs a query along with the sequence number it is as of: outputs for this engine
+    // up to that number are reflected in the result and every later one is not
+    pub async fn execute_as_of<R, F>(&self, f: F) -> Result<(u64, R), &'static str>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Engine<T>) -> R + Send + 'static,
+    {
+        let response = self.enqueue(move |shard| (shard.sequencer.last(), f(&mut shard.engine)))?;
+        response.await.map_err(|_| "Engine stopped")
+    }
+
+    // Sequences and applies a command, then builds the caller's reply from its output
+    pub async fn submit<R, F>(&self, command: Command, f: F) -> Result<R, &'static str>
+    where
+        R: Send + 'static,
+        F: FnOnce(&Engine<T>, &Output) -> R + Send + 'static,
+    {
+        let response = self.enqueue(move |shard| {
+            let output = shard.apply(command)?;
+            Ok(f(&shard.engine, &output))
+        })?;
+        response.await.map_err(|_| "Engine stopped")?
+    }
+
+    // A page of trades, read from the tape when there is one so that trades no longer
+    // kept in memory are included, and from memory otherwise
+    pub async fn trades(&self, query: TradeQuery) -> Result<Vec<Trade>, String> {
+        match self.tape_path.clone() {
+            Some(path) => tokio::task::spawn_blocking(move || read_tape(&path, &query))
+                .await
+                .map_err(|e| e.to_string())?
+                .map_err(|e| e.to_string()),
+            None => Ok(self
+                .execute(move |engine| engine.trades.query(&query))
+                .await?),
+        }
+    }
+
+    // Snapshots the engine between commands, returning the sequence number it is as of
+    pub async fn snapshot(&self) -> Result<u64, String> {
+        let response = self.enqueue(|shard| shard.snapshot())?;
+        response.await.map_err(|_| "Engine stopped")?
+    }
+}
+
+// One matching thread per listed symbol, so instruments never wait on each other
+pub struct EngineShards<T: OrderBook> {
+    handles: BTreeMap<String, EngineHandle<T>>,
+}
+
+impl<T: OrderBook + Send + 'static> EngineShards<T> {
+    // Each symbol's sequence numbers carry on from its entry in `last_seqs`, e.g. as
+    // recovered from its journal, and start from 1 for any symbol not in it
+    pub fn spawn(
+        instruments: InstrumentRegistry<T>,
+        last_seqs: &BTreeMap<String, u64>,
+        listener: Listener<T>,
+        journals: Option<&JournalConfig>,
+    ) -> io::Result<EngineShards<T>> {
+        let mut handles = BTreeMap::new();
+        for engine in instruments.into_engines() {
+            let symbol = engine.instrument.symbol.clone();
+            let last_seq = last_seqs.get(&symbol).copied().unwrap_or(0);
+            let sequencer = Sequencer::starting_after(last_seq);
+            let handle = EngineHandle::spawn(engine, sequencer, listener.clone(), journals)?;
+            handles.insert(symbol, handle);
+        }
+        Ok(EngineShards { handles })
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&EngineHandle<T>> {
+        self.handles.get(symbol)
+    }
+
+    pub fn handles(&self) -> impl Iterator<Item = &EngineHandle<T>> {
+        self.handles.values()
+    }
+
+    pub fn instruments(&self) -> Vec<&Instrument> {
+        self.handles().map(|handle| &handle.instrument).collect()
+    }
+}
@@ -0,0 +1,373 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use time::OffsetDateTime;
+
+use super::candles::{CandleHistory, Interval, PriceDatum};
+use super::order_book::{Fill, Order, OrderType, Side, TimeInForce, TrailingOffset};
+use super::price::Price;
+use super::registry::{OrderRecord, OrderStatus};
+use super::tape::Trade;
+
+const MAGIC: &[u8; 4] = b"TSNP";
+// Bumped whenever the layout below changes, so an old snapshot is refused rather than misread
+pub const SNAPSHOT_VERSION: u16 = 2;
+
+// Point-in-time copy of an engine: every resting and untriggered order in priority
+// order, the order registry, the id counter, the trades kept in memory and the candle
+// history. `seq` is the last sequence number whose command the copy includes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub seq: u64,
+    pub symbol: String,
+    pub id: u64,
+    pub bids: Vec<Order>,
+    pub asks: Vec<Order>,
+    // Untriggered stops, with the best price seen by trailing ones
+    pub stops: Vec<(Order, Option<Price>)>,
+    pub records: Vec<OrderRecord>,
+    pub trades: Vec<Trade>,
+    pub expiries: Vec<(i64, u64)>,
+    pub candles: CandleHistory,
+}
+
+// Writes a snapshot as the magic bytes, a little-endian u16 version and CRC-32 of the
+// payload, then the payload. It goes to a temporary file first so that a crash never
+// leaves a half written snapshot in place of the last good one.
+pub fn write_snapshot(path: &Path, snapshot: &Snapshot) -> io::Result<()> {
+    let mut payload = Encoder::default();
+    payload.snapshot(snapshot);
+    let tmp = path.with_extension("tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(MAGIC)?;
+    file.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+    file.write_all(&crc32fast::hash(&payload.0).to_le_bytes())?;
+    file.write_all(&payload.0)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+// Reads a snapshot, or nothing if none has been written yet
+pub fn read_snapshot(path: &Path) -> io::Result<Option<Snapshot>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if bytes.len() < 10 || &bytes[..4] != MAGIC {
+        return Err(invalid("Not a snapshot file"));
+    }
+    if u16::from_le_bytes([bytes[4], bytes[5]]) != SNAPSHOT_VERSION {
+        return Err(invalid("Unsupported snapshot version"));
+    }
+    let crc = u32::from_le_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]);
+    let payload = &bytes[10..];
+    if crc32fast::hash(payload) != crc {
+        return Err(invalid("Snapshot checksum mismatch"));
+    }
+    let mut decoder = Decoder(payload);
+    let snapshot = decoder.snapshot()?;
+    if !decoder.0.is_empty() {
+        return Err(invalid("Trailing bytes in snapshot"));
+    }
+    Ok(Some(snapshot))
+}
+
+fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Fixed width little-endian fields, with lists and strings prefixed by their length
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i64(&mut self, value: i64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn price(&mut self, value: Price) {
+        self.i64(value.raw());
+    }
+
+    fn time(&mut self, value: OffsetDateTime) {
+        self.i64(value.unix_timestamp_nanos() as i64);
+    }
+
+    fn option<V>(&mut self, value: Option<V>, f: impl FnOnce(&mut Self, V)) {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                f(self, value);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn list<V>(&mut self, values: &[V], mut f: impl FnMut(&mut Self, &V)) {
+        self.u64(values.len() as u64);
+        for value in values {
+            f(self, value);
+        }
+    }
+
+    fn order(&mut self, order: &Order) {
+        self.u64(order.id);
+        self.u8(order.side as u8);
+        self.u64(order.quantity);
+        self.price(order.price);
+        self.u64(order.filled_quantity);
+        self.u64(order.leaves_quantity);
+        self.u8(order.order_type as u8);
+        self.u8(order.time_in_force as u8);
+        self.option(order.expire_at, Self::i64);
+        self.option(order.stop_price, Self::price);
+        self.option(order.trailing_offset, |e, offset| match offset {
+            TrailingOffset::Amount(amount) => {
+                e.u8(0);
+                e.price(amount);
+            }
+            TrailingOffset::Percent(percent) => {
+                e.u8(1);
+                e.price(percent);
+            }
+        });
+        self.option(order.display_quantity, Self::u64);
+        self.bool(order.post_only);
+        self.bool(order.hidden);
+    }
+
+    fn record(&mut self, record: &OrderRecord) {
+        self.order(&record.order);
+        self.u8(record.status as u8);
+        self.time(record.created_at);
+        self.time(record.updated_at);
+        self.list(&record.fills, |e, fill| {
+            e.u64(fill.bid_id);
+            e.u64(fill.ask_id);
+            e.u64(fill.quantity);
+            e.price(fill.price);
+        });
+    }
+
+    fn snapshot(&mut self, snapshot: &Snapshot) {
+        self.u64(snapshot.seq);
+        self.list(snapshot.symbol.as_bytes(), |e, byte| e.u8(*byte));
+        self.u64(snapshot.id);
+        self.list(&snapshot.bids, Self::order);
+        self.list(&snapshot.asks, Self::order);
+        self.list(&snapshot.stops, |e, (order, peak)| {
+            e.order(order);
+            e.option(*peak, Self::price);
+        });
+        self.list(&snapshot.records, Self::record);
+        self.list(&snapshot.trades, |e, trade| {
+            e.u64(trade.id);
+            e.u64(trade.bid_id);
+            e.u64(trade.ask_id);
+            e.u8(trade.aggressor as u8);
+            e.u64(trade.quantity);
+            e.price(trade.price);
+            e.i64(trade.timestamp);
+        });
+        self.list(&snapshot.expiries, |e, &(expire_at, id)| {
+            e.i64(expire_at);
+            e.u64(id);
+        });
+        for interval in Interval::ALL {
+            let bars = snapshot.candles.get(interval, i64::MIN, i64::MAX);
+            self.list(&bars, |e, bar| {
+                e.price(bar.open);
+                e.price(bar.close);
+                e.price(bar.high);
+                e.price(bar.low);
+                e.u64(bar.volume);
+                e.i64(bar.timestamp);
+            });
+        }
+    }
+}
+
+struct Decoder<'a>(&'a [u8]);
+
+impl Decoder<'_> {
+    fn take<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        if self.0.len() < N {
+            return Err(invalid("Snapshot is truncated"));
+        }
+        let (bytes, rest) = self.0.split_at(N);
+        self.0 = rest;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    fn i64(&mut self) -> io::Result<i64> {
+        self.take().map(i64::from_le_bytes)
+    }
+
+    fn bool(&mut self) -> io::Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid("Invalid flag in snapshot")),
+        }
+    }
+
+    fn price(&mut self) -> io::Result<Price> {
+        self.i64().map(Price::from_raw)
+    }
+
+    fn time(&mut self) -> io::Result<OffsetDateTime> {
+        self.i64()
+            .map(|nanos| OffsetDateTime::from_unix_timestamp_nanos(nanos as i128))
+    }
+
+    fn option<V>(&mut self, f: impl FnOnce(&mut Self) -> io::Result<V>) -> io::Result<Option<V>> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => f(self).map(Some),
+            _ => Err(invalid("Invalid option in snapshot")),
+        }
+    }
+
+    fn list<V>(&mut self, mut f: impl FnMut(&mut Self) -> io::Result<V>) -> io::Result<Vec<V>> {
+        let len = self.u64()?;
+        // Every entry takes at least a byte, which bounds what a corrupt length can allocate
+        if len > self.0.len() as u64 {
+            return Err(invalid("Snapshot is truncated"));
+        }
+        (0..len).map(|_| f(self)).collect()
+    }
+
+    fn side(&mut self) -> io::Result<Side> {
+        match self.u8()? {
+            0 => Ok(Side::Buy),
+            1 => Ok(Side::Sell),
+            _ => Err(invalid("Invalid side in snapshot")),
+        }
+    }
+
+    fn order(&mut self) -> io::Result<Order> {
+        Ok(Order {
+            id: self.u64()?,
+            side: self.side()?,
+            quantity: self.u64()?,
+            price: self.price()?,
+            filled_quantity: self.u64()?,
+            leaves_quantity: self.u64()?,
+            order_type: match self.u8()? {
+                0 => OrderType::Limit,
+                1 => OrderType::Market,
+                2 => OrderType::Stop,
+                3 => OrderType::StopLimit,
+                4 => OrderType::TrailingStop,
+                _ => return Err(invalid("Invalid order type in snapshot")),
+            },
+            time_in_force: match self.u8()? {
+                0 => TimeInForce::Gtc,
+                1 => TimeInForce::Ioc,
+                2 => TimeInForce::Fok,
+                3 => TimeInForce::Gtd,
+                4 => TimeInForce::Day,
+                _ => return Err(invalid("Invalid time in force in snapshot")),
+            },
+            expire_at: self.option(Self::i64)?,
+            stop_price: self.option(Self::price)?,
+            trailing_offset: self.option(|d| match d.u8()? {
+                0 => d.price().map(TrailingOffset::Amount),
+                1 => d.price().map(TrailingOffset::Percent),
+                _ => Err(invalid("Invalid trailing offset in snapshot")),
+            })?,
+            display_quantity: self.option(Self::u64)?,
+            post_only: self.bool()?,
+            hidden: self.bool()?,
+        })
+    }
+
+    fn bar(&mut self) -> io::Result<PriceDatum> {
+        Ok(PriceDatum {
+            open: self.price()?,
+            close: self.price()?,
+            high: self.price()?,
+            low: self.price()?,
+            volume: self.u64()?,
+            timestamp: self.i64()?,
+        })
+    }
+
+    fn record(&mut self) -> io::Result<OrderRecord> {
+        Ok(OrderRecord {
+            order: self.order()?,
+            status: match self.u8()? {
+                0 => OrderStatus::New,
+                1 => OrderStatus::PartiallyFilled,
+                2 => OrderStatus::Filled,
+                3 => OrderStatus::Cancelled,
+                4 => OrderStatus::Replaced,
+                5 => OrderStatus::Rejected,
+                6 => OrderStatus::Expired,
+                _ => return Err(invalid("Invalid order status in snapshot")),
+            },
+            created_at: self.time()?,
+            updated_at: self.time()?,
+            fills: self.list(|d| {
+                Ok(Fill {
+                    bid_id: d.u64()?,
+                    ask_id: d.u64()?,
+                    quantity: d.u64()?,
+                    price: d.price()?,
+                })
+            })?,
+        })
+    }
+
+    fn snapshot(&mut self) -> io::Result<Snapshot> {
+        Ok(Snapshot {
+            seq: self.u64()?,
+            symbol: String::from_utf8(self.list(Self::u8)?)
+                .map_err(|_| invalid("Invalid symbol in snapshot"))?,
+            id: self.u64()?,
+            bids: self.list(Self::order)?,
+            asks: self.list(Self::order)?,
+            stops: self.list(|d| Ok((d.order()?, d.option(Self::price)?)))?,
+            records: self.list(Self::record)?,
+            trades: self.list(|d| {
+                Ok(Trade {
+                    id: d.u64()?,
+                    bid_id: d.u64()?,
+                    ask_id: d.u64()?,
+                    aggressor: d.side()?,
+                    quantity: d.u64()?,
+                    price: d.price()?,
+                    timestamp: d.i64()?,
+                })
+            })?,
+            expiries: self.list(|d| Ok((d.i64()?, d.u64()?)))?,
+            candles: CandleHistory::from_bars([
+                self.list(Self::bar)?,
+                self.list(Self::bar)?,
+                self.list(Self::bar)?,
+                self.list(Self::bar)?,
+            ]),
+        })
+    }
+}
@@ -0,0 +1,145 @@
+use super::order_book::{Order, Side};
+use super::price::Price;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+// Untriggered stop, stop-limit and trailing stop orders keyed by trigger price. Buy
+// stops trigger once the market trades at or above their stop price, sell stops at
+// or below it.
+pub struct StopBook {
+    buys: BTreeMap<Price, VecDeque<u64>>,
+    sells: BTreeMap<Price, VecDeque<u64>>,
+    orders: HashMap<u64, Order>,
+    // Best trade price seen by each trailing stop since it was entered
+    peaks: HashMap<u64, Price>,
+}
+
+impl Default for StopBook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StopBook {
+    pub fn new() -> StopBook {
+        StopBook {
+            buys: BTreeMap::new(),
+            sells: BTreeMap::new(),
+            orders: HashMap::new(),
+            peaks: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Order> {
+        self.orders.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: u64) -> Option<&mut Order> {
+        self.orders.get_mut(&id)
+    }
+
+    // Every stop in trigger order within each side, buys first, with the best price
+    // seen by trailing stops
+    pub fn entries(&self) -> Vec<(Order, Option<Price>)> {
+        self.buys
+            .values()
+            .chain(self.sells.values().rev())
+            .flatten()
+            .map(|id| (self.orders[id], self.peaks.get(id).copied()))
+            .collect()
+    }
+
+    pub fn insert(&mut self, order: Order) {
+        let stop_price = match order.stop_price {
+            Some(stop_price) => stop_price,
+            None => return,
+        };
+        let levels = match order.side {
+            Side::Buy => &mut self.buys,
+            Side::Sell => &mut self.sells,
+        };
+        levels.entry(stop_price).or_default().push_back(order.id);
+        self.orders.insert(order.id, order);
+    }
+
+    pub fn insert_trailing(&mut self, order: Order, best_price: Price) {
+        self.peaks.insert(order.id, best_price);
+        self.insert(order);
+    }
+
+    pub fn remove(&mut self, id: u64) -> Option<Order> {
+        let order = self.orders.remove(&id)?;
+        self.peaks.remove(&id);
+        let stop_price = order.stop_price?;
+        let levels = match order.side {
+            Side::Buy => &mut self.buys,
+            Side::Sell => &mut self.sells,
+        };
+        if let Some(level) = levels.get_mut(&stop_price) {
+            level.retain(|&other| other != id);
+            if level.is_empty() {
+                levels.remove(&stop_price);
+            }
+        }
+        Some(order)
+    }
+
+    // Follows a trade at `price` with every trailing stop it improves on, returning
+    // the stops whose trigger moved along with their new trigger price
+    pub fn ratchet(&mut self, price: Price, tick_size: Price) -> Vec<(u64, Price)> {
+        let mut ids: Vec<u64> = self.peaks.keys().copied().collect();
+        ids.sort_unstable();
+        let mut moved = Vec::new();
+        for id in ids {
+            let (mut order, peak) = (self.orders[&id], self.peaks[&id]);
+            let (offset, stop_price) = match (order.trailing_offset, order.stop_price) {
+                (Some(offset), Some(stop_price)) => (offset, stop_price),
+                _ => continue,
+            };
+            let improves = match order.side {
+                Side::Sell => price > peak,
+                Side::Buy => price < peak,
+            };
+            if !improves {
+                continue;
+            }
+            let trigger = match offset.trigger(order.side, price, tick_size) {
+                Some(trigger) => trigger,
+                None => continue,
+            };
+            let tightens = match order.side {
+                Side::Sell => trigger > stop_price,
+                Side::Buy => trigger < stop_price,
+            };
+            if tightens {
+                self.remove(id);
+                order.stop_price = Some(trigger);
+                self.insert_trailing(order, price);
+                moved.push((id, trigger));
+            } else {
+                self.peaks.insert(id, price);
+            }
+        }
+        moved
+    }
+
+    // Takes the next order triggered by a trade at `last_price`: stops nearest the
+    // previous price go first, and within a stop price the earliest order
+    pub fn pop_triggered(&mut self, last_price: Price) -> Option<Order> {
+        let id = match self.buys.first_key_value() {
+            Some((&stop_price, level)) if stop_price <= last_price => level.front().copied(),
+            _ => match self.sells.last_key_value() {
+                Some((&stop_price, level)) if stop_price >= last_price => level.front().copied(),
+                _ => None,
+            },
+        }?;
+        self.remove(id)
+    }
+}
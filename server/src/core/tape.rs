@@ -0,0 +1,232 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::order_book::Side;
+use super::price::Price;
+
+// Trades each engine keeps in memory, older ones are only on the tape
+const TRADES_KEPT: usize = 10_000;
+// Largest page of trades a query returns
+pub const MAX_PAGE: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Trade {
+    // Numbered from 1 per instrument, in the order trades happened
+    pub id: u64,
+    pub bid_id: u64,
+    pub ask_id: u64,
+    // Side of the incoming order that took liquidity
+    pub aggressor: Side,
+    pub quantity: u64,
+    pub price: Price,
+    // Unix time in nanoseconds
+    pub timestamp: i64,
+}
+
+// One page of trades: those after the trade id `after` with timestamps between `from`
+// and `to` inclusive, oldest first. The last id returned is the `after` of the next page.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TradeQuery {
+    pub after: u64,
+    pub from: i64,
+    pub to: i64,
+    pub limit: usize,
+}
+
+impl Default for TradeQuery {
+    fn default() -> Self {
+        TradeQuery {
+            after: 0,
+            from: i64::MIN,
+            to: i64::MAX,
+            limit: MAX_PAGE,
+        }
+    }
+}
+
+impl TradeQuery {
+    fn matches(&self, trade: &Trade) -> bool {
+        trade.id > self.after && trade.timestamp >= self.from && trade.timestamp <= self.to
+    }
+
+    fn page(&self, trades: impl Iterator<Item = Trade>) -> Vec<Trade> {
+        trades
+            // Trades are in time order, so nothing past the end of the range can match
+            .take_while(|trade| trade.timestamp <= self.to)
+            .filter(|trade| self.matches(trade))
+            .take(self.limit.min(MAX_PAGE))
+            .collect()
+    }
+}
+
+// The latest trades, dropping the oldest once full
+#[derive(Clone, Debug, PartialEq)]
+pub struct TradeRing {
+    trades: VecDeque<Trade>,
+}
+
+impl Default for TradeRing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TradeRing {
+    pub fn new() -> TradeRing {
+        TradeRing {
+            trades: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, trade: Trade) {
+        if self.trades.len() == TRADES_KEPT {
+            self.trades.pop_front();
+        }
+        self.trades.push_back(trade);
+    }
+
+    pub fn last(&self) -> Option<&Trade> {
+        self.trades.back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.trades.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trades.is_empty()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Trade> {
+        self.trades.iter()
+    }
+
+    // Trades with ids above `id`
+    pub fn after(&self, id: u64) -> impl Iterator<Item = &Trade> {
+        let start = self.trades.partition_point(|trade| trade.id <= id);
+        self.trades.range(start..)
+    }
+
+    pub fn query(&self, query: &TradeQuery) -> Vec<Trade> {
+        query.page(self.after(query.after).copied())
+    }
+}
+
+// Every trade an engine has made, appended to `<dir>/<SYMBOL>.tape` as fixed size
+// little-endian records so that pages can be found by seeking rather than scanning
+pub struct TradeTape {
+    file: File,
+    last_id: u64,
+}
+
+const RECORD_SIZE: u64 = 49;
+
+impl TradeTape {
+    pub fn open(path: &Path) -> io::Result<TradeTape> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        // A record torn by a crash is dropped, and written again from memory
+        let len = file.metadata()?.len();
+        file.set_len(len - len % RECORD_SIZE)?;
+        let records = len / RECORD_SIZE;
+        let last_id = match records {
+            0 => 0,
+            _ => read_record(&mut file, records - 1)?.id,
+        };
+        Ok(TradeTape { file, last_id })
+    }
+
+    pub fn last_id(&self) -> u64 {
+        self.last_id
+    }
+
+    // Appends the trades in a ring that the tape does not have yet
+    pub fn sync(&mut self, trades: &TradeRing) -> io::Result<()> {
+        let mut bytes = Vec::new();
+        for trade in trades.after(self.last_id) {
+            encode(trade, &mut bytes);
+        }
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        self.file.write_all(&bytes)?;
+        self.last_id = trades.last().map_or(self.last_id, |trade| trade.id);
+        Ok(())
+    }
+}
+
+// Reads a page of trades from a tape, which may be being appended to meanwhile
+pub fn read_tape(path: &Path, query: &TradeQuery) -> io::Result<Vec<Trade>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let records = file.metadata()?.len() / RECORD_SIZE;
+    // Both ids and timestamps only ever increase along the tape
+    let mut low = 0;
+    let mut high = records;
+    while low < high {
+        let mid = (low + high) / 2;
+        let trade = read_record(&mut file, mid)?;
+        if trade.id <= query.after || trade.timestamp < query.from {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    let mut trades = Vec::new();
+    let mut index = low;
+    while index < records && trades.len() < query.limit.min(MAX_PAGE) {
+        let trade = read_record(&mut file, index)?;
+        if trade.timestamp > query.to {
+            break;
+        }
+        if query.matches(&trade) {
+            trades.push(trade);
+        }
+        index += 1;
+    }
+    Ok(trades)
+}
+
+fn encode(trade: &Trade, bytes: &mut Vec<u8>) {
+    bytes.extend_from_slice(&trade.id.to_le_bytes());
+    bytes.extend_from_slice(&trade.bid_id.to_le_bytes());
+    bytes.extend_from_slice(&trade.ask_id.to_le_bytes());
+    bytes.push(trade.aggressor as u8);
+    bytes.extend_from_slice(&trade.quantity.to_le_bytes());
+    bytes.extend_from_slice(&trade.price.raw().to_le_bytes());
+    bytes.extend_from_slice(&trade.timestamp.to_le_bytes());
+}
+
+fn read_record(file: &mut File, index: u64) -> io::Result<Trade> {
+    let mut record = [0u8; RECORD_SIZE as usize];
+    file.seek(SeekFrom::Start(index * RECORD_SIZE))?;
+    file.read_exact(&mut record)?;
+    let u64_at = |at: usize| u64::from_le_bytes(record[at..at + 8].try_into().unwrap());
+    Ok(Trade {
+        id: u64_at(0),
+        bid_id: u64_at(8),
+        ask_id: u64_at(16),
+        aggressor: match record[24] {
+            0 => Side::Buy,
+            1 => Side::Sell,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid side on tape",
+                ))
+            }
+        },
+        quantity: u64_at(25),
+        price: Price::from_raw(u64_at(33) as i64),
+        timestamp: u64_at(41) as i64,
+    })
+}
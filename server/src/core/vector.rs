@@ -1,4 +1,5 @@
-use super::order_book::{Order, OrderBook};
+use super::order_book::{Order, OrderBook, PriceLevel, Side};
+use super::price::Price;
 use std::cmp::min;
 
 pub struct VectorOrderBook {
@@ -9,15 +10,48 @@ pub struct VectorOrderBook {
 impl VectorOrderBook {
     fn sort_bids(&mut self) {
         self.bids
-            .sort_unstable_by(|a, b| b.price.partial_cmp(&a.price).unwrap());
+            .sort_by_key(|order| std::cmp::Reverse(order.price));
     }
 
     fn sort_asks(&mut self) {
-        self.asks
-            .sort_unstable_by(|a, b| a.price.partial_cmp(&b.price).unwrap());
+        self.asks.sort_by_key(|order| order.price);
     }
 }
 
+fn visible_orders(orders: &[Order]) -> [Order; 10] {
+    let mut array: [Order; 10] = Default::default();
+    for (slot, order) in array
+        .iter_mut()
+        .zip(orders.iter().filter(|order| !order.hidden))
+    {
+        *slot = *order;
+    }
+    array
+}
+
+// Reducing quantity at the same price keeps time priority, any other amend moves the order
+// to the back of the queue at its new price once the side is sorted again
+fn amend_order(
+    orders: &mut Vec<Order>,
+    id: u64,
+    quantity: u64,
+    price: Price,
+) -> Result<(), &'static str> {
+    let index = match orders.iter().position(|x| x.id == id) {
+        Some(index) => index,
+        None => return Err("Failure: order not found"),
+    };
+    let mut amended = orders[index];
+    amended.amend(quantity, price)?;
+    if price == orders[index].price && amended.leaves_quantity <= orders[index].leaves_quantity {
+        orders[index] = amended;
+    } else {
+        orders.remove(index);
+        orders.push(amended);
+    }
+    Ok(())
+}
+
 impl OrderBook for VectorOrderBook {
     fn new() -> VectorOrderBook {
         VectorOrderBook {
@@ -47,7 +81,7 @@ impl OrderBook for VectorOrderBook {
     }
 
     fn remove_ask_order(&mut self, id: u64) -> Result<&'static str, &'static str> {
-        match self.bids.iter().position(|x| x.id == id) {
+        match self.asks.iter().position(|x| x.id == id) {
             Some(i) => {
                 self.asks.remove(i);
                 Ok("Success: order removed")
@@ -60,51 +94,50 @@ impl OrderBook for VectorOrderBook {
         &mut self,
         id: u64,
         quantity: u64,
-        price: f64,
+        price: Price,
     ) -> Result<&'static str, &'static str> {
-        let index = self.bids.iter().position(|x| x.id == id);
-        if let Some(index) = index {
-            self.bids[index].quantity = quantity;
-            self.bids[index].price = price;
-            self.sort_bids();
-            Ok("Success: order amended")
-        } else {
-            Err("Failure: order not found")
-        }
+        amend_order(&mut self.bids, id, quantity, price)?;
+        self.sort_bids();
+        Ok("Success: order amended")
     }
 
     fn amend_ask_order(
         &mut self,
         id: u64,
         quantity: u64,
-        price: f64,
+        price: Price,
     ) -> Result<&'static str, &'static str> {
-        let index = self.asks.iter().position(|x| x.id == id);
-        if let Some(index) = index {
-            self.asks[index].quantity = quantity;
-            self.asks[index].price = price;
-            self.sort_asks();
-            Ok("Success: order amended")
-        } else {
-            Err("Failure: order not found")
-        }
+        amend_order(&mut self.asks, id, quantity, price)?;
+        self.sort_asks();
+        Ok("Success: order amended")
     }
 
-    fn match_orders(&mut self) -> Result<(u64, u64, u64, f64), &'static str> {
+    fn match_orders(&mut self, aggressor: Side) -> Result<(u64, u64, u64, Price), &'static str> {
         if self.bids.is_empty() || self.asks.is_empty() {
             return Err("No matching orders");
         }
 
-        let best_bid = &self.bids[0];
-        let best_ask = &self.asks[0];
+        let best_bid = &mut self.bids[0];
+        let best_ask = &mut self.asks[0];
 
         if best_bid.price >= best_ask.price {
-            let match_quantity = min(best_bid.quantity, best_ask.quantity);
-            let match_price = best_ask.price;
+            let match_quantity = min(best_bid.leaves_quantity, best_ask.leaves_quantity);
+            let match_price = match aggressor {
+                Side::Buy => best_ask.price,
+                Side::Sell => best_bid.price,
+            };
             let best_bid_id = best_bid.id;
             let best_ask_id = best_ask.id;
-            self.bids.remove(0);
-            self.asks.remove(0);
+            best_bid.fill(match_quantity);
+            best_ask.fill(match_quantity);
+
+            // Residual quantity stays at the front of the book
+            if best_bid.leaves_quantity == 0 {
+                self.bids.remove(0);
+            }
+            if best_ask.leaves_quantity == 0 {
+                self.asks.remove(0);
+            }
             Ok((best_bid_id, best_ask_id, match_quantity, match_price))
         } else {
             Err("No matching orders")
@@ -114,59 +147,120 @@ impl OrderBook for VectorOrderBook {
     fn display(&self) {
         println!("Bids:");
         for order in &self.bids {
-            println!("  {} @ {} ({})", order.quantity, order.price, order.id);
+            println!(
+                "  {} @ {} ({})",
+                order.leaves_quantity, order.price, order.id
+            );
         }
 
         println!("Asks:");
         for order in &self.asks {
-            println!("  {} @ {} ({})", order.quantity, order.price, order.id);
+            println!(
+                "  {} @ {} ({})",
+                order.leaves_quantity, order.price, order.id
+            );
         }
     }
 
     fn get_bids(&self) -> [Order; 10] {
-        let n = std::cmp::min(10, self.bids.len());
-        let mut array: [Order; 10] = Default::default();
-        for i in 0..n {
-            array[i] = self.bids[i].clone();
-        }
-        array
+        visible_orders(&self.bids)
     }
 
     fn get_asks(&self) -> [Order; 10] {
-        let n = std::cmp::min(10, self.asks.len());
-        let mut array: [Order; 10] = Default::default();
-        for i in 0..n {
-            array[i] = self.asks[i].clone();
+        visible_orders(&self.asks)
+    }
+
+    fn get_orders(&self, side: Side) -> Vec<Order> {
+        match side {
+            Side::Buy => self.bids.clone(),
+            Side::Sell => self.asks.clone(),
+        }
+    }
+
+    fn get_levels(&self, side: Side, depth: usize) -> Vec<PriceLevel> {
+        let orders = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        let mut levels: Vec<PriceLevel> = Vec::new();
+        for order in orders.iter().filter(|order| !order.hidden) {
+            if let Some(level) = levels.last_mut().filter(|level| level.price == order.price) {
+                level.volume += order.leaves_quantity;
+            } else if levels.len() == depth {
+                break;
+            } else {
+                levels.push(PriceLevel {
+                    price: order.price,
+                    volume: order.leaves_quantity,
+                });
+            }
         }
-        array
+        levels
     }
 
-    fn get_volume_at_limit(&self, price: f64) -> u64 {
+    fn get_volume_at_limit(&self, price: Price) -> u64 {
         let mut volume = 0;
         for order in self.bids.iter().chain(self.asks.iter()) {
-            if order.price == price {
-                volume += order.quantity;
+            if order.price == price && !order.hidden {
+                volume += order.leaves_quantity;
             }
         }
         volume
     }
 
+    fn get_level_volume(&self, side: Side, price: Price) -> u64 {
+        let orders = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        orders
+            .iter()
+            .filter(|order| order.price == price && !order.hidden)
+            .map(|order| order.leaves_quantity)
+            .sum()
+    }
+
+    fn get_crossing_volume(&self, aggressor: Side, price: Price) -> u64 {
+        match aggressor {
+            Side::Buy => self
+                .asks
+                .iter()
+                .take_while(|order| order.price <= price)
+                .map(|order| order.leaves_quantity)
+                .sum(),
+            Side::Sell => self
+                .bids
+                .iter()
+                .take_while(|order| order.price >= price)
+                .map(|order| order.leaves_quantity)
+                .sum(),
+        }
+    }
+
+    fn get_best_price(&self, side: Side) -> Option<Price> {
+        let orders = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        orders.first().map(|order| order.price)
+    }
+
     fn get_order_by_id(&self, id: u64) -> Result<Order, &'static str> {
         for order in self.bids.iter().chain(self.asks.iter()) {
             if order.id == id {
-                return Ok(order.clone());
+                return Ok(*order);
             }
         }
         Err("Order not found")
     }
 
-    fn get_market_price(&self) -> Result<f64, &'static str> {
+    fn get_market_price(&self) -> Result<Price, &'static str> {
         if self.bids.is_empty() || self.asks.is_empty() {
             Err("No price available")
         } else {
             let best_bid = &self.bids[0];
             let best_ask = &self.asks[0];
-            Ok((best_bid.price + best_ask.price) / 2.0)
+            Ok(best_bid.price.midpoint(best_ask.price))
         }
     }
 }
@@ -1,8 +1,9 @@
-mod core;
-mod net;
-
-use net::main::start;
+use server::net::main::{orders, replay, start};
 
 fn main() {
-    start();
+    match std::env::args().nth(1).as_deref() {
+        Some("replay") => replay(),
+        Some("orders") => orders(),
+        _ => start(),
+    }
 }
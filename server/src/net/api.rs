@@ -1,5 +1,6 @@
 use axum::{
-    extract::{Path, State},
+    extract::{FromRef, Path, Query, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
     routing::{get, post},
     Json, Router,
 };
@@ -7,22 +8,68 @@ use serde::Deserialize;
 use serde_json::{json, Value};
 use std::{collections::HashMap, time::Duration};
 use std::{sync::Arc, time::SystemTime};
-use tokio::sync::Mutex;
 use tokio::time::sleep;
 use tower_http::cors::CorsLayer;
 
-use crate::core::{engine::Engine, order_book::Side, vector::VectorOrderBook};
+use super::stream::{get_executions, get_stream};
+use crate::core::{
+    bus::EventBus,
+    candles::Interval,
+    order_book::{NewOrder, OrderBook, OrderType, Side, TimeInForce, TrailingOffset},
+    price::Price,
+    sequencer::{Command, Event, Output},
+    shard::EngineShards,
+    tape::{TradeQuery, MAX_PAGE},
+};
+
+// The engines, and the bus their outputs are pushed to streaming clients on
+pub struct ApiState<T: OrderBook> {
+    pub shards: Arc<EngineShards<T>>,
+    pub bus: Arc<EventBus>,
+    // Bearer token the admin routes require, which are disabled without one
+    pub admin_token: Option<Arc<str>>,
+}
+
+impl<T: OrderBook> Clone for ApiState<T> {
+    fn clone(&self) -> Self {
+        ApiState {
+            shards: self.shards.clone(),
+            bus: self.bus.clone(),
+            admin_token: self.admin_token.clone(),
+        }
+    }
+}
+
+impl<T: OrderBook> FromRef<ApiState<T>> for Arc<EngineShards<T>> {
+    fn from_ref(state: &ApiState<T>) -> Self {
+        state.shards.clone()
+    }
+}
 
-pub async fn start_api(n: Arc<Mutex<Engine<VectorOrderBook>>>) {
+pub async fn start_api<T: OrderBook + Send + 'static>(
+    n: Arc<EngineShards<T>>,
+    bus: Arc<EventBus>,
+    admin_token: Option<String>,
+) {
     let app = Router::new()
         .route("/test", get(get_test))
-        .route("/book", get(get_book))
-        .route("/price", get(get_price))
-        .route("/trades", get(get_trades))
-        .route("/order", post(post_order))
-        .route("/report/:id", get(get_report))
+        .route("/instruments", get(get_instruments::<T>))
+        .route("/book/:symbol", get(get_book::<T>))
+        .route("/price/:symbol", get(get_price::<T>))
+        .route("/candles/:symbol", get(get_candles::<T>))
+        .route("/trades/:symbol", get(get_trades::<T>))
+        .route("/tape/:symbol", get(get_tape::<T>))
+        .route("/order", post(post_order::<T>))
+        .route("/report/:symbol/:id", get(get_report::<T>))
+        .route("/admin/snapshot", post(post_snapshot::<T>))
+        .route("/executions/:symbol", get(get_executions::<T>))
+        .route("/ws", get(get_stream::<T>))
         .layer(CorsLayer::permissive())
-        .with_state(n);
+        .with_state(ApiState {
+            shards: n,
+            bus,
+            admin_token: admin_token.map(Arc::from),
+        });
     println!("API listening on port 3000");
 
     axum::Server::bind(&"0.0.0.0:3000".parse().unwrap())
@@ -35,8 +82,23 @@ async fn get_test() -> &'static str {
     "Hello, World!"
 }
 
-async fn get_book(State(n): State<Arc<Mutex<Engine<VectorOrderBook>>>>) -> Json<Value> {
-    let (bids, asks) = n.lock().await.get_book();
+async fn get_instruments<T: OrderBook + Send + 'static>(
+    State(n): State<Arc<EngineShards<T>>>,
+) -> Json<Value> {
+    Json(json!(n.instruments()))
+}
+
+async fn get_book<T: OrderBook + Send + 'static>(
+    State(n): State<Arc<EngineShards<T>>>,
+    Path(symbol): Path<String>,
+) -> Json<Value> {
+    let (bids, asks) = match n.get(&symbol) {
+        Some(handle) => match handle.execute(|engine| engine.get_book()).await {
+            Ok(book) => book,
+            Err(error) => return Json(json!({ "error": error })),
+        },
+        None => return Json(json!({ "error": "Unknown symbol" })),
+    };
     let book = json!({
         "bids": bids,
         "asks": asks
@@ -44,17 +106,26 @@ async fn get_book(State(n): State<Arc<Mutex<Engine<VectorOrderBook>>>>) -> Json<
     Json(book)
 }
 
-async fn get_price(State(n): State<Arc<Mutex<Engine<VectorOrderBook>>>>) -> Json<Value> {
-    let mut open = 0.0;
-    let mut close = 0.0;
-    let mut high = 0.0;
-    let mut low = f64::MAX;
+async fn get_price<T: OrderBook + Send + 'static>(
+    State(n): State<Arc<EngineShards<T>>>,
+    Path(symbol): Path<String>,
+) -> Json<Value> {
+    let mut open = Price::ZERO;
+    let mut close = Price::ZERO;
+    let mut high = Price::ZERO;
+    let mut low = Price::MAX;
     let mut timestamp = 0;
+    let handle = match n.get(&symbol) {
+        Some(handle) => handle,
+        None => return Json(json!({ "error": "Unknown symbol" })),
+    };
 
     for i in 0..10 {
         {
-            let n_lock = n.lock().await;
-            let price = n_lock.get_market_price();
+            let price = handle
+                .execute(|engine| engine.get_market_price())
+                .await
+                .and_then(|price| price);
             match price {
                 Ok(price) => {
                     if i == 0 {
@@ -62,8 +133,8 @@ async fn get_price(State(n): State<Arc<Mutex<Engine<VectorOrderBook>>>>) -> Json
                         high = price;
                         low = price;
                     } else {
-                        high = f64::max(price, high);
-                        low = f64::min(price, low);
+                        high = high.max(price);
+                        low = low.min(price);
                     }
                     if i == 9 {
                         close = price;
@@ -84,46 +155,289 @@ async fn get_price(State(n): State<Arc<Mutex<Engine<VectorOrderBook>>>>) -> Json
     Json(json!({ "open": open, "close": close, "high": high, "low": low, "timestamp": timestamp }))
 }
 
-async fn get_trades(State(n): State<Arc<Mutex<Engine<VectorOrderBook>>>>) -> Json<Value> {
-    let trades = n.lock().await.get_trade_history();
-    Json(json!(trades))
+// Trades aggregated into OHLCV bars, e.g. `/candles/XYZ?interval=1m&from=&to=`
+// with `from` and `to` the unix times in milliseconds the bars start between
+async fn get_candles<T: OrderBook + Send + 'static>(
+    State(n): State<Arc<EngineShards<T>>>,
+    Path(symbol): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Json<Value> {
+    let handle = match n.get(&symbol) {
+        Some(handle) => handle,
+        None => return Json(json!({ "error": "Unknown symbol" })),
+    };
+    let interval: Interval = match params.get("interval").map(|s| s.parse()) {
+        Some(Ok(interval)) => interval,
+        Some(Err(e)) => return Json(json!({ "error": e })),
+        None => return Json(json!({ "error": "Missing interval" })),
+    };
+    let bound = |name, default| match params.get(name) {
+        Some(s) if !s.is_empty() => s.parse::<i64>().map_err(|_| "Invalid time range"),
+        _ => Ok(default),
+    };
+    let (from, to) = match (bound("from", i64::MIN), bound("to", i64::MAX)) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(e), _) | (_, Err(e)) => return Json(json!({ "error": e })),
+    };
+    match handle
+        .execute(move |engine| engine.candles.get(interval, from, to))
+        .await
+    {
+        Ok(candles) => Json(json!(candles)),
+        Err(e) => Json(json!({ "error": e })),
+    }
+}
+
+async fn get_trades<T: OrderBook + Send + 'static>(
+    State(n): State<Arc<EngineShards<T>>>,
+    Path(symbol): Path<String>,
+) -> Json<Value> {
+    match n.get(&symbol) {
+        Some(handle) => match handle.execute(|engine| engine.get_trade_history()).await {
+            Ok(trades) => Json(json!(trades)),
+            Err(error) => Json(json!({ "error": error })),
+        },
+        None => Json(json!({ "error": "Unknown symbol" })),
+    }
+}
+
+// Pages through every trade, e.g. `/tape/XYZ?after=100&limit=50` or `/tape/XYZ?from=&to=`
+// with `from` and `to` in unix nanoseconds. `next` is the `after` of the following page,
+// or null once there are no more.
+async fn get_tape<T: OrderBook + Send + 'static>(
+    State(n): State<Arc<EngineShards<T>>>,
+    Path(symbol): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Json<Value> {
+    let handle = match n.get(&symbol) {
+        Some(handle) => handle,
+        None => return Json(json!({ "error": "Unknown symbol" })),
+    };
+    let query = match trade_query(&params) {
+        Ok(query) => query,
+        Err(e) => return Json(json!({ "error": e })),
+    };
+    match handle.trades(query).await {
+        Ok(trades) => {
+            let next = trades
+                .last()
+                .filter(|_| trades.len() == query.limit)
+                .map(|trade| trade.id);
+            Json(json!({ "trades": trades, "next": next }))
+        }
+        Err(e) => Json(json!({ "error": e })),
+    }
+}
+
+fn trade_query(params: &HashMap<String, String>) -> Result<TradeQuery, &'static str> {
+    let mut query = TradeQuery::default();
+    let param = |name| params.get(name).filter(|s| !s.is_empty());
+    if let Some(after) = param("after") {
+        query.after = after.parse().map_err(|_| "Invalid trade id")?;
+    }
+    if let Some(from) = param("from") {
+        query.from = from.parse().map_err(|_| "Invalid time range")?;
+    }
+    if let Some(to) = param("to") {
+        query.to = to.parse().map_err(|_| "Invalid time range")?;
+    }
+    if let Some(limit) = param("limit") {
+        query.limit = match limit.parse() {
+            Ok(limit) if limit > 0 && limit <= MAX_PAGE => limit,
+            _ => return Err("Limit must be between 1 and 1000"),
+        };
+    }
+    Ok(query)
 }
 
 #[derive(Deserialize)]
 struct OrderRequest {
+    pub symbol: String,
     pub side: Side,
     pub quantity: u64,
-    pub price: f64,
+    #[serde(default)]
+    pub price: Option<Price>,
+    #[serde(rename = "type", default)]
+    pub order_type: OrderType,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    #[serde(default)]
+    pub expire_at: Option<i64>,
+    #[serde(default)]
+    pub stop_price: Option<Price>,
+    #[serde(default)]
+    pub trailing_offset: Option<TrailingOffset>,
+    #[serde(default)]
+    pub display_quantity: Option<u64>,
+    #[serde(default)]
+    pub post_only: bool,
+    #[serde(default)]
+    pub hidden: bool,
 }
 
-async fn post_order(
-    State(n): State<Arc<Mutex<Engine<VectorOrderBook>>>>,
+async fn post_order<T: OrderBook + Send + 'static>(
+    State(n): State<Arc<EngineShards<T>>>,
     axum::extract::Json(data): axum::extract::Json<serde_json::Value>,
 ) -> Json<Value> {
-    let order_request: OrderRequest = serde_json::from_value(data).unwrap();
-    let mut n_lock = n.lock().await;
-    let result = n_lock.add_order(
-        order_request.side.to_string(),
-        order_request.quantity,
+    let order_request: OrderRequest = match serde_json::from_value(data) {
+        Ok(order_request) => order_request,
+        Err(e) => return Json(json!({ "error": e.to_string() })),
+    };
+    let (side, quantity) = (order_request.side, order_request.quantity);
+    let mut request = match (
+        order_request.order_type,
         order_request.price,
-    );
-    Json(json!({"id": result.unwrap()}))
+        order_request.stop_price,
+    ) {
+        (OrderType::Market, _, _) => NewOrder::market(side, quantity),
+        (OrderType::Limit, Some(price), _) => NewOrder::limit(side, quantity, price),
+        (OrderType::Stop, _, Some(stop_price)) => NewOrder::stop(side, quantity, stop_price),
+        (OrderType::StopLimit, Some(price), Some(stop_price)) => {
+            NewOrder::stop_limit(side, quantity, stop_price, price)
+        }
+        (OrderType::TrailingStop, _, _) => match order_request.trailing_offset {
+            Some(offset) => NewOrder::trailing_stop(side, quantity, offset),
+            None => return Json(json!({ "error": "Trailing stop requires an offset" })),
+        },
+        (OrderType::Limit | OrderType::StopLimit, None, _) => {
+            return Json(json!({ "error": "Limit order requires a price" }))
+        }
+        (OrderType::Stop | OrderType::StopLimit, _, None) => {
+            return Json(json!({ "error": "Stop order requires a stop price" }))
+        }
+    }
+    .with_time_in_force(order_request.time_in_force, order_request.expire_at);
+    request.display_quantity = order_request.display_quantity;
+    request.post_only = order_request.post_only;
+    request.hidden = order_request.hidden;
+    let output = match n.get(&order_request.symbol) {
+        Some(handle) => {
+            handle
+                .submit(Command::New(request), |_, output| output.clone())
+                .await
+        }
+        None => Err("Unknown symbol"),
+    };
+    match output {
+        Ok(output) => Json(order_response(&output)),
+        Err(e) => Json(json!({ "error": e })),
+    }
 }
 
-async fn get_report(
-    State(n): State<Arc<Mutex<Engine<VectorOrderBook>>>>,
+fn order_response(output: &Output) -> Value {
+    match output.events.first() {
+        Some(Event::Accepted { id }) => {
+            let fills: Vec<_> = output
+                .events
+                .iter()
+                .filter_map(|event| match event {
+                    Event::Fill(fill) if fill.bid_id == *id || fill.ask_id == *id => Some(fill),
+                    _ => None,
+                })
+                .collect();
+            json!({ "seq": output.seq, "id": id, "fills": fills })
+        }
+        Some(Event::Rejected { reason }) => json!({ "seq": output.seq, "error": reason }),
+        _ => json!({ "seq": output.seq, "error": "Order was not acknowledged" }),
+    }
+}
+
+// Checks a request carries `Authorization: Bearer <ADMIN_TOKEN>`
+fn authorize_admin(
+    token: Option<&str>,
+    headers: &HeaderMap,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    let token = match token {
+        Some(token) => token,
+        None => {
+            let error = json!({ "error": "Admin routes are disabled without ADMIN_TOKEN" });
+            return Err((StatusCode::FORBIDDEN, Json(error)));
+        }
+    };
+    let presented = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    // Compared in full whatever the first difference, so timing does not reveal a prefix
+    let matches = presented.len() == token.len()
+        && presented
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0;
+    if matches {
+        Ok(())
+    } else {
+        let error = json!({ "error": "Invalid admin token" });
+        Err((StatusCode::UNAUTHORIZED, Json(error)))
+    }
+}
+
+// Snapshots every engine, reporting the sequence number each snapshot is as of
+async fn post_snapshot<T: OrderBook + Send + 'static>(
+    State(state): State<ApiState<T>>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    authorize_admin(state.admin_token.as_deref(), &headers)?;
+    let mut snapshots = serde_json::Map::new();
+    for handle in state.shards.handles() {
+        let result = match handle.snapshot().await {
+            Ok(seq) => json!({ "seq": seq }),
+            Err(e) => json!({ "error": e }),
+        };
+        snapshots.insert(handle.instrument.symbol.clone(), result);
+    }
+    Ok(Json(Value::Object(snapshots)))
+}
+
+async fn get_report<T: OrderBook + Send + 'static>(
+    State(n): State<Arc<EngineShards<T>>>,
     Path(params): Path<HashMap<String, String>>,
 ) -> Json<Value> {
     let id_param = params.get("id");
-    let id_opt = id_param.map(|s| s.parse::<u64>().unwrap());
+    let id_opt = id_param.and_then(|s| s.parse::<u64>().ok());
+    #[allow(clippy::manual_unwrap_or, clippy::manual_unwrap_or_default)]
     let id = match id_opt {
         Some(i) => i,
         None => 0,
     };
-    let n_lock = n.lock().await;
-    let status_result = n_lock.get_order_status(id);
-    match status_result {
-        Ok(status) => Json(json!({ "status": status })),
-        Err(e) => Json(json!({ "error": e })),
-    }
+    let symbol = params.get("symbol").map(String::as_str).unwrap_or_default();
+    let handle = match n.get(symbol) {
+        Some(handle) => handle,
+        None => return Json(json!({ "error": "Unknown symbol" })),
+    };
+    let result = handle
+        .execute(move |engine| {
+            let status = engine.get_order_status(id)?;
+            Ok((status, engine.get_order_record(id)?.clone()))
+        })
+        .await
+        .and_then(|result| result);
+    let (status, record) = match result {
+        Ok((status, record)) => (status, record),
+        Err(e) => return Json(json!({ "error": e })),
+    };
+    let order = record.order;
+    Json(json!({
+        "status": status,
+        "state": record.status.to_string(),
+        "symbol": handle.instrument.symbol,
+        "id": order.id,
+        "side": order.side,
+        "quantity": order.quantity,
+        "price": order.price,
+        "filled_quantity": order.filled_quantity,
+        "leaves_quantity": order.leaves_quantity,
+        "time_in_force": order.time_in_force,
+        "expire_at": order.expire_at,
+        "stop_price": order.stop_price,
+        "trailing_offset": order.trailing_offset,
+        "display_quantity": order.display_quantity,
+        "post_only": order.post_only,
+        "hidden": order.hidden,
+        "average_price": record.average_price(),
+        "created_at": record.created_at.format("%Y-%m-%dT%H:%M:%SZ"),
+        "updated_at": record.updated_at.format("%Y-%m-%dT%H:%M:%SZ"),
+    }))
 }
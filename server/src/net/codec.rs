@@ -3,7 +3,7 @@
 2: side (buy, sell)
 3: quantity
 4: price
-5: status (new, filled, rejected)
+5: status (new, partially filled, filled, cancelled, replaced, rejected, expired)
 6: volume at limit
 7: trades
 8: book
@@ -11,10 +11,41 @@
 10: market trades
 11: market book
 12: reason
+13: fills
+14: cumulative quantity
+15: leaves quantity
+16: order type (limit, market, stop, stop limit, trailing stop)
+17: time in force (GTC, IOC, FOK, GTD, DAY)
+18: expire time (unix seconds)
+19: stop price
+20: trailing offset (amount, or percentage with a % suffix)
+21: display quantity
+22: post only
+23: hidden
+24: symbol
+25: sequence number (per symbol, from 1)
+26: market depth (levels per side)
+27: depth (volume@price levels, bids then asks)
+28: level updates (action@side@volume@price, action A, C or D)
+29: subscribe
+30: trade id (the last one seen, to page from)
+31: start time (unix nanoseconds)
+32: end time (unix nanoseconds)
+33: max trades
+34: trade records (id@bid id@ask id@aggressor side@quantity@price@unix nanoseconds)
+35: sender comp id
+36: heartbeat interval (seconds)
+37: test request id
+38: message sequence number (per session and direction, from 1)
+39: exec type (trade, cancelled, expired)
+40: last quantity
+41: last price
 */
 
 use time::OffsetDateTime;
 
+use crate::core::order_book;
+use crate::core::price::Price;
 use crate::net::fix::*;
 
 const DELIMITER: char = '|';
@@ -29,7 +60,14 @@ pub fn encode_message(message: &FixMessage) -> String {
         MessageType::OrderStatusRequest => "S",
         MessageType::MarketDataRequest => "M",
         MessageType::MarketData => "D",
+        MessageType::MarketDataIncremental => "I",
+        MessageType::TradeHistoryRequest => "T",
+        MessageType::TradeHistory => "H",
         MessageType::Reject => "R",
+        MessageType::Logon => "A",
+        MessageType::Heartbeat => "0",
+        MessageType::TestRequest => "1",
+        MessageType::Logout => "5",
     };
 
     let fields_str = fields
@@ -49,8 +87,12 @@ pub fn encode_message(message: &FixMessage) -> String {
                 "5={}",
                 match status {
                     OrderStatus::New => "N",
+                    OrderStatus::PartiallyFilled => "P",
                     OrderStatus::Filled => "F",
+                    OrderStatus::Cancelled => "C",
+                    OrderStatus::Replaced => "O",
                     OrderStatus::Rejected => "R",
+                    OrderStatus::Expired => "X",
                 }
             ),
             MessageField::VolumeAtLimit(volume_at_limit) => format!("6={}", volume_at_limit),
@@ -81,6 +123,114 @@ pub fn encode_message(message: &FixMessage) -> String {
             MessageField::MarketTrades(market_trades) => format!("10={}", market_trades),
             MessageField::MarketBook(market_book) => format!("11={}", market_book),
             MessageField::Reason(reason) => format!("12={}", reason),
+            MessageField::Fills(fills) => {
+                let fills_str = fills
+                    .iter()
+                    .map(|(quantity, price)| format!("{}@{}", quantity, price))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                format!("13={}", fills_str)
+            }
+            MessageField::CumQuantity(quantity) => format!("14={}", quantity),
+            MessageField::LeavesQuantity(quantity) => format!("15={}", quantity),
+            MessageField::OrderType(order_type) => format!(
+                "16={}",
+                match order_type {
+                    OrderType::Limit => "L",
+                    OrderType::Market => "M",
+                    OrderType::Stop => "S",
+                    OrderType::StopLimit => "T",
+                    OrderType::TrailingStop => "R",
+                }
+            ),
+            MessageField::TimeInForce(time_in_force) => format!("17={}", time_in_force),
+            MessageField::ExpireTime(expire_at) => format!("18={}", expire_at),
+            MessageField::StopPrice(stop_price) => format!("19={}", stop_price),
+            MessageField::TrailingOffset(offset) => match offset {
+                TrailingOffset::Amount(amount) => format!("20={}", amount),
+                TrailingOffset::Percent(percent) => format!("20={}%", percent),
+            },
+            MessageField::DisplayQuantity(quantity) => format!("21={}", quantity),
+            MessageField::PostOnly(post_only) => format!("22={}", post_only),
+            MessageField::Hidden(hidden) => format!("23={}", hidden),
+            MessageField::Symbol(symbol) => format!("24={}", symbol),
+            MessageField::SeqNum(seq) => format!("25={}", seq),
+            MessageField::MarketDepth(depth) => format!("26={}", depth),
+            MessageField::Depth(bids, asks) => {
+                let levels_str = |levels: &[PriceLevel]| {
+                    levels
+                        .iter()
+                        .map(|level| format!("{}@{}", level.volume, level.price))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                };
+                format!("27={}:{}", levels_str(bids), levels_str(asks))
+            }
+            MessageField::LevelUpdates(updates) => {
+                let updates_str = updates
+                    .iter()
+                    .map(|update| {
+                        format!(
+                            "{}@{}@{}@{}",
+                            match update.action {
+                                LevelAction::Add => "A",
+                                LevelAction::Change => "C",
+                                LevelAction::Delete => "D",
+                            },
+                            match update.side {
+                                order_book::Side::Buy => "B",
+                                order_book::Side::Sell => "S",
+                            },
+                            update.volume,
+                            update.price
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",");
+                format!("28={}", updates_str)
+            }
+            MessageField::Subscribe(subscribe) => format!("29={}", subscribe),
+            MessageField::TradeId(id) => format!("30={}", id),
+            MessageField::StartTime(time) => format!("31={}", time),
+            MessageField::EndTime(time) => format!("32={}", time),
+            MessageField::MaxTrades(limit) => format!("33={}", limit),
+            MessageField::TradeRecords(trades) => {
+                let trades_str = trades
+                    .iter()
+                    .map(|trade| {
+                        format!(
+                            "{}@{}@{}@{}@{}@{}@{}",
+                            trade.id,
+                            trade.bid_id,
+                            trade.ask_id,
+                            match trade.aggressor {
+                                order_book::Side::Buy => "B",
+                                order_book::Side::Sell => "S",
+                            },
+                            trade.quantity,
+                            trade.price,
+                            trade.timestamp
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",");
+                format!("34={}", trades_str)
+            }
+            MessageField::SenderCompId(comp_id) => format!("35={}", comp_id),
+            MessageField::HeartbeatInterval(seconds) => format!("36={}", seconds),
+            MessageField::TestReqId(id) => format!("37={}", id),
+            MessageField::MsgSeqNum(seq) => format!("38={}", seq),
+            MessageField::ExecType(exec_type) => format!(
+                "39={}",
+                match exec_type {
+                    ExecType::Trade => "F",
+                    ExecType::Cancelled => "4",
+                    ExecType::Expired => "C",
+                    ExecType::StopMoved => "D",
+                }
+            ),
+            MessageField::LastQuantity(quantity) => format!("40={}", quantity),
+            MessageField::LastPrice(price) => format!("41={}", price),
         })
         .collect::<Vec<String>>()
         .join(&DELIMITER.to_string());
@@ -102,7 +252,14 @@ pub fn decode_message(data: &str) -> Option<FixMessage> {
         "S" => MessageType::OrderStatusRequest,
         "M" => MessageType::MarketDataRequest,
         "D" => MessageType::MarketData,
+        "I" => MessageType::MarketDataIncremental,
+        "T" => MessageType::TradeHistoryRequest,
+        "H" => MessageType::TradeHistory,
         "R" => MessageType::Reject,
+        "A" => MessageType::Logon,
+        "0" => MessageType::Heartbeat,
+        "1" => MessageType::TestRequest,
+        "5" => MessageType::Logout,
         _ => return None,
     };
 
@@ -123,25 +280,32 @@ pub fn decode_message(data: &str) -> Option<FixMessage> {
                 _ => return None,
             }),
             "3" => MessageField::Quantity(value.parse::<u64>().ok()?),
-            "4" => MessageField::Price(value.parse::<f64>().ok()?),
+            "4" => MessageField::Price(value.parse::<Price>().ok()?),
             "5" => MessageField::Status(match value {
                 "N" => OrderStatus::New,
+                "P" => OrderStatus::PartiallyFilled,
                 "F" => OrderStatus::Filled,
+                "C" => OrderStatus::Cancelled,
+                "O" => OrderStatus::Replaced,
                 "R" => OrderStatus::Rejected,
+                "X" => OrderStatus::Expired,
                 _ => return None,
             }),
-            "6" => MessageField::VolumeAtLimit(value.parse::<f64>().ok()?),
+            "6" => MessageField::VolumeAtLimit(value.parse::<Price>().ok()?),
             "7" => {
-                let mut trades: Vec<(u64, f64, String)> = Vec::new();
+                let mut trades: Vec<(u64, Price, String)> = Vec::new();
                 for trade_str in value.split(',').rev().take(10) {
                     let trade_parts: Vec<&str> = trade_str.split('@').collect();
+                    if trade_parts.len() != 2 {
+                        return None;
+                    }
                     let quantity = trade_parts[0].parse::<u64>().ok()?;
-                    let price = trade_parts[1].parse::<f64>().ok()?;
+                    let price = trade_parts[1].parse::<Price>().ok()?;
                     let datetime = OffsetDateTime::now_utc();
                     trades.push((quantity, price, datetime.format("%Y-%m-%dT%H:%M:%SZ").to_string()));
                 }
                 while trades.len() < 10 {
-                    trades.push((0, 0.0, "".to_string()));
+                    trades.push((0, Price::ZERO, "".to_string()));
                 }
                 MessageField::Trades(trades)
             }
@@ -158,6 +322,114 @@ pub fn decode_message(data: &str) -> Option<FixMessage> {
             "10" => MessageField::MarketTrades(value.parse::<bool>().ok()?),
             "11" => MessageField::MarketBook(value.parse::<bool>().ok()?),
             "12" => MessageField::Reason(value.to_owned()),
+            "13" => {
+                let mut fills = Vec::new();
+                for fill_str in value.split(',').filter(|s| !s.is_empty()) {
+                    let (quantity, price) = fill_str.split_once('@')?;
+                    fills.push((quantity.parse::<u64>().ok()?, price.parse::<Price>().ok()?));
+                }
+                MessageField::Fills(fills)
+            }
+            "14" => MessageField::CumQuantity(value.parse::<u64>().ok()?),
+            "15" => MessageField::LeavesQuantity(value.parse::<u64>().ok()?),
+            "16" => MessageField::OrderType(match value {
+                "L" => OrderType::Limit,
+                "M" => OrderType::Market,
+                "S" => OrderType::Stop,
+                "T" => OrderType::StopLimit,
+                "R" => OrderType::TrailingStop,
+                _ => return None,
+            }),
+            "17" => MessageField::TimeInForce(match value {
+                "GTC" => TimeInForce::Gtc,
+                "IOC" => TimeInForce::Ioc,
+                "FOK" => TimeInForce::Fok,
+                "GTD" => TimeInForce::Gtd,
+                "DAY" => TimeInForce::Day,
+                _ => return None,
+            }),
+            "18" => MessageField::ExpireTime(value.parse::<i64>().ok()?),
+            "19" => MessageField::StopPrice(value.parse::<Price>().ok()?),
+            "20" => MessageField::TrailingOffset(match value.strip_suffix('%') {
+                Some(percent) => TrailingOffset::Percent(percent.parse::<Price>().ok()?),
+                None => TrailingOffset::Amount(value.parse::<Price>().ok()?),
+            }),
+            "21" => MessageField::DisplayQuantity(value.parse::<u64>().ok()?),
+            "22" => MessageField::PostOnly(value.parse::<bool>().ok()?),
+            "23" => MessageField::Hidden(value.parse::<bool>().ok()?),
+            "24" => MessageField::Symbol(value.to_owned()),
+            "25" => MessageField::SeqNum(value.parse::<u64>().ok()?),
+            "26" => MessageField::MarketDepth(value.parse::<u64>().ok()?),
+            "27" => {
+                let (bids, asks) = value.split_once(':')?;
+                MessageField::Depth(parse_levels(bids)?, parse_levels(asks)?)
+            }
+            "28" => {
+                let mut updates = Vec::new();
+                for update_str in value.split(',').filter(|s| !s.is_empty()) {
+                    let update_parts: Vec<&str> = update_str.split('@').collect();
+                    if update_parts.len() != 4 {
+                        return None;
+                    }
+                    updates.push(LevelUpdate {
+                        action: match update_parts[0] {
+                            "A" => LevelAction::Add,
+                            "C" => LevelAction::Change,
+                            "D" => LevelAction::Delete,
+                            _ => return None,
+                        },
+                        side: match update_parts[1] {
+                            "B" => order_book::Side::Buy,
+                            "S" => order_book::Side::Sell,
+                            _ => return None,
+                        },
+                        volume: update_parts[2].parse::<u64>().ok()?,
+                        price: update_parts[3].parse::<Price>().ok()?,
+                    });
+                }
+                MessageField::LevelUpdates(updates)
+            }
+            "29" => MessageField::Subscribe(value.parse::<bool>().ok()?),
+            "30" => MessageField::TradeId(value.parse::<u64>().ok()?),
+            "31" => MessageField::StartTime(value.parse::<i64>().ok()?),
+            "32" => MessageField::EndTime(value.parse::<i64>().ok()?),
+            "33" => MessageField::MaxTrades(value.parse::<u64>().ok()?),
+            "34" => {
+                let mut trades = Vec::new();
+                for trade_str in value.split(',').filter(|s| !s.is_empty()) {
+                    let trade_parts: Vec<&str> = trade_str.split('@').collect();
+                    if trade_parts.len() != 7 {
+                        return None;
+                    }
+                    trades.push(Trade {
+                        id: trade_parts[0].parse::<u64>().ok()?,
+                        bid_id: trade_parts[1].parse::<u64>().ok()?,
+                        ask_id: trade_parts[2].parse::<u64>().ok()?,
+                        aggressor: match trade_parts[3] {
+                            "B" => order_book::Side::Buy,
+                            "S" => order_book::Side::Sell,
+                            _ => return None,
+                        },
+                        quantity: trade_parts[4].parse::<u64>().ok()?,
+                        price: trade_parts[5].parse::<Price>().ok()?,
+                        timestamp: trade_parts[6].parse::<i64>().ok()?,
+                    });
+                }
+                MessageField::TradeRecords(trades)
+            }
+            "35" => MessageField::SenderCompId(value.to_owned()),
+            "36" => MessageField::HeartbeatInterval(value.parse::<u64>().ok()?),
+            "37" => MessageField::TestReqId(value.to_owned()),
+            "38" => MessageField::MsgSeqNum(value.parse::<u64>().ok()?),
+            "39" => MessageField::ExecType(match value {
+                "F" => ExecType::Trade,
+                "4" => ExecType::Cancelled,
+                "C" => ExecType::Expired,
+                "D" => ExecType::StopMoved,
+                _ => return None,
+            }),
+            "40" => MessageField::LastQuantity(value.parse::<u64>().ok()?),
+            "41" => MessageField::LastPrice(value.parse::<Price>().ok()?),
             _ => return None,
         };
         fields.push(field);
@@ -166,12 +438,25 @@ pub fn decode_message(data: &str) -> Option<FixMessage> {
     Some((message_type, fields))
 }
 
+fn parse_levels(levels_str: &str) -> Option<Vec<PriceLevel>> {
+    let mut levels = Vec::new();
+    for level_str in levels_str.split(',').filter(|s| !s.is_empty()) {
+        let (volume, price) = level_str.split_once('@')?;
+        levels.push(PriceLevel {
+            price: price.parse::<Price>().ok()?,
+            volume: volume.parse::<u64>().ok()?,
+        });
+    }
+    Some(levels)
+}
+
+#[allow(clippy::explicit_counter_loop)]
 fn parse_orders(orders_str: &str, is_buy: bool) -> Option<[OrderData; 10]> {
     let mut orders = [OrderData {
         id: 0,
         side: Side::Buy,
         quantity: 0,
-        price: 0.0,
+        price: Price::ZERO,
     }; 10];
     let mut i = 0;
     for order_str in orders_str.split(',') {
@@ -183,7 +468,7 @@ fn parse_orders(orders_str: &str, is_buy: bool) -> Option<[OrderData; 10]> {
             return None;
         }
         let quantity = order_parts[0].parse::<u64>().ok()?;
-        let price = order_parts[1].parse::<f64>().ok()?;
+        let price = order_parts[1].parse::<Price>().ok()?;
         let mut side = Side::Buy;
         if !is_buy {
             side = Side::Sell;
@@ -1,6 +1,13 @@
 use std::fmt;
 
+pub use crate::core::depth::{LevelAction, LevelUpdate};
+pub use crate::core::execution::ExecType;
+pub use crate::core::order_book::PriceLevel;
 use crate::core::order_book::{self, Order};
+pub use crate::core::order_book::{OrderType, TimeInForce, TrailingOffset};
+use crate::core::price::Price;
+pub use crate::core::registry::OrderStatus;
+pub use crate::core::tape::Trade;
 use crate::net::fix;
 
 #[allow(dead_code)]
@@ -13,7 +20,17 @@ pub enum MessageType {
     OrderStatusRequest,
     MarketDataRequest,
     MarketData,
+    // Depth changes pushed to a subscribed connection
+    MarketDataIncremental,
+    // A page of the trade tape, and the answer to one
+    TradeHistoryRequest,
+    TradeHistory,
     Reject,
+    // Session level messages, which never reach an engine
+    Logon,
+    Heartbeat,
+    TestRequest,
+    Logout,
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -22,11 +39,11 @@ pub enum Side {
     Sell,
 }
 
-impl ToString for Side {
-    fn to_string(&self) -> String {
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Side::Buy => "Buy".to_string(),
-            Side::Sell => "Sell".to_string(),
+            Side::Buy => write!(f, "Buy"),
+            Side::Sell => write!(f, "Sell"),
         }
     }
 }
@@ -40,19 +57,11 @@ impl From<order_book::Side> for Side {
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub enum OrderStatus {
-    New,
-    Filled,
-    Rejected,
-}
-
-impl fmt::Display for OrderStatus {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            OrderStatus::New => write!(f, "New"),
-            OrderStatus::Filled => write!(f, "Filled"),
-            OrderStatus::Rejected => write!(f, "Rejected"),
+impl From<Side> for order_book::Side {
+    fn from(side: Side) -> Self {
+        match side {
+            Side::Buy => Self::Buy,
+            Side::Sell => Self::Sell,
         }
     }
 }
@@ -62,18 +71,15 @@ pub struct OrderData {
     pub id: u64,
     pub side: Side,
     pub quantity: u64,
-    pub price: f64,
+    pub price: Price,
 }
 
 impl fmt::Display for OrderData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "Order ID: {}\nSide: {}\nQuantity: {}\nPrice: {:.2}\n",
-            self.id,
-            self.side.to_string(),
-            self.quantity,
-            self.price,
+            "Order ID: {}\nSide: {}\nQuantity: {}\nPrice: {}\n",
+            self.id, self.side, self.quantity, self.price,
         )
     }
 }
@@ -83,26 +89,56 @@ impl From<Order> for OrderData {
         Self {
             id: order.id,
             side: fix::Side::from(order.side),
-            quantity: order.quantity,
+            quantity: order.leaves_quantity,
             price: order.price,
         }
     }
 }
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, PartialEq)]
 pub enum MessageField {
     OrderId(u64),
     Side(Side),
     Quantity(u64),
-    Price(f64),
+    Price(Price),
     Status(OrderStatus),
-    VolumeAtLimit(f64),
-    Trades(Vec<(u64, f64, std::string::String)>),
+    VolumeAtLimit(Price),
+    Trades(Vec<(u64, Price, std::string::String)>),
     Book([OrderData; 10], [OrderData; 10]),
     MarketPrice(bool),
     MarketTrades(bool),
     MarketBook(bool),
     Reason(String),
+    Fills(Vec<(u64, Price)>),
+    CumQuantity(u64),
+    LeavesQuantity(u64),
+    OrderType(OrderType),
+    TimeInForce(TimeInForce),
+    ExpireTime(i64),
+    StopPrice(Price),
+    TrailingOffset(TrailingOffset),
+    DisplayQuantity(u64),
+    PostOnly(bool),
+    Hidden(bool),
+    Symbol(String),
+    SeqNum(u64),
+    MarketDepth(u64),
+    Depth(Vec<PriceLevel>, Vec<PriceLevel>),
+    LevelUpdates(Vec<LevelUpdate>),
+    Subscribe(bool),
+    TradeId(u64),
+    StartTime(i64),
+    EndTime(i64),
+    MaxTrades(u64),
+    TradeRecords(Vec<Trade>),
+    SenderCompId(String),
+    HeartbeatInterval(u64),
+    TestReqId(String),
+    MsgSeqNum(u64),
+    ExecType(ExecType),
+    LastQuantity(u64),
+    LastPrice(Price),
 }
 
 pub type FixMessage = (MessageType, Vec<MessageField>);
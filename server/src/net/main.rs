@@ -1,183 +1,446 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use std::thread;
 use std::time::Duration;
-use tokio::sync::Mutex;
+use time::OffsetDateTime;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::{TcpListener, TcpStream},
+    io::{AsyncWrite, AsyncWriteExt},
+    net::TcpListener,
+    sync::{broadcast, Semaphore},
 };
 
 use super::api::start_api;
-use super::codec::{decode_message, encode_message};
+use super::codec::encode_message;
 use super::fix::{FixMessage, MessageField, MessageType, OrderData};
-use crate::core::{engine, vector::VectorOrderBook};
+use super::session::{refuse_connection, serve_connection, LoggedOn};
+use super::stream::{orders_snapshot, orders_update};
+use crate::core::{
+    btree::BTreeOrderBook,
+    bus::{EventBus, Update},
+    depth::DepthBook,
+    engine,
+    instrument::{Instrument, InstrumentRegistry},
+    journal::{read_journal, JournalConfig},
+    order_book::{
+        Fill, MarketRemainder, NewOrder, Order, OrderBook, OrderType, PostOnlyPolicy, Side,
+        TimeInForce,
+    },
+    price::Price,
+    registry::OrderStatus,
+    replay::recover,
+    sequencer::{Command, Event, Output},
+    shard::{EngineShards, Listener},
+    snapshot::read_snapshot,
+    tape::{TradeQuery, MAX_PAGE},
+    vector::VectorOrderBook,
+};
 use array_init::array_init;
 
 #[tokio::main]
 pub async fn start() {
-    let n = Arc::new(Mutex::new(engine::Engine::<VectorOrderBook>::new()));
-    println!("Engine created");
+    match std::env::var("ORDER_BOOK").as_deref() {
+        Ok("btree") => run::<BTreeOrderBook>().await,
+        _ => run::<VectorOrderBook>().await,
+    }
+}
+
+// Re-runs every journaled command against engines restored from their snapshots and
+// reports any command whose events differ from the recorded ones
+pub fn replay() {
+    let diverged = match std::env::var("ORDER_BOOK").as_deref() {
+        Ok("btree") => replay_journals::<BTreeOrderBook>(),
+        _ => replay_journals::<VectorOrderBook>(),
+    };
+    std::process::exit(if diverged { 1 } else { 0 });
+}
+
+fn replay_journals<T: OrderBook>() -> bool {
+    let config = journal_config().expect("JOURNAL_DIR must be set to replay");
+    let mut instruments = list_instruments::<T>();
+    recover_instruments(&mut instruments, &config).1
+}
+
+// Prints the market-by-order feed recorded for each instrument as JSON lines: the displayed
+// orders in its snapshot, if any, then every journaled change to them after it
+pub fn orders() {
+    let config = journal_config().expect("JOURNAL_DIR must be set to read the feed");
+    for instrument in listings() {
+        let symbol = instrument.symbol;
+        let snapshot =
+            read_snapshot(&config.snapshot_path(&symbol)).expect("Failed to read snapshot");
+        let snapshot_seq = snapshot.as_ref().map(|snapshot| snapshot.seq);
+        if let Some(snapshot) = snapshot {
+            let displayed = |mut orders: Vec<Order>| {
+                orders.retain(|order| !order.hidden);
+                orders
+            };
+            let bids = displayed(snapshot.bids);
+            let asks = displayed(snapshot.asks);
+            println!("{}", orders_snapshot(&symbol, snapshot.seq, &bids, &asks));
+        }
+        for entry in read_journal(&config.path(&symbol)).expect("Failed to read journal") {
+            let output = Output {
+                seq: entry.seq,
+                symbol: symbol.clone(),
+                events: entry.events,
+            };
+            if Some(output.seq) > snapshot_seq {
+                if let Some(update) = orders_update(&output) {
+                    println!("{}", update);
+                }
+            }
+        }
+    }
+}
+
+// Comma separated SYMBOL[:tick size[:lot size[:price band %]]] listings
+fn listings() -> Vec<Instrument> {
+    let tick_size: Price = std::env::var("TICK_SIZE")
+        .unwrap_or_else(|_| "0.01".to_string())
+        .parse()
+        .expect("Invalid TICK_SIZE");
+    let listings = std::env::var("INSTRUMENTS").unwrap_or_else(|_| format!("XYZ:{}", tick_size));
+    listings
+        .split(',')
+        .map(|listing| listing.parse().expect("Invalid INSTRUMENTS"))
+        .collect()
+}
+
+fn list_instruments<T: OrderBook>() -> InstrumentRegistry<T> {
+    let mut instruments = InstrumentRegistry::<T>::new();
+    for instrument in listings() {
+        let engine = instruments
+            .add(instrument)
+            .expect("Duplicate symbol in INSTRUMENTS");
+        if let Ok("limit") = std::env::var("MARKET_REMAINDER").as_deref() {
+            engine.market_remainder = MarketRemainder::Limit;
+        }
+        if let Ok("reprice") = std::env::var("POST_ONLY").as_deref() {
+            engine.post_only_policy = PostOnlyPolicy::Reprice;
+        }
+        println!(
+            "Engine created for {} with tick size {}",
+            engine.instrument.symbol, engine.instrument.tick_size
+        );
+    }
+    instruments
+}
+
+fn journal_config() -> Option<JournalConfig> {
+    let dir = std::env::var("JOURNAL_DIR").ok()?;
+    Some(JournalConfig {
+        dir: dir.into(),
+        policy: std::env::var("JOURNAL_FSYNC")
+            .unwrap_or_else(|_| "always".to_string())
+            .parse()
+            .expect("Invalid JOURNAL_FSYNC"),
+    })
+}
+
+// Replays each engine's journal into it, returning the last sequence number seen for each
+// symbol and whether any command diverged from its recorded events
+fn recover_instruments<T: OrderBook>(
+    instruments: &mut InstrumentRegistry<T>,
+    config: &JournalConfig,
+) -> (BTreeMap<String, u64>, bool) {
+    let mut last_seqs = BTreeMap::new();
+    let mut diverged = false;
+    for engine in instruments.engines_mut() {
+        let symbol = engine.instrument.symbol.clone();
+        let recovery = recover(engine, config).expect("Failed to read journal");
+        if let Some(seq) = recovery.snapshot {
+            println!("Loaded {} snapshot at sequence number {}", symbol, seq);
+        }
+        println!(
+            "Replayed {} commands for {} up to sequence number {}",
+            recovery.commands, symbol, recovery.last_seq
+        );
+        for divergence in &recovery.divergences {
+            eprintln!(
+                "Divergence at sequence number {}: recorded {:?}, replayed {:?}",
+                divergence.seq, divergence.recorded, divergence.replayed
+            );
+        }
+        last_seqs.insert(symbol, recovery.last_seq);
+        diverged |= !recovery.divergences.is_empty();
+    }
+    (last_seqs, diverged)
+}
+
+async fn run<T: OrderBook + Send + 'static>() {
+    let mut instruments = list_instruments::<T>();
+    let journals = journal_config();
+    let mut last_seqs = BTreeMap::new();
+    if let Some(config) = &journals {
+        std::fs::create_dir_all(&config.dir).expect("Failed to create JOURNAL_DIR");
+        // Rebuilds the state the server had when it last stopped
+        last_seqs = recover_instruments(&mut instruments, config).0;
+        println!("Journaling to {}", config.dir.display());
+    }
+    // Each instrument is matched on its own thread from here on, publishing what every
+    // command did to the event bus
+    let bus = Arc::new(EventBus::new());
+    let publisher = bus.clone();
+    let listener: Listener<T> = Arc::new(move |n, output| {
+        report_output(n, output);
+        publisher.publish(n, output);
+    });
+    let shards = EngineShards::spawn(instruments, &last_seqs, listener, journals.as_ref());
+    let n = Arc::new(shards.expect("Failed to open journal"));
     let mut handles = vec![];
 
     let n_1 = n.clone();
+    let bus_1 = bus.clone();
     handles.push(tokio::spawn(async move {
-        start_api(n_1).await;
+        start_api(n_1, bus_1, std::env::var("ADMIN_TOKEN").ok()).await;
     }));
 
     let n_2 = n.clone();
     handles.push(tokio::spawn(async move {
-        start_socket(n_2).await;
+        start_socket(n_2, bus).await;
     }));
 
     let n_3 = n.clone();
     handles.push(tokio::spawn(async move {
-        start_matching(n_3).await;
+        start_expiry_scheduler(n_3).await;
     }));
 
+    if let (Some(_), Ok(interval)) = (&journals, std::env::var("SNAPSHOT_INTERVAL")) {
+        let interval = interval.parse().expect("Invalid SNAPSHOT_INTERVAL");
+        let n_4 = n.clone();
+        handles.push(tokio::spawn(async move {
+            start_snapshot_scheduler(n_4, Duration::from_secs(interval)).await;
+        }));
+    }
+
     futures::future::join_all(handles).await;
 }
 
-async fn start_socket(n: Arc<Mutex<engine::Engine<VectorOrderBook>>>) {
-    let listener = TcpListener::bind("127.0.0.1:6379").await.unwrap();
-    println!("Listening on {}", listener.local_addr().unwrap());
-
+// Expires GTD and DAY orders once their expiry time has passed
+async fn start_expiry_scheduler<T: OrderBook + Send + 'static>(shards: Arc<EngineShards<T>>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
     loop {
-        // the second item contains the IP and port of the new connection
-        let (socket, _) = listener.accept().await.unwrap();
-        println!("Accepted connection from {}", socket.peer_addr().unwrap());
-        process_request(socket, n.clone())
-            .await
-            .unwrap_or_else(|e| {
-                eprintln!("Error processing connection: {}", e);
-            });
+        interval.tick().await;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        for handle in shards.handles() {
+            // Only sequence an expiry when an order is actually due
+            let due = handle
+                .execute(move |n| n.next_expiry().is_some_and(|expire_at| expire_at <= now))
+                .await;
+            let result = match due {
+                Ok(true) => handle.submit(Command::Expire { now }, |_, _| ()).await,
+                Ok(false) => Ok(()),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                eprintln!("Error expiring {} orders: {}", handle.instrument.symbol, e);
+            }
+        }
     }
 }
 
-async fn start_matching(n: Arc<Mutex<engine::Engine<VectorOrderBook>>>) {
+// Snapshots every engine each `period`, which also empties their journals
+async fn start_snapshot_scheduler<T: OrderBook + Send + 'static>(
+    shards: Arc<EngineShards<T>>,
+    period: Duration,
+) {
+    let mut interval = tokio::time::interval(period);
+    // The first tick completes immediately, and there is nothing to snapshot yet
+    interval.tick().await;
     loop {
-        let mut n_data = n.lock().await;
-        let _result = match n_data.match_orders() {
-            Ok((match_quantity, match_price)) => {
-                println!("Matched {} @ {}", match_quantity, match_price);
-            }
-            Err(e) => {
-                if e != "No matching orders" {
-                    eprintln!("Error matching orders: {}", e);
-                }
+        interval.tick().await;
+        for handle in shards.handles() {
+            if let Err(e) = handle.snapshot().await {
+                eprintln!("Error snapshotting {}: {}", handle.instrument.symbol, e);
             }
+        }
+    }
+}
+
+// Logs what each command did to orders other than the one it was for
+fn report_output<T: OrderBook>(n: &engine::Engine<T>, output: &Output) {
+    for event in &output.events {
+        let (message, id) = match event {
+            Event::OrderUpdate {
+                id,
+                status: OrderStatus::Expired,
+                ..
+            } => ("Order expired", id),
+            _ => continue,
         };
-        thread::sleep(Duration::from_millis(1000));
+        let mut report = execution_report(n, *id, &[]);
+        report.1.push(MessageField::SeqNum(output.seq));
+        println!("{}: {}", message, encode_message(&report));
     }
 }
 
-async fn process_request(
-    mut socket: TcpStream,
-    n_locked: Arc<Mutex<engine::Engine<VectorOrderBook>>>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let msg_len = socket.read_u32().await?.to_be(); // Read the length of the message
-    let mut buf = vec![0u8; msg_len as usize];
-    socket.read_exact(&mut buf).await?;
+// Connections served at once unless MAX_CONNECTIONS says otherwise
+const DEFAULT_MAX_CONNECTIONS: usize = 1024;
 
-    let str_from_bytes = std::str::from_utf8(&buf)?;
-    match decode_message(str_from_bytes) {
-        Some(fix_msg) => engine_response(&mut socket, n_locked, fix_msg).await,
-        None => {
-            eprintln!("Error decoding FixMessage");
-            return Err("Error decoding FixMessage".into());
-        }
+async fn start_socket<T: OrderBook + Send + 'static>(n: Arc<EngineShards<T>>, bus: Arc<EventBus>) {
+    let listener = TcpListener::bind("127.0.0.1:6379").await.unwrap();
+    println!("Listening on {}", listener.local_addr().unwrap());
+    let logged_on = LoggedOn::default();
+    let max_connections = match std::env::var("MAX_CONNECTIONS") {
+        Ok(max) => max.parse().expect("Invalid MAX_CONNECTIONS"),
+        Err(_) => DEFAULT_MAX_CONNECTIONS,
+    };
+    let connections = Arc::new(Semaphore::new(max_connections));
+
+    loop {
+        // the second item contains the IP and port of the new connection
+        let (socket, address) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            // e.g. out of file descriptors, which closing other connections frees up
+            Err(e) => {
+                eprintln!("Error accepting connection: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let permit = match connections.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                eprintln!("Refused connection from {}: too many connections", address);
+                tokio::spawn(refuse_connection(socket, "Too many connections"));
+                continue;
+            }
+        };
+        println!("Accepted connection from {}", address);
+        // Each connection is served on its own task, so that one slow client holds up no other
+        let (n, bus, logged_on) = (n.clone(), bus.clone(), logged_on.clone());
+        tokio::spawn(async move {
+            serve_connection(socket, n, bus, logged_on).await;
+            drop(permit);
+        });
     }
 }
 
-async fn engine_response(
-    socket: &mut TcpStream,
-    n_locked: Arc<Mutex<engine::Engine<VectorOrderBook>>>,
+// A connection's view of one instrument's depth, and the updates still to apply to it
+pub(super) struct DepthSubscription {
+    pub(super) symbol: String,
+    pub(super) book: DepthBook,
+    pub(super) updates: broadcast::Receiver<Arc<Update>>,
+}
+
+pub(super) async fn engine_response<T: OrderBook + Send + 'static>(
+    shards: Arc<EngineShards<T>>,
+    bus: &EventBus,
     fix_msg: FixMessage,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let mut n = n_locked.lock().await;
+) -> Result<(FixMessage, Option<DepthSubscription>), Box<dyn std::error::Error>> {
+    let (message_type, fields) = fix_msg;
+    // Every message names the instrument it is for
+    let (symbols, fields): (Vec<_>, Vec<_>) = fields
+        .into_iter()
+        .partition(|field| matches!(field, MessageField::Symbol(_)));
+    let handle = match symbols.first() {
+        Some(MessageField::Symbol(symbol)) => shards.get(symbol),
+        _ => None,
+    };
 
-    let response: FixMessage = match fix_msg.0 {
-        MessageType::NewOrder => {
-            if let [MessageField::Side(side), MessageField::Quantity(quantity), MessageField::Price(price)] =
-                fix_msg.1.as_slice()
-            {
-                match n.add_order(side.to_string(), *quantity, *price) {
-                    Ok(id) => (
-                        MessageType::ExecutionReport,
-                        vec![MessageField::OrderId(id)],
-                    ),
-                    Err(e) => {
-                        eprintln!("Error adding order: {}", e);
-                        (
-                            MessageType::Reject,
-                            vec![MessageField::Reason(e.to_string())],
-                        )
-                    }
-                }
-            } else {
-                return Err("Error processing NewOrder message".into());
+    let subscribe = message_type == MessageType::MarketDataRequest
+        && fields.contains(&MessageField::Subscribe(true));
+    let depth = fields.iter().find_map(|field| match field {
+        MessageField::MarketDepth(depth) => Some(*depth as usize),
+        _ => None,
+    });
+    let fix_msg = (message_type, fields);
+    let command = command_request(&fix_msg)?;
+    let mut subscription = None;
+    let response = match (handle, command) {
+        (Some(handle), Some(command)) => handle.submit(command, command_report).await?,
+        (Some(handle), None) if fix_msg.0 == MessageType::TradeHistoryRequest => {
+            let query = trade_query(&fix_msg.1);
+            let trades = handle.trades(query).await?;
+            let mut fields = vec![MessageField::Symbol(handle.instrument.symbol.clone())];
+            // A full page may have more after it, from the last trade id in it
+            if let Some(trade) = trades.last().filter(|_| trades.len() == query.limit) {
+                fields.push(MessageField::TradeId(trade.id));
             }
+            fields.push(MessageField::TradeRecords(trades));
+            (MessageType::TradeHistory, fields)
         }
-        MessageType::OrderReplaceRequest => {
-            if let [MessageField::OrderId(id), MessageField::Quantity(quantity), MessageField::Price(price)] =
-                fix_msg.1.as_slice()
-            {
-                match n.amend_order(*id, *quantity, *price) {
-                    Ok(_) => (
-                        MessageType::ExecutionReport,
-                        vec![MessageField::OrderId(*id)],
-                    ),
-                    Err(e) => {
-                        eprintln!("Error amending order: {}", e);
-                        (
-                            MessageType::Reject,
-                            vec![MessageField::Reason(e.to_string())],
-                        )
-                    }
-                }
-            } else {
-                return Err("Error processing OrderReplaceRequest message".into());
-            }
+        (Some(handle), None) if subscribe => {
+            let depth = depth.ok_or("Depth subscriptions require a market depth")?;
+            // Subscribed before the book is read so that no later change is missed
+            let updates = bus.subscribe();
+            let (seq, (response, bids, asks)) = handle
+                .execute_as_of(move |n| {
+                    (
+                        engine_message(n, fix_msg).map_err(|e| e.to_string()),
+                        n.get_depth(Side::Buy, usize::MAX),
+                        n.get_depth(Side::Sell, usize::MAX),
+                    )
+                })
+                .await?;
+            subscription = Some(DepthSubscription {
+                symbol: handle.instrument.symbol.clone(),
+                book: DepthBook::new(depth, seq, &bids, &asks),
+                updates,
+            });
+            market_data_as_of(seq, response?)
         }
-        MessageType::OrderCancelRequest => {
-            if let [MessageField::OrderId(id)] = fix_msg.1.as_slice() {
-                match n.cancel_order(*id) {
-                    Ok(_) => (
-                        MessageType::ExecutionReport,
-                        vec![MessageField::OrderId(*id)],
-                    ),
-                    Err(e) => {
-                        eprintln!("Error cancelling order: {}", e);
-                        (
-                            MessageType::Reject,
-                            vec![MessageField::Reason(e.to_string())],
-                        )
-                    }
-                }
-            } else {
-                return Err("Error processing OrderCancelRequest message".into());
-            }
+        (Some(handle), None) => {
+            let (seq, response) = handle
+                .execute_as_of(move |n| engine_message(n, fix_msg).map_err(|e| e.to_string()))
+                .await?;
+            market_data_as_of(seq, response?)
+        }
+        (None, _) => (
+            MessageType::Reject,
+            vec![MessageField::Reason("Unknown symbol".into())],
+        ),
+    };
+    Ok((response, subscription))
+}
+
+fn trade_query(fields: &[MessageField]) -> TradeQuery {
+    let mut query = TradeQuery::default();
+    for field in fields {
+        match field {
+            MessageField::TradeId(id) => query.after = *id,
+            MessageField::StartTime(time) => query.from = *time,
+            MessageField::EndTime(time) => query.to = *time,
+            MessageField::MaxTrades(limit) => query.limit = (*limit as usize).clamp(1, MAX_PAGE),
+            _ => {}
         }
+    }
+    query
+}
+
+// Stamps market data with the sequence number of the last command it reflects
+fn market_data_as_of(seq: u64, mut response: FixMessage) -> FixMessage {
+    if response.0 == MessageType::MarketData {
+        response.1.push(MessageField::SeqNum(seq));
+    }
+    response
+}
+
+pub(super) async fn write_message<W: AsyncWrite + Unpin>(
+    socket: &mut W,
+    message: &FixMessage,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let message_buf = encode_message(message);
+    let message_bytes = message_buf.as_bytes().to_vec();
+    let message_len = message_bytes.len() as u32;
+    socket.write_u32(message_len.to_be()).await?;
+    socket.write_all(&message_bytes).await?;
+    Ok(())
+}
+
+fn engine_message<T: OrderBook>(
+    n: &mut engine::Engine<T>,
+    fix_msg: FixMessage,
+) -> Result<FixMessage, Box<dyn std::error::Error>> {
+    let response: FixMessage = match fix_msg.0 {
         MessageType::OrderStatusRequest => {
             if let [MessageField::OrderId(id)] = fix_msg.1.as_slice() {
-                match n.get_execution_status(*id) {
-                    Ok(status) => (
-                        MessageType::ExecutionReport,
-                        vec![MessageField::OrderId(*id), MessageField::Status(status)],
-                    ),
-                    Err(e) => {
-                        eprintln!("Error getting order status: {}", e);
-                        (
-                            MessageType::Reject,
-                            vec![MessageField::Reason(e.to_string())],
-                        )
-                    }
-                }
+                execution_report(n, *id, &[])
             } else {
                 return Err("Error processing OrderStatusRequest message".into());
             }
         }
         MessageType::MarketDataRequest => {
-            let mut response_fields = vec![];
+            let mut response_fields = vec![MessageField::Symbol(n.instrument.symbol.clone())];
 
             if let Some(MessageField::VolumeAtLimit(price)) = fix_msg
                 .1
@@ -193,7 +456,16 @@ async fn engine_response(
                 .iter()
                 .find(|&field| matches!(field, MessageField::MarketPrice(_)))
             {
-                let market_price = MessageField::Price(n.get_market_price().unwrap());
+                // An empty or one-sided book has no market price to report
+                let market_price = match n.get_market_price() {
+                    Ok(price) => MessageField::Price(price),
+                    Err(reason) => {
+                        return Ok((
+                            MessageType::Reject,
+                            vec![MessageField::Reason(reason.into())],
+                        ))
+                    }
+                };
                 response_fields.push(market_price);
             }
 
@@ -218,6 +490,19 @@ async fn engine_response(
                 let market_book_field = MessageField::Book(bids_data, asks_data);
                 response_fields.push(market_book_field);
             }
+
+            if let Some(MessageField::MarketDepth(depth)) = fix_msg
+                .1
+                .iter()
+                .find(|&field| matches!(field, MessageField::MarketDepth(_)))
+            {
+                let depth = *depth as usize;
+                let depth_field = MessageField::Depth(
+                    n.get_depth(Side::Buy, depth),
+                    n.get_depth(Side::Sell, depth),
+                );
+                response_fields.push(depth_field);
+            }
             (MessageType::MarketData, response_fields)
         }
         _ => (
@@ -226,12 +511,134 @@ async fn engine_response(
         ),
     };
 
-    let response_buf = encode_message(&response);
-    let response_bytes = response_buf.as_bytes().to_vec();
-    let response_len = response_bytes.len() as u32;
-    socket.write_u32(response_len.to_be()).await?;
-    socket.write_all(&response_bytes).await?;
+    Ok(response)
+}
 
-    drop(n);
-    Ok(())
+// The state changing messages, which are sequenced before they reach the engine
+fn command_request(fix_msg: &FixMessage) -> Result<Option<Command>, Box<dyn std::error::Error>> {
+    let command = match fix_msg.0 {
+        MessageType::NewOrder => match new_order_request(&fix_msg.1) {
+            Some(request) => Command::New(request),
+            None => return Err("Error processing NewOrder message".into()),
+        },
+        MessageType::OrderReplaceRequest => match fix_msg.1.as_slice() {
+            [MessageField::OrderId(id), MessageField::Quantity(quantity), MessageField::Price(price)] => {
+                Command::Amend {
+                    id: *id,
+                    quantity: *quantity,
+                    price: *price,
+                }
+            }
+            _ => return Err("Error processing OrderReplaceRequest message".into()),
+        },
+        MessageType::OrderCancelRequest => match fix_msg.1.as_slice() {
+            [MessageField::OrderId(id)] => Command::Cancel { id: *id },
+            _ => return Err("Error processing OrderCancelRequest message".into()),
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(command))
+}
+
+// Answers a command with an execution report for its order, or a reject, carrying
+// the sequence number it was applied under
+fn command_report<T: OrderBook>(n: &engine::Engine<T>, output: &Output) -> FixMessage {
+    let mut report = match output.events.first() {
+        Some(Event::Accepted { id }) => {
+            let fills: Vec<Fill> = output
+                .events
+                .iter()
+                .filter_map(|event| match event {
+                    Event::Fill(fill) if fill.bid_id == *id || fill.ask_id == *id => Some(*fill),
+                    _ => None,
+                })
+                .collect();
+            execution_report(n, *id, &fills)
+        }
+        Some(Event::Rejected { reason }) => {
+            eprintln!("Error processing order: {}", reason);
+            (
+                MessageType::Reject,
+                vec![MessageField::Reason(reason.clone())],
+            )
+        }
+        _ => (
+            MessageType::Reject,
+            vec![MessageField::Reason("Command was not acknowledged".into())],
+        ),
+    };
+    report.1.push(MessageField::SeqNum(output.seq));
+    report
+}
+
+fn new_order_request(fields: &[MessageField]) -> Option<NewOrder> {
+    let mut side = None;
+    let mut quantity = None;
+    let mut price = None;
+    let mut order_type = OrderType::Limit;
+    let mut time_in_force = TimeInForce::Gtc;
+    let mut expire_at = None;
+    let mut stop_price = None;
+    let mut trailing_offset = None;
+    let mut display_quantity = None;
+    let mut post_only = false;
+    let mut hidden = false;
+    for field in fields {
+        match field {
+            MessageField::Side(value) => side = Some((*value).into()),
+            MessageField::Quantity(value) => quantity = Some(*value),
+            MessageField::Price(value) => price = Some(*value),
+            MessageField::OrderType(value) => order_type = *value,
+            MessageField::TimeInForce(value) => time_in_force = *value,
+            MessageField::ExpireTime(value) => expire_at = Some(*value),
+            MessageField::StopPrice(value) => stop_price = Some(*value),
+            MessageField::TrailingOffset(value) => trailing_offset = Some(*value),
+            MessageField::DisplayQuantity(value) => display_quantity = Some(*value),
+            MessageField::PostOnly(value) => post_only = *value,
+            MessageField::Hidden(value) => hidden = *value,
+            _ => {}
+        }
+    }
+    let mut request = match order_type {
+        OrderType::Limit => NewOrder::limit(side?, quantity?, price?),
+        OrderType::Market => NewOrder::market(side?, quantity?),
+        OrderType::Stop => NewOrder::stop(side?, quantity?, stop_price?),
+        OrderType::StopLimit => NewOrder::stop_limit(side?, quantity?, stop_price?, price?),
+        OrderType::TrailingStop => NewOrder::trailing_stop(side?, quantity?, trailing_offset?),
+    };
+    request.display_quantity = display_quantity;
+    request.post_only = post_only;
+    request.hidden = hidden;
+    Some(request.with_time_in_force(time_in_force, expire_at))
+}
+
+fn execution_report<T: OrderBook>(n: &engine::Engine<T>, id: u64, fills: &[Fill]) -> FixMessage {
+    let record = match n.get_order_record(id) {
+        Ok(record) => record,
+        Err(e) => {
+            eprintln!("Error getting order status: {}", e);
+            return (
+                MessageType::Reject,
+                vec![MessageField::Reason(e.to_string())],
+            );
+        }
+    };
+    let mut fields = vec![
+        MessageField::Symbol(n.instrument.symbol.clone()),
+        MessageField::OrderId(id),
+        MessageField::Status(record.status),
+        MessageField::CumQuantity(record.order.filled_quantity),
+        MessageField::LeavesQuantity(record.order.leaves_quantity),
+    ];
+    if let Some(stop_price) = record.order.stop_price {
+        fields.push(MessageField::StopPrice(stop_price));
+    }
+    if !fills.is_empty() {
+        let fills = fills
+            .iter()
+            .map(|fill| (fill.quantity, fill.price))
+            .collect();
+        fields.push(MessageField::Fills(fills));
+    }
+    (MessageType::ExecutionReport, fields)
 }
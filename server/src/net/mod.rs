@@ -2,3 +2,5 @@ pub mod api;
 mod codec;
 pub mod fix;
 pub mod main;
+pub mod session;
+mod stream;
@@ -0,0 +1,481 @@
+use std::collections::{HashMap, HashSet};
+use std::future::pending;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::{
+    io::{AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{tcp::OwnedReadHalf, TcpStream},
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc,
+    },
+    time::{timeout, Instant},
+};
+
+use super::codec::decode_message;
+use super::fix::{FixMessage, MessageField, MessageType};
+use super::main::{engine_response, write_message};
+use crate::core::{
+    bus::{EventBus, Update},
+    depth::DepthBook,
+    order_book::OrderBook,
+    shard::EngineShards,
+};
+
+// Heartbeat interval, in seconds, for a Logon that does not give one
+const DEFAULT_HEARTBEAT: u64 = 30;
+// Longest heartbeat interval a Logon may ask for, in seconds
+const MAX_HEARTBEAT: u64 = 3600;
+// Longest a connection may go without logging on
+const LOGON_TIMEOUT: Duration = Duration::from_secs(10);
+// Longest the rest of a frame may take to arrive once its length has, and a write to be taken
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+// Largest frame accepted, well above any request a client needs to send
+const MAX_FRAME: u32 = 64 * 1024;
+
+// Comp ids with a session open, each of which may only be logged on once at a time
+pub type LoggedOn = Arc<Mutex<HashSet<String>>>;
+
+// A comp id logged on by a session, released however the session ends, a panic included
+struct Logon {
+    comp_id: String,
+    logged_on: LoggedOn,
+}
+
+impl Drop for Logon {
+    fn drop(&mut self) {
+        let mut logged_on = self.logged_on.lock().unwrap_or_else(|e| e.into_inner());
+        logged_on.remove(&self.comp_id);
+        println!("{} logged out", self.comp_id);
+    }
+}
+
+// One client's conversation over a connection, from its Logon to its Logout
+struct Session<W> {
+    writer: W,
+    logon: Option<Logon>,
+    // Last message sequence numbers received and sent
+    in_seq: u64,
+    out_seq: u64,
+    heartbeat: Duration,
+    last_received: Instant,
+    last_sent: Instant,
+    // A test request still waiting on its heartbeat, and when it was sent
+    test_request: Option<(String, Instant)>,
+    connected: Instant,
+    // Books followed on this connection, kept up to date from the bus
+    depth: HashMap<String, DepthBook>,
+    // Orders entered on this connection and still live, by symbol and id, with the sequence
+    // numbers of the commands whose responses reported their executions but whose updates
+    // have yet to arrive
+    orders: HashMap<(String, u64), Vec<u64>>,
+    updates: Option<broadcast::Receiver<Arc<Update>>>,
+}
+
+// Whether a session carries on after handling something
+enum Flow {
+    Continue,
+    Close,
+}
+
+impl<W: AsyncWrite + Unpin> Session<W> {
+    fn new(writer: W) -> Session<W> {
+        let now = Instant::now();
+        Session {
+            writer,
+            logon: None,
+            in_seq: 0,
+            out_seq: 0,
+            heartbeat: Duration::from_secs(DEFAULT_HEARTBEAT),
+            last_received: now,
+            last_sent: now,
+            test_request: None,
+            connected: now,
+            depth: HashMap::new(),
+            orders: HashMap::new(),
+            updates: None,
+        }
+    }
+
+    // Stamps a message with the next outbound sequence number and sends it
+    async fn send(&mut self, mut message: FixMessage) -> Result<(), Box<dyn std::error::Error>> {
+        self.out_seq += 1;
+        message.1.push(MessageField::MsgSeqNum(self.out_seq));
+        self.last_sent = Instant::now();
+        // A client that stops reading would otherwise hold its session open forever
+        match timeout(IO_TIMEOUT, write_message(&mut self.writer, &message)).await {
+            Ok(result) => result,
+            Err(_) => Err("Write timed out".into()),
+        }
+    }
+
+    async fn logout(&mut self, reason: &str) -> Result<Flow, Box<dyn std::error::Error>> {
+        let fields = vec![MessageField::Reason(reason.to_string())];
+        self.send((MessageType::Logout, fields)).await?;
+        Ok(Flow::Close)
+    }
+
+    async fn reject(&mut self, reason: String) -> Result<Flow, Box<dyn std::error::Error>> {
+        let fields = vec![MessageField::Reason(reason)];
+        self.send((MessageType::Reject, fields)).await?;
+        Ok(Flow::Continue)
+    }
+
+    async fn receive<T: OrderBook + Send + 'static>(
+        &mut self,
+        message: FixMessage,
+        shards: &Arc<EngineShards<T>>,
+        bus: &EventBus,
+        logged_on: &LoggedOn,
+    ) -> Result<Flow, Box<dyn std::error::Error>> {
+        let (message_type, fields) = message;
+        let (seqs, fields): (Vec<_>, Vec<_>) = fields
+            .into_iter()
+            .partition(|field| matches!(field, MessageField::MsgSeqNum(_)));
+        // Messages are never resent, so one missing from either direction ends the session
+        let expected = self.in_seq + 1;
+        match seqs.first() {
+            Some(&MessageField::MsgSeqNum(seq)) if seq == expected => self.in_seq = seq,
+            Some(&MessageField::MsgSeqNum(seq)) => {
+                let reason = format!("Expected MsgSeqNum {}, received {}", expected, seq);
+                return self.logout(&reason).await;
+            }
+            _ => return self.logout("MsgSeqNum missing").await,
+        }
+        self.last_received = Instant::now();
+        if self.logon.is_none() {
+            return match message_type {
+                MessageType::Logon => self.logon(&fields, logged_on).await,
+                _ => self.logout("First message must be a Logon").await,
+            };
+        }
+        match message_type {
+            MessageType::Logon => self.reject("Already logged on".to_string()).await,
+            MessageType::Heartbeat => {
+                let answered = self.test_request.as_ref().is_some_and(|(id, _)| {
+                    fields.contains(&MessageField::TestReqId(id.to_string()))
+                });
+                if answered {
+                    self.test_request = None;
+                }
+                Ok(Flow::Continue)
+            }
+            MessageType::TestRequest => {
+                let fields = fields
+                    .into_iter()
+                    .filter(|field| matches!(field, MessageField::TestReqId(_)))
+                    .collect();
+                self.send((MessageType::Heartbeat, fields)).await?;
+                Ok(Flow::Continue)
+            }
+            MessageType::Logout => self.logout("Logged out").await,
+            _ => self.request((message_type, fields), shards, bus).await,
+        }
+    }
+
+    async fn logon(
+        &mut self,
+        fields: &[MessageField],
+        logged_on: &LoggedOn,
+    ) -> Result<Flow, Box<dyn std::error::Error>> {
+        let mut comp_id = None;
+        let mut heartbeat = DEFAULT_HEARTBEAT;
+        for field in fields {
+            match field {
+                MessageField::SenderCompId(id) if !id.is_empty() => comp_id = Some(id.clone()),
+                MessageField::HeartbeatInterval(seconds) => heartbeat = *seconds,
+                _ => {}
+            }
+        }
+        let comp_id = match comp_id {
+            Some(comp_id) => comp_id,
+            None => return self.logout("Logon requires a SenderCompId").await,
+        };
+        if !(1..=MAX_HEARTBEAT).contains(&heartbeat) {
+            let reason = format!("Heartbeat interval must be 1 to {} seconds", MAX_HEARTBEAT);
+            return self.logout(&reason).await;
+        }
+        if !logged_on.lock().unwrap().insert(comp_id.clone()) {
+            return self.logout("Already logged on elsewhere").await;
+        }
+        println!("{} logged on", comp_id);
+        self.logon = Some(Logon {
+            comp_id,
+            logged_on: logged_on.clone(),
+        });
+        self.heartbeat = Duration::from_secs(heartbeat);
+        let fields = vec![MessageField::HeartbeatInterval(heartbeat)];
+        self.send((MessageType::Logon, fields)).await?;
+        Ok(Flow::Continue)
+    }
+
+    // Hands an application message to the engines and sends back their answer
+    async fn request<T: OrderBook + Send + 'static>(
+        &mut self,
+        message: FixMessage,
+        shards: &Arc<EngineShards<T>>,
+        bus: &EventBus,
+    ) -> Result<Flow, Box<dyn std::error::Error>> {
+        let unsubscribe = message.0 == MessageType::MarketDataRequest
+            && message.1.contains(&MessageField::Subscribe(false));
+        if unsubscribe {
+            for field in &message.1 {
+                if let MessageField::Symbol(symbol) = field {
+                    self.depth.remove(symbol);
+                }
+            }
+        }
+        let entering = matches!(
+            message.0,
+            MessageType::NewOrder | MessageType::OrderReplaceRequest
+        );
+        if entering {
+            // Subscribed before the order is entered so that none of its executions are missed
+            self.updates.get_or_insert_with(|| bus.subscribe());
+        }
+        // Executions in the response to these are not pushed again
+        let reporting = entering || message.0 == MessageType::OrderCancelRequest;
+        let result = engine_response(shards.clone(), bus, message)
+            .await
+            .map_err(|e| e.to_string());
+        let (response, subscription) = match result {
+            Ok(result) => result,
+            Err(reason) => return self.reject(reason).await,
+        };
+        if reporting && response.0 == MessageType::ExecutionReport {
+            self.follow_order(&response.1);
+        }
+        self.send(response).await?;
+        if let Some(subscription) = subscription {
+            // A receiver already open was subscribed before this book was read, so it
+            // misses nothing either
+            self.updates.get_or_insert(subscription.updates);
+            self.depth.insert(subscription.symbol, subscription.book);
+        }
+        Ok(Flow::Continue)
+    }
+
+    // Remembers the order an execution report answering a new order, amend or cancel is
+    // for, so that its later executions are pushed here and the ones in the report are not
+    fn follow_order(&mut self, fields: &[MessageField]) {
+        let (mut symbol, mut id, mut seq, mut live) = (None, None, None, true);
+        for field in fields {
+            match field {
+                MessageField::Symbol(s) => symbol = Some(s.clone()),
+                MessageField::OrderId(i) => id = Some(*i),
+                MessageField::SeqNum(s) => seq = Some(*s),
+                MessageField::Status(status) => live = !status.is_terminal(),
+                _ => {}
+            }
+        }
+        if let (Some(symbol), Some(id), Some(seq)) = (symbol, id, seq) {
+            let key = (symbol, id);
+            // An order this ends stays followed until the update that ended it arrives, so
+            // that updates still to come from before it are pushed
+            if live || self.orders.contains_key(&key) {
+                self.orders.entry(key).or_default().push(seq);
+            }
+        }
+    }
+
+    // Pushes what an update changed in each book followed, and reports the executions of
+    // orders entered here
+    async fn update(
+        &mut self,
+        update: Result<Arc<Update>, RecvError>,
+    ) -> Result<Flow, Box<dyn std::error::Error>> {
+        let update = match update {
+            Ok(update) => update,
+            Err(RecvError::Lagged(missed)) => {
+                // The books can no longer be kept in step, so the client has to subscribe
+                // again, and ask for the status of its orders. The receiver carries on from
+                // the oldest update still held.
+                self.depth.clear();
+                if self.orders.is_empty() {
+                    self.updates = None;
+                }
+                return self.reject(format!("Missed {} updates", missed)).await;
+            }
+            Err(RecvError::Closed) => {
+                self.updates = None;
+                return Ok(Flow::Continue);
+            }
+        };
+        let output = &update.output;
+        for execution in &update.executions {
+            let key = (output.symbol.clone(), execution.id);
+            let reported = match self.orders.get_mut(&key) {
+                Some(reported) => reported,
+                None => continue,
+            };
+            // The response to the command that entered or amended an order already reported
+            // its fills, and updates arrive in sequence, so none before this one are to come
+            let already_reported = reported.contains(&output.seq);
+            reported.retain(|&seq| seq > output.seq);
+            if execution.status.is_terminal() {
+                self.orders.remove(&key);
+            }
+            if already_reported {
+                continue;
+            }
+            let mut fields = vec![
+                MessageField::Symbol(output.symbol.clone()),
+                MessageField::OrderId(execution.id),
+                MessageField::ExecType(execution.exec_type),
+                MessageField::Status(execution.status),
+                MessageField::LastQuantity(execution.last_quantity),
+            ];
+            if let Some(price) = execution.last_price {
+                fields.push(MessageField::LastPrice(price));
+            }
+            if let Some(stop_price) = execution.stop_price {
+                fields.push(MessageField::StopPrice(stop_price));
+            }
+            fields.push(MessageField::CumQuantity(execution.cum_quantity));
+            fields.push(MessageField::LeavesQuantity(execution.leaves_quantity));
+            fields.push(MessageField::SeqNum(output.seq));
+            self.send((MessageType::ExecutionReport, fields)).await?;
+        }
+        let changes = match self.depth.get_mut(&output.symbol) {
+            Some(book) => book.apply(output),
+            None => return Ok(Flow::Continue),
+        };
+        if !changes.is_empty() {
+            let message = (
+                MessageType::MarketDataIncremental,
+                vec![
+                    MessageField::Symbol(output.symbol.clone()),
+                    MessageField::SeqNum(output.seq),
+                    MessageField::LevelUpdates(changes),
+                ],
+            );
+            self.send(message).await?;
+        }
+        Ok(Flow::Continue)
+    }
+
+    // Sends a heartbeat when nothing else has gone out for a heartbeat interval, and a
+    // test request when nothing has come in for a little longer. A client that does
+    // not answer the test request within another interval is logged out.
+    async fn tick(&mut self) -> Result<Flow, Box<dyn std::error::Error>> {
+        let now = Instant::now();
+        if self.logon.is_none() {
+            if now - self.connected >= LOGON_TIMEOUT {
+                return self.logout("Logon timed out").await;
+            }
+            return Ok(Flow::Continue);
+        }
+        if let Some((_, sent_at)) = &self.test_request {
+            if now - *sent_at >= self.heartbeat {
+                return self.logout("Heartbeat timed out").await;
+            }
+        } else if now - self.last_received >= self.heartbeat + self.heartbeat / 5 {
+            let id = self.out_seq.to_string();
+            self.test_request = Some((id.clone(), now));
+            let fields = vec![MessageField::TestReqId(id)];
+            self.send((MessageType::TestRequest, fields)).await?;
+            return Ok(Flow::Continue);
+        }
+        if now - self.last_sent >= self.heartbeat {
+            self.send((MessageType::Heartbeat, Vec::new())).await?;
+        }
+        Ok(Flow::Continue)
+    }
+}
+
+// Serves one connection until its session ends or the client goes away
+pub async fn serve_connection<T: OrderBook + Send + 'static>(
+    socket: TcpStream,
+    shards: Arc<EngineShards<T>>,
+    bus: Arc<EventBus>,
+    logged_on: LoggedOn,
+) {
+    let (reader, writer) = socket.into_split();
+    // Frames are read on their own task, as a read cut short by another branch of the
+    // select below would lose whatever part of a frame it had read
+    let (frames_tx, mut frames) = mpsc::channel(16);
+    let reading = tokio::spawn(read_frames(reader, frames_tx));
+    let mut session = Session::new(writer);
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        let result = tokio::select! {
+            frame = frames.recv() => match frame {
+                Some(Ok(frame)) => match decode_message(&frame) {
+                    Some(message) => session.receive(message, &shards, &bus, &logged_on).await,
+                    None => session.reject("Error decoding FixMessage".to_string()).await,
+                },
+                Some(Err(reason)) => session.logout(reason).await,
+                // A client that has closed its side has been answered everything it sent
+                // by now, as frames already read are handled before the channel ends
+                None => Ok(Flow::Close),
+            },
+            update = next_update(&mut session.updates) => session.update(update).await,
+            _ = ticker.tick() => session.tick().await,
+        };
+        match result {
+            Ok(Flow::Continue) => {}
+            Ok(Flow::Close) => break,
+            Err(e) => {
+                eprintln!("Error serving connection: {}", e);
+                break;
+            }
+        }
+    }
+    // The read half keeps the socket open until its task ends, however the session did
+    reading.abort();
+    let _ = timeout(IO_TIMEOUT, session.writer.shutdown()).await;
+}
+
+// Turns a connection away before serving it, e.g. when too many are open already
+pub async fn refuse_connection(mut socket: TcpStream, reason: &str) {
+    let message = (
+        MessageType::Logout,
+        vec![
+            MessageField::Reason(reason.to_string()),
+            MessageField::MsgSeqNum(1),
+        ],
+    );
+    let _ = timeout(IO_TIMEOUT, async {
+        let _ = write_message(&mut socket, &message).await;
+        let _ = socket.shutdown().await;
+    })
+    .await;
+}
+
+async fn next_update(
+    updates: &mut Option<broadcast::Receiver<Arc<Update>>>,
+) -> Result<Arc<Update>, RecvError> {
+    match updates {
+        Some(updates) => updates.recv().await,
+        None => pending().await,
+    }
+}
+
+// Reads length prefixed frames until the client closes its side, or until a frame that
+// cannot be read, which is passed on as the reason to end the session
+async fn read_frames(
+    mut reader: OwnedReadHalf,
+    frames: mpsc::Sender<Result<String, &'static str>>,
+) {
+    loop {
+        let msg_len = match reader.read_u32().await {
+            Ok(len) => len.to_be(),
+            Err(_) => return,
+        };
+        // Checked before allocating, as the length is whatever the client says it is
+        let frame = if msg_len > MAX_FRAME {
+            Err("Frame too large")
+        } else {
+            let mut buf = vec![0u8; msg_len as usize];
+            match timeout(IO_TIMEOUT, reader.read_exact(&mut buf)).await {
+                Ok(Ok(_)) => String::from_utf8(buf).map_err(|_| "Frame is not UTF-8"),
+                Ok(Err(_)) => return,
+                Err(_) => Err("Frame timed out"),
+            }
+        };
+        let done = frame.is_err();
+        if frames.send(frame).await.is_err() || done {
+            return;
+        }
+    }
+}
@@ -0,0 +1,464 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+    },
+    response::{
+        sse::{self, KeepAlive, Sse},
+        Response,
+    },
+    Json,
+};
+use futures::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use super::api::ApiState;
+use crate::core::{
+    bus::Update,
+    depth::DepthBook,
+    order_book::{Order, OrderBook, Side},
+    sequencer::{Event, Output},
+    shard::EngineShards,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Channel {
+    Trades,
+    Top,
+    // Aggregated price levels, a snapshot followed by incremental updates
+    Depth,
+    // Every displayed order, a snapshot followed by each add, modify, cancel and execution
+    Orders,
+    // Fills and status changes of the orders listed in `ids`. Nothing ties an order to the
+    // client that entered it, so any client can follow any order: like the other channels
+    // this is a public feed, and only TCP sessions are pushed just their own orders.
+    Executions,
+}
+
+#[derive(Deserialize)]
+struct Subscription {
+    channel: Channel,
+    symbol: String,
+    #[serde(default)]
+    ids: Vec<u64>,
+    // Levels per side on the depth channel
+    depth: Option<usize>,
+}
+
+const DEFAULT_DEPTH: usize = 10;
+
+// e.g. {"op": "subscribe", "channel": "executions", "symbol": "XYZ", "ids": [1, 2]}
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Request {
+    Subscribe(Subscription),
+    Unsubscribe(Subscription),
+}
+
+#[derive(Default)]
+struct Subscriptions {
+    // Order ids followed on the executions channel, empty for every other channel
+    channels: HashMap<(Channel, String), HashSet<u64>>,
+    // Each depth subscription's view of the book, by symbol
+    depth: HashMap<String, DepthBook>,
+    // Sequence number each market-by-order snapshot is as of, by symbol
+    orders: HashMap<String, u64>,
+}
+
+impl Subscriptions {
+    fn get(&self, channel: Channel, symbol: &str) -> Option<&HashSet<u64>> {
+        self.channels.get(&(channel, symbol.to_string()))
+    }
+}
+
+// Pushes each client the events it has subscribed to as matching threads publish them
+pub async fn get_stream<T: OrderBook + Send + 'static>(
+    ws: WebSocketUpgrade,
+    State(state): State<ApiState<T>>,
+) -> Response {
+    let updates = state.bus.subscribe();
+    ws.on_upgrade(move |socket| stream_updates(socket, state.shards, updates))
+}
+
+async fn stream_updates<T: OrderBook + Send + 'static>(
+    mut socket: WebSocket,
+    shards: Arc<EngineShards<T>>,
+    mut updates: broadcast::Receiver<Arc<Update>>,
+) {
+    let mut subscriptions = Subscriptions::default();
+    loop {
+        let messages = tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    handle_request(&text, &shards, &mut subscriptions).await
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+            update = updates.recv() => match update {
+                Ok(update) => update_messages(&update, &mut subscriptions),
+                Err(RecvError::Lagged(missed)) => {
+                    vec![json!({ "error": format!("Missed {} updates", missed) })]
+                }
+                Err(RecvError::Closed) => break,
+            },
+        };
+        for message in messages {
+            if socket
+                .send(Message::Text(message.to_string()))
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+}
+
+async fn handle_request<T: OrderBook + Send + 'static>(
+    text: &str,
+    shards: &EngineShards<T>,
+    subscriptions: &mut Subscriptions,
+) -> Vec<Value> {
+    let request: Request = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(e) => return vec![json!({ "error": e.to_string() })],
+    };
+    match request {
+        Request::Subscribe(subscription) => {
+            let handle = match shards.get(&subscription.symbol) {
+                Some(handle) => handle,
+                None => return vec![json!({ "error": "Unknown symbol" })],
+            };
+            let key = (subscription.channel, subscription.symbol.clone());
+            subscriptions
+                .channels
+                .entry(key)
+                .or_default()
+                .extend(subscription.ids);
+            let mut messages = vec![json!({
+                "subscribed": subscription.channel,
+                "symbol": subscription.symbol,
+            })];
+            // Top of book subscribers start from the current best prices
+            if subscription.channel == Channel::Top {
+                let top = handle
+                    .execute(|engine| {
+                        (
+                            engine.get_top_of_book(Side::Buy),
+                            engine.get_top_of_book(Side::Sell),
+                        )
+                    })
+                    .await;
+                messages.push(match top {
+                    Ok((bid, ask)) => json!({
+                        "channel": "top",
+                        "symbol": subscription.symbol,
+                        "bid": bid,
+                        "ask": ask,
+                    }),
+                    Err(e) => json!({ "error": e }),
+                });
+            }
+            // Depth subscribers get the book as of a sequence number, then every change after it
+            if subscription.channel == Channel::Depth {
+                let levels = handle
+                    .execute_as_of(|engine| {
+                        (
+                            engine.get_depth(Side::Buy, usize::MAX),
+                            engine.get_depth(Side::Sell, usize::MAX),
+                        )
+                    })
+                    .await;
+                let depth = subscription.depth.unwrap_or(DEFAULT_DEPTH);
+                messages.push(match levels {
+                    Ok((seq, (bids, asks))) => {
+                        let book = DepthBook::new(depth, seq, &bids, &asks);
+                        let snapshot = depth_snapshot(&subscription.symbol, &book);
+                        subscriptions
+                            .depth
+                            .insert(subscription.symbol.clone(), book);
+                        snapshot
+                    }
+                    Err(e) => json!({ "error": e }),
+                });
+            }
+            // As are market-by-order subscribers, with every displayed order in queue order
+            if subscription.channel == Channel::Orders {
+                let orders = handle
+                    .execute_as_of(|engine| {
+                        (
+                            engine.get_displayed_orders(Side::Buy),
+                            engine.get_displayed_orders(Side::Sell),
+                        )
+                    })
+                    .await;
+                messages.push(match orders {
+                    Ok((seq, (bids, asks))) => {
+                        subscriptions
+                            .orders
+                            .insert(subscription.symbol.clone(), seq);
+                        orders_snapshot(&subscription.symbol, seq, &bids, &asks)
+                    }
+                    Err(e) => json!({ "error": e }),
+                });
+            }
+            messages
+        }
+        Request::Unsubscribe(subscription) => {
+            let key = (subscription.channel, subscription.symbol.clone());
+            // Unsubscribing from some ids keeps following the rest
+            if let Some(ids) = subscriptions.channels.get_mut(&key) {
+                ids.retain(|id| !subscription.ids.contains(id));
+                if subscription.ids.is_empty() || ids.is_empty() {
+                    subscriptions.channels.remove(&key);
+                }
+            }
+            if subscription.channel == Channel::Depth {
+                subscriptions.depth.remove(&subscription.symbol);
+            }
+            if subscription.channel == Channel::Orders {
+                subscriptions.orders.remove(&subscription.symbol);
+            }
+            vec![json!({
+                "unsubscribed": subscription.channel,
+                "symbol": subscription.symbol,
+            })]
+        }
+    }
+}
+
+// What one update means for a client with these subscriptions
+fn depth_snapshot(symbol: &str, book: &DepthBook) -> Value {
+    json!({
+        "channel": "depth",
+        "type": "snapshot",
+        "symbol": symbol,
+        "seq": book.seq(),
+        "depth": book.depth(),
+        "bids": book.levels(Side::Buy),
+        "asks": book.levels(Side::Sell),
+    })
+}
+
+fn update_messages(update: &Update, subscriptions: &mut Subscriptions) -> Vec<Value> {
+    let output = &update.output;
+    let subscribed = |channel| subscriptions.get(channel, &output.symbol);
+    let mut messages = Vec::new();
+    if subscribed(Channel::Trades).is_some() {
+        for event in &output.events {
+            if let Event::Fill(fill) = event {
+                messages.push(json!({
+                    "channel": "trades",
+                    "symbol": output.symbol,
+                    "seq": output.seq,
+                    "quantity": fill.quantity,
+                    "price": fill.price,
+                    "bid_id": fill.bid_id,
+                    "ask_id": fill.ask_id,
+                }));
+            }
+        }
+    }
+    let book_changed = output
+        .events
+        .iter()
+        .any(|event| matches!(event, Event::BookDelta { .. }));
+    if book_changed && subscribed(Channel::Top).is_some() {
+        messages.push(json!({
+            "channel": "top",
+            "symbol": output.symbol,
+            "seq": output.seq,
+            "bid": update.best_bid,
+            "ask": update.best_ask,
+        }));
+    }
+    if let Some(ids) = subscribed(Channel::Executions) {
+        for event in &output.events {
+            match event {
+                Event::Fill(fill) => {
+                    for id in [fill.bid_id, fill.ask_id] {
+                        if ids.contains(&id) {
+                            messages.push(json!({
+                                "channel": "executions",
+                                "symbol": output.symbol,
+                                "seq": output.seq,
+                                "id": id,
+                                "event": "fill",
+                                "quantity": fill.quantity,
+                                "price": fill.price,
+                            }));
+                        }
+                    }
+                }
+                Event::OrderUpdate {
+                    id,
+                    status,
+                    filled_quantity,
+                    leaves_quantity,
+                } if ids.contains(id) => {
+                    messages.push(json!({
+                        "channel": "executions",
+                        "symbol": output.symbol,
+                        "seq": output.seq,
+                        "id": id,
+                        "event": "update",
+                        "status": status,
+                        "filled_quantity": filled_quantity,
+                        "leaves_quantity": leaves_quantity,
+                    }));
+                }
+                Event::StopMoved { id, stop_price } if ids.contains(id) => {
+                    messages.push(json!({
+                        "channel": "executions",
+                        "symbol": output.symbol,
+                        "seq": output.seq,
+                        "id": id,
+                        "event": "stop_moved",
+                        "stop_price": stop_price,
+                    }));
+                }
+                _ => {}
+            }
+        }
+    }
+    if let Some(book) = subscriptions.depth.get_mut(&output.symbol) {
+        let changes = book.apply(output);
+        if !changes.is_empty() {
+            messages.push(json!({
+                "channel": "depth",
+                "type": "update",
+                "symbol": output.symbol,
+                "seq": output.seq,
+                "changes": changes,
+            }));
+        }
+    }
+    match subscriptions.orders.get(&output.symbol) {
+        Some(&seq) if output.seq > seq => messages.extend(orders_update(output)),
+        _ => {}
+    }
+    messages
+}
+
+// Market-by-order messages, shared with the journal replay of the feed
+pub fn orders_snapshot(symbol: &str, seq: u64, bids: &[Order], asks: &[Order]) -> Value {
+    let orders = |orders: &[Order]| -> Vec<Value> {
+        orders
+            .iter()
+            .map(|order| {
+                json!({
+                    "id": order.id,
+                    "price": order.price,
+                    "remaining": order.leaves_quantity,
+                })
+            })
+            .collect()
+    };
+    json!({
+        "channel": "orders",
+        "type": "snapshot",
+        "symbol": symbol,
+        "seq": seq,
+        "bids": orders(bids),
+        "asks": orders(asks),
+    })
+}
+
+pub fn orders_update(output: &Output) -> Option<Value> {
+    let changes: Vec<Value> = output
+        .events
+        .iter()
+        .filter_map(|event| match event {
+            Event::BookOrder {
+                action,
+                id,
+                side,
+                price,
+                quantity,
+                remaining,
+            } => Some(json!({
+                "action": action,
+                "id": id,
+                "side": side,
+                "price": price,
+                "quantity": quantity,
+                "remaining": remaining,
+            })),
+            _ => None,
+        })
+        .collect();
+    if changes.is_empty() {
+        return None;
+    }
+    Some(json!({
+        "channel": "orders",
+        "type": "update",
+        "symbol": output.symbol,
+        "seq": output.seq,
+        "changes": changes,
+    }))
+}
+
+// Pushes execution reports for the orders listed in `ids` as server-sent events, e.g.
+// `/executions/XYZ?ids=1,2`, until the client goes away. As on the executions channel, the
+// ids are not checked against who entered the orders, so this is an unscoped feed.
+pub async fn get_executions<T: OrderBook + Send + 'static>(
+    State(state): State<ApiState<T>>,
+    Path(symbol): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Sse<impl Stream<Item = Result<sse::Event, Infallible>>>, Json<Value>> {
+    if state.shards.get(&symbol).is_none() {
+        return Err(Json(json!({ "error": "Unknown symbol" })));
+    }
+    let ids: HashSet<u64> = match params.get("ids") {
+        Some(ids) => ids
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| Json(json!({ "error": "Invalid order id" })))?,
+        None => {
+            return Err(Json(
+                json!({ "error": "ids must list the orders to follow" }),
+            ))
+        }
+    };
+    let updates = state.bus.subscribe();
+    let events = stream::unfold(updates, move |mut updates| {
+        let (symbol, ids) = (symbol.clone(), ids.clone());
+        async move {
+            loop {
+                let events: Vec<_> = match updates.recv().await {
+                    Ok(update) if update.output.symbol == symbol => update
+                        .executions
+                        .iter()
+                        .filter(|execution| ids.contains(&execution.id))
+                        .map(|execution| {
+                            let mut report = json!(execution);
+                            report["symbol"] = json!(symbol);
+                            report["seq"] = json!(update.output.seq);
+                            sse::Event::default()
+                                .event("execution")
+                                .data(report.to_string())
+                        })
+                        .collect(),
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(missed)) => vec![sse::Event::default()
+                        .event("error")
+                        .data(format!("Missed {} updates", missed))],
+                    Err(RecvError::Closed) => return None,
+                };
+                if !events.is_empty() {
+                    return Some((stream::iter(events.into_iter().map(Ok)), updates));
+                }
+            }
+        }
+    });
+    Ok(Sse::new(events.flatten()).keep_alive(KeepAlive::default()))
+}
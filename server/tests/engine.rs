@@ -1,16 +1,40 @@
 #[cfg(test)]
 mod tests {
+    use server::core::btree::BTreeOrderBook;
+    use server::core::bus::EventBus;
+    use server::core::candles::{Interval, PriceDatum};
     use server::core::engine::Engine;
-    use server::core::order_book::{Order, OrderBook, Side};
+    use server::core::execution::{executions, ExecType};
+    use server::core::instrument::{Instrument, InstrumentRegistry};
+    use server::core::order_book::{
+        Fill, MarketRemainder, NewOrder, Order, OrderBook, OrderType, PostOnlyPolicy, PriceLevel,
+        Side, TimeInForce, TrailingOffset,
+    };
+    use server::core::price::Price;
+    use server::core::sequencer::{Command, Event, OrderAction, Output};
+    use server::core::shard::EngineShards;
     use server::core::vector::VectorOrderBook;
+    use server::net::fix::OrderStatus;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use time::{Duration, OffsetDateTime};
+
+    fn px(price: &str) -> Price {
+        price.parse().unwrap()
+    }
+
+    fn trade<T: OrderBook>(n: &mut Engine<T>, price: &str) {
+        n.add_order("Sell".to_string(), 1, px(price)).unwrap();
+        n.add_order("Buy".to_string(), 1, px(price)).unwrap();
+    }
 
     #[test]
     fn order_book() {
         let mut book: VectorOrderBook = VectorOrderBook::new();
-        let order1 = Order::new(1, Side::Buy, 50, 100.0);
-        let order2 = Order::new(2, Side::Buy, 100, 99.0);
-        let order3 = Order::new(3, Side::Sell, 50, 101.0);
-        let order4 = Order::new(4, Side::Sell, 50, 102.0);
+        let order1 = Order::new(1, Side::Buy, 50, px("100"));
+        let order2 = Order::new(2, Side::Buy, 100, px("99"));
+        let order3 = Order::new(3, Side::Sell, 50, px("101"));
+        let order4 = Order::new(4, Side::Sell, 50, px("102"));
         book.add_bid_order(order1);
         book.add_bid_order(order2);
         book.add_ask_order(order3);
@@ -22,12 +46,966 @@ mod tests {
     #[allow(unused_must_use)]
     fn engine() {
         let mut n = Engine::<VectorOrderBook>::new();
-        n.add_order("Buy".to_string(), 50, 100.0);
-        n.add_order("Buy".to_string(), 100, 99.0);
-        n.add_order("Sell".to_string(), 50, 101.0);
-        n.add_order("Sell".to_string(), 50, 102.0);
-        n.display();
-        n.match_orders();
+        n.add_order("Buy".to_string(), 50, px("100"));
+        n.add_order("Buy".to_string(), 100, px("99"));
+        n.add_order("Sell".to_string(), 50, px("101"));
+        n.add_order("Sell".to_string(), 50, px("102"));
         n.display();
     }
+
+    #[test]
+    fn orders_match_on_entry() {
+        let mut n = Engine::<BTreeOrderBook>::new();
+        n.add_order("Sell".to_string(), 50, px("101")).unwrap();
+        n.add_order("Sell".to_string(), 50, px("102")).unwrap();
+        n.add_order("Sell".to_string(), 50, px("103")).unwrap();
+        let (id, fills) = n.add_order("Buy".to_string(), 80, px("102.5")).unwrap();
+        assert_eq!(
+            fills,
+            vec![
+                Fill {
+                    bid_id: id,
+                    ask_id: 1,
+                    quantity: 50,
+                    price: px("101"),
+                },
+                Fill {
+                    bid_id: id,
+                    ask_id: 2,
+                    quantity: 30,
+                    price: px("102"),
+                },
+            ]
+        );
+        assert_eq!(n.get_volume_at_limit(px("102")), 20);
+
+        let (_, fills) = n.add_order("Buy".to_string(), 10, px("100")).unwrap();
+        assert!(fills.is_empty());
+        let (_, fills) = n.amend_order(3, 50, px("100")).unwrap();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].price, px("100"));
+        assert_eq!(n.trades.len(), 3);
+    }
+
+    #[test]
+    fn off_tick_prices_are_rejected() {
+        let mut n = Engine::<VectorOrderBook>::with_tick_size(px("0.05"));
+        assert!(n.add_order("Buy".to_string(), 10, px("100.05")).is_ok());
+        assert!(n.add_order("Buy".to_string(), 10, px("100.07")).is_err());
+        assert!(n.add_order("Sell".to_string(), 10, px("0")).is_err());
+        assert!(n.amend_order(1, 10, px("100.01")).is_err());
+        assert_eq!(n.get_volume_at_limit(px("100.05")), 10);
+    }
+
+    #[test]
+    fn partially_filled_status() {
+        let mut n = Engine::<VectorOrderBook>::new();
+        let (bid, _) = n.add_order("Buy".to_string(), 50, px("100")).unwrap();
+        let (ask, fills) = n.add_order("Sell".to_string(), 20, px("99")).unwrap();
+        assert_eq!(fills[0].price, px("100"));
+        assert_eq!(
+            n.get_execution_status(bid),
+            Ok(OrderStatus::PartiallyFilled)
+        );
+        assert_eq!(n.get_execution_status(ask), Ok(OrderStatus::Filled));
+        assert!(n
+            .get_order_status(bid)
+            .unwrap()
+            .starts_with("PartiallyFilled -- Buy 30/50"));
+        n.add_order("Sell".to_string(), 30, px("100")).unwrap();
+        assert_eq!(n.get_execution_status(bid), Ok(OrderStatus::Filled));
+    }
+
+    #[test]
+    fn order_lifecycle() {
+        let mut n = Engine::<BTreeOrderBook>::new();
+        assert!(n.get_execution_status(1).is_err());
+
+        let (bid, _) = n.add_order("Buy".to_string(), 50, px("100")).unwrap();
+        assert_eq!(n.get_execution_status(bid), Ok(OrderStatus::New));
+        n.add_order("Sell".to_string(), 10, px("100")).unwrap();
+        assert_eq!(
+            n.get_execution_status(bid),
+            Ok(OrderStatus::PartiallyFilled)
+        );
+        // An amend keeps the fill state, and only an unfilled order is reported replaced
+        n.amend_order(bid, 30, px("99")).unwrap();
+        assert_eq!(
+            n.get_execution_status(bid),
+            Ok(OrderStatus::PartiallyFilled)
+        );
+        let (ask, _) = n.add_order("Sell".to_string(), 10, px("101")).unwrap();
+        n.amend_order(ask, 20, px("101")).unwrap();
+        assert_eq!(n.get_execution_status(ask), Ok(OrderStatus::Replaced));
+        n.cancel_order(bid).unwrap();
+
+        let record = n.get_order_record(bid).unwrap();
+        assert_eq!(record.status, OrderStatus::Cancelled);
+        assert_eq!(record.order.filled_quantity, 10);
+        assert_eq!(record.order.leaves_quantity, 0);
+        assert_eq!(record.average_price(), Some(px("100")));
+        assert!(n.cancel_order(bid).is_err());
+        assert!(n.amend_order(bid, 40, px("99")).is_err());
+    }
+
+    #[test]
+    fn market_orders() {
+        let mut n = Engine::<VectorOrderBook>::new();
+        n.add_order("Sell".to_string(), 50, px("101")).unwrap();
+        n.add_order("Sell".to_string(), 50, px("102")).unwrap();
+        let (id, fills) = n.submit_order(NewOrder::market(Side::Buy, 120)).unwrap();
+        assert_eq!(fills.len(), 2);
+        assert_eq!(fills[1].price, px("102"));
+        let record = n.get_order_record(id).unwrap();
+        assert_eq!(record.status, OrderStatus::Cancelled);
+        assert_eq!(record.order.filled_quantity, 100);
+        assert_eq!(record.order.order_type, OrderType::Market);
+        assert!(n.get_book().0[0].quantity == 0);
+
+        // With nothing to trade against a market order is cancelled outright
+        let (id, fills) = n.submit_order(NewOrder::market(Side::Sell, 10)).unwrap();
+        assert!(fills.is_empty());
+        assert_eq!(n.get_execution_status(id), Ok(OrderStatus::Cancelled));
+        assert!(n.submit_order(NewOrder::market(Side::Sell, 0)).is_err());
+    }
+
+    #[test]
+    fn market_remainder_rests_as_limit() {
+        let mut n = Engine::<BTreeOrderBook>::new();
+        n.market_remainder = MarketRemainder::Limit;
+        n.add_order("Buy".to_string(), 30, px("100")).unwrap();
+        n.add_order("Buy".to_string(), 30, px("99.5")).unwrap();
+        let (id, fills) = n.submit_order(NewOrder::market(Side::Sell, 80)).unwrap();
+        assert_eq!(fills.len(), 2);
+
+        let record = n.get_order_record(id).unwrap();
+        assert_eq!(record.status, OrderStatus::PartiallyFilled);
+        assert_eq!(record.order.order_type, OrderType::Limit);
+        assert_eq!(record.order.price, px("99.5"));
+        let ask = n.get_book().1[0];
+        assert_eq!(
+            (ask.id, ask.leaves_quantity, ask.price),
+            (id, 20, px("99.5"))
+        );
+        assert_eq!(n.get_volume_at_limit(px("99.5")), 20);
+    }
+
+    #[test]
+    fn immediate_or_cancel_and_fill_or_kill() {
+        let mut n = Engine::<BTreeOrderBook>::new();
+        n.add_order("Sell".to_string(), 50, px("101")).unwrap();
+        n.add_order("Sell".to_string(), 50, px("102")).unwrap();
+
+        let fok =
+            NewOrder::limit(Side::Buy, 120, px("102")).with_time_in_force(TimeInForce::Fok, None);
+        let (id, fills) = n.submit_order(fok).unwrap();
+        assert!(fills.is_empty());
+        assert_eq!(n.get_execution_status(id), Ok(OrderStatus::Cancelled));
+        assert_eq!(n.get_volume_at_limit(px("101")), 50);
+
+        let fok =
+            NewOrder::limit(Side::Buy, 60, px("102")).with_time_in_force(TimeInForce::Fok, None);
+        let (id, fills) = n.submit_order(fok).unwrap();
+        assert_eq!(fills.len(), 2);
+        assert_eq!(n.get_execution_status(id), Ok(OrderStatus::Filled));
+
+        let ioc =
+            NewOrder::limit(Side::Buy, 60, px("102")).with_time_in_force(TimeInForce::Ioc, None);
+        let (id, fills) = n.submit_order(ioc).unwrap();
+        assert_eq!(fills[0].quantity, 40);
+        let record = n.get_order_record(id).unwrap();
+        assert_eq!(record.status, OrderStatus::Cancelled);
+        assert_eq!(record.order.filled_quantity, 40);
+        assert!(n.book.get_order_by_id(id).is_err());
+    }
+
+    #[test]
+    fn good_till_date_and_day_orders_expire() {
+        let mut n = Engine::<VectorOrderBook>::new();
+        let now = OffsetDateTime::now_utc();
+        let expire_at = now.unix_timestamp() + 60;
+
+        let gtd = NewOrder::limit(Side::Buy, 10, px("100"));
+        assert!(n
+            .submit_order(gtd.with_time_in_force(TimeInForce::Gtd, None))
+            .is_err());
+        assert!(n
+            .submit_order(gtd.with_time_in_force(TimeInForce::Gtd, Some(now.unix_timestamp() - 1)))
+            .is_err());
+        let (gtd, _) = n
+            .submit_order(gtd.with_time_in_force(TimeInForce::Gtd, Some(expire_at)))
+            .unwrap();
+        let day =
+            NewOrder::limit(Side::Sell, 10, px("101")).with_time_in_force(TimeInForce::Day, None);
+        let (day, _) = n.submit_order(day).unwrap();
+        let (gtc, _) = n.add_order("Sell".to_string(), 10, px("102")).unwrap();
+
+        assert!(n.expire_orders(now).is_empty());
+        assert_eq!(n.expire_orders(now + Duration::seconds(60)), vec![gtd]);
+        assert_eq!(n.get_execution_status(gtd), Ok(OrderStatus::Expired));
+        assert_eq!(n.get_volume_at_limit(px("100")), 0);
+
+        assert_eq!(n.expire_orders(now + Duration::days(1)), vec![day]);
+        assert_eq!(n.get_execution_status(gtc), Ok(OrderStatus::New));
+        assert!(n.expire_orders(now + Duration::days(2)).is_empty());
+    }
+
+    #[test]
+    fn finished_orders_are_retired() {
+        let mut n = Engine::<BTreeOrderBook>::new();
+        let now = OffsetDateTime::now_utc();
+        let day =
+            NewOrder::limit(Side::Buy, 10, px("100")).with_time_in_force(TimeInForce::Day, None);
+        n.apply(Command::New(day), now);
+        assert!(n.next_expiry().is_some());
+        // A filled order is no longer due to expire, but can still be looked up
+        n.apply(
+            Command::New(NewOrder::limit(Side::Sell, 10, px("100"))),
+            now,
+        );
+        assert_eq!(n.next_expiry(), None);
+        assert_eq!(n.get_execution_status(1), Ok(OrderStatus::Filled));
+
+        // Only the latest 10,000 finished orders are kept
+        for id in 3..10_004 {
+            n.apply(Command::New(NewOrder::limit(Side::Buy, 1, px("99"))), now);
+            n.apply(Command::Cancel { id }, now);
+        }
+        assert_eq!(n.orders.len(), 10_001);
+        assert!(n.get_execution_status(1).is_err());
+        assert_eq!(n.get_execution_status(3), Ok(OrderStatus::Cancelled));
+        assert_eq!(n.get_execution_status(10_003), Ok(OrderStatus::Cancelled));
+
+        let mut restored = Engine::<BTreeOrderBook>::new();
+        restored.restore(n.snapshot(0));
+        assert_eq!(restored.snapshot(0), n.snapshot(0));
+    }
+
+    #[test]
+    fn stop_orders_trigger_on_last_trade() {
+        let mut n = Engine::<BTreeOrderBook>::new();
+        n.add_order("Buy".to_string(), 10, px("99")).unwrap();
+        n.add_order("Buy".to_string(), 10, px("98")).unwrap();
+        n.add_order("Sell".to_string(), 10, px("101")).unwrap();
+        let (stop, fills) = n
+            .submit_order(NewOrder::stop(Side::Sell, 10, px("99")))
+            .unwrap();
+        assert!(fills.is_empty());
+        let (stop_limit, _) = n
+            .submit_order(NewOrder::stop_limit(Side::Sell, 20, px("98"), px("97.5")))
+            .unwrap();
+        assert_eq!(n.stops.len(), 2);
+        assert_eq!(n.get_volume_at_limit(px("99")), 10);
+
+        // The sell at 99 triggers the stop, whose fill at 98 triggers the stop-limit
+        n.add_order("Sell".to_string(), 5, px("99")).unwrap();
+        assert!(n.stops.is_empty());
+        let record = n.get_order_record(stop).unwrap();
+        assert_eq!(record.status, OrderStatus::Filled);
+        assert_eq!(record.fills[0].price, px("99"));
+        assert_eq!(record.fills[1].price, px("98"));
+        let record = n.get_order_record(stop_limit).unwrap();
+        assert_eq!(record.status, OrderStatus::PartiallyFilled);
+        assert_eq!(record.order.leaves_quantity, 15);
+        assert_eq!(n.get_book().1[0].id, stop_limit);
+        assert_eq!(n.get_book().1[0].price, px("97.5"));
+    }
+
+    #[test]
+    fn stop_orders_can_be_cancelled_and_amended() {
+        let mut n = Engine::<VectorOrderBook>::new();
+        assert!(n
+            .submit_order(NewOrder {
+                stop_price: None,
+                ..NewOrder::stop(Side::Buy, 10, px("101"))
+            })
+            .is_err());
+        let (stop, _) = n
+            .submit_order(NewOrder::stop_limit(Side::Buy, 10, px("101"), px("102")))
+            .unwrap();
+        n.amend_order(stop, 15, px("102.5")).unwrap();
+        assert_eq!(n.stops.get(stop).unwrap().price, px("102.5"));
+        assert_eq!(n.get_execution_status(stop), Ok(OrderStatus::Replaced));
+        n.cancel_order(stop).unwrap();
+        assert!(n.stops.is_empty());
+
+        // A stop the market has already printed through is rejected rather than entered
+        n.add_order("Sell".to_string(), 10, px("101")).unwrap();
+        n.add_order("Buy".to_string(), 5, px("101")).unwrap();
+        assert!(n
+            .submit_order(NewOrder::stop(Side::Buy, 5, px("100")))
+            .is_err());
+        assert!(n
+            .submit_order(NewOrder::stop(Side::Sell, 5, px("101")))
+            .is_err());
+        assert!(n.stops.is_empty());
+
+        // Otherwise it waits for a later trade through its stop price
+        let (stop, fills) = n
+            .submit_order(NewOrder::stop(Side::Buy, 5, px("101.5")))
+            .unwrap();
+        assert!(fills.is_empty());
+        n.add_order("Buy".to_string(), 5, px("101")).unwrap();
+        assert_eq!(n.get_execution_status(stop), Ok(OrderStatus::New));
+        n.add_order("Sell".to_string(), 10, px("102")).unwrap();
+        n.add_order("Buy".to_string(), 1, px("102")).unwrap();
+        assert_eq!(n.get_execution_status(stop), Ok(OrderStatus::Filled));
+        assert_eq!(n.get_volume_at_limit(px("102")), 4);
+    }
+
+    #[test]
+    fn trailing_stops_ratchet_and_trigger() {
+        let mut n = Engine::<BTreeOrderBook>::new();
+        let offset = TrailingOffset::Amount(px("2"));
+        assert!(n
+            .submit_order(NewOrder::trailing_stop(Side::Sell, 10, offset))
+            .is_err());
+
+        trade(&mut n, "100");
+        let (id, _) = n
+            .submit_order(NewOrder::trailing_stop(Side::Sell, 10, offset))
+            .unwrap();
+        assert_eq!(n.stops.get(id).unwrap().stop_price, Some(px("98")));
+        trade(&mut n, "101");
+        trade(&mut n, "103");
+        trade(&mut n, "102");
+        assert_eq!(
+            n.take_trigger_updates(),
+            vec![(id, px("99")), (id, px("101"))]
+        );
+        assert_eq!(
+            n.get_order_record(id).unwrap().order.stop_price,
+            Some(px("101"))
+        );
+
+        // Retracing to the trigger enters the stop as a market order
+        n.add_order("Buy".to_string(), 10, px("100.5")).unwrap();
+        trade(&mut n, "101");
+        let record = n.get_order_record(id).unwrap();
+        assert_eq!(record.status, OrderStatus::Filled);
+        assert_eq!(record.fills[0].price, px("100.5"));
+        assert!(n.stops.is_empty());
+    }
+
+    #[test]
+    fn trailing_stop_percentage_offset() {
+        let mut n = Engine::<VectorOrderBook>::new();
+        trade(&mut n, "100");
+        let zero = TrailingOffset::Amount(Price::ZERO);
+        assert!(n
+            .submit_order(NewOrder::trailing_stop(Side::Buy, 5, zero))
+            .is_err());
+        // Offsets that would take the trigger beyond the range of a price are refused
+        let huge = TrailingOffset::Amount(Price::MAX);
+        assert!(n
+            .submit_order(NewOrder::trailing_stop(Side::Buy, 5, huge))
+            .is_err());
+        let tick = px("0.01");
+        let one = TrailingOffset::Amount(px("1"));
+        assert_eq!(one.trigger(Side::Buy, Price::MAX, tick), None);
+        assert_eq!(one.trigger(Side::Sell, px("100"), tick), Some(px("99")));
+        let offset = TrailingOffset::Percent(px("1"));
+        let (id, _) = n
+            .submit_order(NewOrder::trailing_stop(Side::Buy, 5, offset))
+            .unwrap();
+        assert_eq!(n.stops.get(id).unwrap().stop_price, Some(px("101")));
+        trade(&mut n, "95.5");
+        assert_eq!(n.take_trigger_updates(), vec![(id, px("96.46"))]);
+        assert_eq!(n.get_execution_status(id), Ok(OrderStatus::New));
+    }
+
+    fn iceberg_orders<T: OrderBook>() {
+        let mut n = Engine::<T>::new();
+        let (iceberg, _) = n
+            .submit_order(NewOrder::iceberg(Side::Sell, 100, px("101"), 20))
+            .unwrap();
+        let (plain, _) = n.add_order("Sell".to_string(), 30, px("101")).unwrap();
+        assert_eq!(n.get_book().1[0].leaves_quantity, 20);
+        assert_eq!(n.get_volume_at_limit(px("101")), 50);
+
+        // Filling the peak replenishes it from the reserve behind the other order
+        let (_, fills) = n.add_order("Buy".to_string(), 25, px("101")).unwrap();
+        assert_eq!(
+            fills
+                .iter()
+                .map(|f| (f.ask_id, f.quantity))
+                .collect::<Vec<_>>(),
+            vec![(iceberg, 20), (plain, 5)]
+        );
+        let asks = n.get_book().1;
+        assert_eq!(
+            [
+                (asks[0].id, asks[0].leaves_quantity),
+                (asks[1].id, asks[1].leaves_quantity)
+            ],
+            [(plain, 25), (iceberg, 20)]
+        );
+        let record = n.get_order_record(iceberg).unwrap();
+        assert_eq!(record.status, OrderStatus::PartiallyFilled);
+        assert_eq!(record.order.leaves_quantity, 80);
+        n.cancel_order(iceberg).unwrap();
+        assert_eq!(n.get_volume_at_limit(px("101")), 25);
+
+        // An incoming iceberg trades its full size before showing only its peak
+        let (bid, fills) = n
+            .submit_order(NewOrder::iceberg(Side::Buy, 60, px("101"), 10))
+            .unwrap();
+        assert_eq!(fills[0].quantity, 25);
+        assert_eq!(n.get_book().0[0].leaves_quantity, 10);
+        n.amend_order(bid, 80, px("100")).unwrap();
+        assert_eq!(n.get_order_record(bid).unwrap().order.leaves_quantity, 55);
+        assert_eq!(n.get_volume_at_limit(px("100")), 10);
+
+        assert!(n
+            .submit_order(NewOrder::iceberg(Side::Buy, 60, px("100"), 0))
+            .is_err());
+        assert!(n
+            .submit_order(NewOrder {
+                display_quantity: Some(10),
+                ..NewOrder::market(Side::Buy, 60)
+            })
+            .is_err());
+
+        // A fill or kill order counts the reserve behind an iceberg's peak
+        let fok =
+            NewOrder::limit(Side::Sell, 50, px("100")).with_time_in_force(TimeInForce::Fok, None);
+        let (id, fills) = n.submit_order(fok).unwrap();
+        assert_eq!(fills.iter().map(|f| f.quantity).sum::<u64>(), 50);
+        assert_eq!(n.get_execution_status(id), Ok(OrderStatus::Filled));
+        let fok =
+            NewOrder::limit(Side::Sell, 10, px("100")).with_time_in_force(TimeInForce::Fok, None);
+        let (id, fills) = n.submit_order(fok).unwrap();
+        assert!(fills.is_empty());
+        assert_eq!(n.get_execution_status(id), Ok(OrderStatus::Cancelled));
+        assert_eq!(n.get_order_record(bid).unwrap().order.leaves_quantity, 5);
+    }
+
+    #[test]
+    fn iceberg_orders_replenish() {
+        iceberg_orders::<VectorOrderBook>();
+        iceberg_orders::<BTreeOrderBook>();
+    }
+
+    #[test]
+    fn post_only_orders() {
+        let mut n = Engine::<BTreeOrderBook>::new();
+        n.add_order("Sell".to_string(), 10, px("101")).unwrap();
+        let post_only = |price: &str| NewOrder {
+            post_only: true,
+            ..NewOrder::limit(Side::Buy, 10, px(price))
+        };
+        assert!(n.submit_order(post_only("101")).is_err());
+        let (id, _) = n.submit_order(post_only("100.99")).unwrap();
+        assert!(n.amend_order(id, 10, px("101.5")).is_err());
+        assert_eq!(n.get_volume_at_limit(px("101")), 10);
+
+        n.post_only_policy = PostOnlyPolicy::Reprice;
+        let (id, fills) = n.submit_order(post_only("102")).unwrap();
+        assert!(fills.is_empty());
+        assert_eq!(n.get_order_record(id).unwrap().order.price, px("100.99"));
+        assert_eq!(n.get_volume_at_limit(px("100.99")), 20);
+        // Amends that would cross are repriced the same way
+        n.amend_order(id, 10, px("100.98")).unwrap();
+        let (id, fills) = n.amend_order(id, 15, px("101.5")).unwrap();
+        assert!(fills.is_empty());
+        assert_eq!(n.get_order_record(id).unwrap().order.price, px("100.99"));
+        assert_eq!(n.get_volume_at_limit(px("100.99")), 25);
+        assert!(n
+            .submit_order(NewOrder {
+                post_only: true,
+                ..NewOrder::market(Side::Buy, 10)
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn hidden_orders_trade_but_are_not_shown() {
+        let mut n = Engine::<VectorOrderBook>::new();
+        let (hidden, _) = n
+            .submit_order(NewOrder {
+                hidden: true,
+                ..NewOrder::limit(Side::Sell, 10, px("101"))
+            })
+            .unwrap();
+        assert_eq!(n.get_book().1[0].quantity, 0);
+        assert_eq!(n.get_volume_at_limit(px("101")), 0);
+        let (_, fills) = n.add_order("Buy".to_string(), 4, px("101")).unwrap();
+        assert_eq!(fills[0].ask_id, hidden);
+        assert!(n
+            .submit_order(NewOrder {
+                hidden: true,
+                ..NewOrder::iceberg(Side::Sell, 10, px("101"), 5)
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn lot_sizes_and_price_bands() {
+        let instrument: Instrument = "ABC:0.05:10:5".parse().unwrap();
+        assert_eq!(instrument.lot_size, 10);
+        assert_eq!(instrument.price_band, Some(px("5")));
+        assert!("".parse::<Instrument>().is_err());
+        assert!("ABC:0".parse::<Instrument>().is_err());
+
+        let mut n = Engine::<BTreeOrderBook>::with_instrument(instrument);
+        assert!(n.add_order("Buy".to_string(), 15, px("100")).is_err());
+        // Nothing has traded yet so the band does not apply
+        n.add_order("Sell".to_string(), 10, px("100")).unwrap();
+        n.add_order("Buy".to_string(), 10, px("100")).unwrap();
+        assert!(n.add_order("Buy".to_string(), 10, px("94.95")).is_err());
+        assert!(n.add_order("Sell".to_string(), 10, px("105.05")).is_err());
+        assert!(n.add_order("Buy".to_string(), 10, px("95")).is_ok());
+
+        // A stop's price is only checked if it has a limit price to amend
+        let (stop, _) = n
+            .submit_order(NewOrder::stop(Side::Buy, 10, px("102")))
+            .unwrap();
+        n.amend_order(stop, 20, px("0")).unwrap();
+        assert_eq!(n.stops.get(stop).unwrap().leaves_quantity, 20);
+        let (stop_limit, _) = n
+            .submit_order(NewOrder::stop_limit(Side::Buy, 10, px("102"), px("103")))
+            .unwrap();
+        assert!(n.amend_order(stop_limit, 10, px("105.05")).is_err());
+        assert!(n.amend_order(stop_limit, 10, px("104")).is_ok());
+    }
+
+    #[test]
+    fn instruments_trade_independently() {
+        let mut instruments = InstrumentRegistry::<VectorOrderBook>::new();
+        instruments.add("XYZ".parse().unwrap()).unwrap();
+        instruments.add("ABC:0.5".parse().unwrap()).unwrap();
+        assert!(instruments.add("ABC".parse().unwrap()).is_err());
+        assert_eq!(instruments.len(), 2);
+
+        let xyz = instruments.get_mut("XYZ").unwrap();
+        trade(xyz, "100.01");
+        let abc = instruments.get_mut("ABC").unwrap();
+        assert!(abc.add_order("Buy".to_string(), 1, px("100.01")).is_err());
+        trade(abc, "50.5");
+        assert_eq!(
+            instruments.get("XYZ").unwrap().get_trade_history()[0].1,
+            px("100.01")
+        );
+        assert_eq!(
+            instruments.get("ABC").unwrap().get_trade_history()[0].1,
+            px("50.5")
+        );
+        assert!(instruments.get("DEF").is_none());
+    }
+
+    #[tokio::test]
+    async fn shards_match_each_symbol_on_its_own_thread() {
+        let mut instruments = InstrumentRegistry::<BTreeOrderBook>::new();
+        instruments.add("XYZ".parse().unwrap()).unwrap();
+        instruments.add("ABC".parse().unwrap()).unwrap();
+        // ABC carries on from a recovered journal
+        let last_seqs = BTreeMap::from([("ABC".to_string(), 7)]);
+        let shards =
+            EngineShards::spawn(instruments, &last_seqs, Arc::new(|_, _| {}), None).unwrap();
+        assert!(shards.get("DEF").is_none());
+
+        let xyz = shards.get("XYZ").unwrap();
+        let abc = shards.get("ABC").unwrap();
+        xyz.execute(|n| trade(n, "100")).await.unwrap();
+        let thread =
+            |_: &mut Engine<BTreeOrderBook>| std::thread::current().name().map(String::from);
+        assert_eq!(
+            xyz.execute(thread).await.unwrap().as_deref(),
+            Some("engine-XYZ")
+        );
+        assert_eq!(
+            abc.execute(thread).await.unwrap().as_deref(),
+            Some("engine-ABC")
+        );
+        let prices = (
+            xyz.execute(|n| n.get_trade_history()[0].1).await,
+            abc.execute(|n| n.get_trade_history()[0].1).await,
+        );
+        assert_eq!(prices, (Ok(px("100")), Ok(Price::ZERO)));
+
+        // Each symbol numbers its own commands without gaps, whatever the others do
+        let sell = Command::New(NewOrder::limit(Side::Sell, 1, px("100")));
+        let seq = |_: &Engine<BTreeOrderBook>, output: &Output| output.seq;
+        assert_eq!(abc.submit(sell, seq).await, Ok(8));
+        assert_eq!(xyz.submit(sell, seq).await, Ok(1));
+        assert_eq!(xyz.submit(sell, seq).await, Ok(2));
+        assert_eq!(abc.submit(Command::Cancel { id: 1 }, seq).await, Ok(9));
+        assert_eq!(xyz.execute_as_of(|_| ()).await, Ok((2, ())));
+    }
+
+    #[tokio::test]
+    async fn bus_publishes_outputs_with_top_of_book() {
+        let mut instruments = InstrumentRegistry::<BTreeOrderBook>::new();
+        instruments.add("XYZ".parse().unwrap()).unwrap();
+        let bus = Arc::new(EventBus::new());
+        let publisher = bus.clone();
+        let shards = EngineShards::spawn(
+            instruments,
+            &BTreeMap::new(),
+            Arc::new(move |n, output| publisher.publish(n, output)),
+            None,
+        )
+        .unwrap();
+        let mut updates = bus.subscribe();
+        let xyz = shards.get("XYZ").unwrap();
+        let hidden = NewOrder {
+            hidden: true,
+            ..NewOrder::limit(Side::Buy, 5, px("100"))
+        };
+        for request in [
+            NewOrder::limit(Side::Buy, 5, px("99")),
+            NewOrder::limit(Side::Buy, 3, px("99")),
+            hidden,
+            NewOrder::limit(Side::Sell, 2, px("101")),
+        ] {
+            xyz.submit(Command::New(request), |_, _| ()).await.unwrap();
+        }
+        let mut last = None;
+        for seq in 1..=4 {
+            let update = updates.recv().await.unwrap();
+            assert_eq!(update.output.seq, seq);
+            last = Some(update);
+        }
+        // Hidden orders stay out of the top of book
+        let last = last.unwrap();
+        assert_eq!(
+            (last.best_bid, last.best_ask),
+            (
+                Some(PriceLevel {
+                    price: px("99"),
+                    volume: 8
+                }),
+                Some(PriceLevel {
+                    price: px("101"),
+                    volume: 2
+                })
+            )
+        );
+    }
+
+    #[test]
+    fn commands_produce_events() {
+        let mut n = Engine::<BTreeOrderBook>::new();
+        let events = n.apply(
+            Command::New(NewOrder::limit(Side::Sell, 10, px("100"))),
+            OffsetDateTime::now_utc(),
+        );
+        assert_eq!(
+            events,
+            vec![
+                Event::Accepted { id: 1 },
+                Event::OrderUpdate {
+                    id: 1,
+                    status: OrderStatus::New,
+                    filled_quantity: 0,
+                    leaves_quantity: 10
+                },
+                Event::BookOrder {
+                    action: OrderAction::Add,
+                    id: 1,
+                    side: Side::Sell,
+                    price: px("100"),
+                    quantity: 0,
+                    remaining: 10
+                },
+                Event::BookDelta {
+                    side: Side::Sell,
+                    price: px("100"),
+                    volume: 10
+                },
+            ]
+        );
+        let events = n.apply(
+            Command::New(NewOrder::limit(Side::Buy, 4, px("101"))),
+            OffsetDateTime::now_utc(),
+        );
+        assert_eq!(
+            events,
+            vec![
+                Event::Accepted { id: 2 },
+                Event::Fill(Fill {
+                    bid_id: 2,
+                    ask_id: 1,
+                    quantity: 4,
+                    price: px("100")
+                }),
+                Event::OrderUpdate {
+                    id: 2,
+                    status: OrderStatus::Filled,
+                    filled_quantity: 4,
+                    leaves_quantity: 0
+                },
+                Event::OrderUpdate {
+                    id: 1,
+                    status: OrderStatus::PartiallyFilled,
+                    filled_quantity: 4,
+                    leaves_quantity: 6
+                },
+                Event::BookOrder {
+                    action: OrderAction::Execute,
+                    id: 1,
+                    side: Side::Sell,
+                    price: px("100"),
+                    quantity: 4,
+                    remaining: 6
+                },
+                Event::BookDelta {
+                    side: Side::Sell,
+                    price: px("100"),
+                    volume: 6
+                },
+            ]
+        );
+        assert_eq!(
+            n.apply(Command::Cancel { id: 2 }, OffsetDateTime::now_utc()),
+            vec![Event::Rejected {
+                reason: "Order not found for id: 2".to_string()
+            }]
+        );
+    }
+
+    // The market-by-order changes in a command's events, as (action, id, price, quantity, remaining)
+    fn book_orders(events: Vec<Event>) -> Vec<(OrderAction, u64, Price, u64, u64)> {
+        events
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::BookOrder {
+                    action,
+                    id,
+                    price,
+                    quantity,
+                    remaining,
+                    ..
+                } => Some((action, id, price, quantity, remaining)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn book_orders_follow_the_queue<T: OrderBook>() {
+        let mut n = Engine::<T>::new();
+        let mut apply = |command| book_orders(n.apply(command, OffsetDateTime::now_utc()));
+        let mut iceberg = NewOrder::limit(Side::Sell, 30, px("100"));
+        iceberg.display_quantity = Some(10);
+        assert_eq!(
+            apply(Command::New(iceberg)),
+            vec![(OrderAction::Add, 1, px("100"), 0, 10)]
+        );
+        let mut hidden = NewOrder::limit(Side::Sell, 10, px("100"));
+        hidden.hidden = true;
+        assert!(apply(Command::New(hidden)).is_empty());
+        apply(Command::New(NewOrder::limit(Side::Sell, 10, px("101"))));
+
+        // The aggressor is only shown if some of it is left to rest
+        assert_eq!(
+            apply(Command::New(NewOrder::limit(Side::Buy, 25, px("100")))),
+            vec![
+                (OrderAction::Execute, 1, px("100"), 10, 0),
+                // A replenished iceberg goes behind the hidden order
+                (OrderAction::Add, 1, px("100"), 0, 10),
+                (OrderAction::Execute, 1, px("100"), 5, 5),
+            ]
+        );
+        assert_eq!(
+            apply(Command::New(NewOrder::limit(Side::Buy, 20, px("100")))),
+            vec![
+                (OrderAction::Execute, 1, px("100"), 5, 0),
+                (OrderAction::Add, 1, px("100"), 0, 10),
+                (OrderAction::Execute, 1, px("100"), 10, 0),
+                (OrderAction::Add, 5, px("100"), 0, 5),
+            ]
+        );
+        assert_eq!(
+            apply(Command::Amend {
+                id: 3,
+                quantity: 5,
+                price: px("101")
+            }),
+            vec![(OrderAction::Modify, 3, px("101"), 0, 5)]
+        );
+        assert_eq!(
+            apply(Command::Amend {
+                id: 3,
+                quantity: 5,
+                price: px("102")
+            }),
+            vec![
+                (OrderAction::Cancel, 3, px("101"), 0, 0),
+                (OrderAction::Add, 3, px("102"), 0, 5),
+            ]
+        );
+
+        // Growing an order sends it behind the others at its price, shrinking one does not
+        apply(Command::New(NewOrder::limit(Side::Sell, 5, px("102"))));
+        assert_eq!(
+            apply(Command::Amend {
+                id: 3,
+                quantity: 8,
+                price: px("102")
+            }),
+            vec![
+                (OrderAction::Cancel, 3, px("102"), 0, 0),
+                (OrderAction::Add, 3, px("102"), 0, 8),
+            ]
+        );
+        assert_eq!(
+            apply(Command::Amend {
+                id: 6,
+                quantity: 2,
+                price: px("102")
+            }),
+            vec![(OrderAction::Modify, 6, px("102"), 0, 2)]
+        );
+        assert_eq!(
+            apply(Command::Cancel { id: 3 }),
+            vec![(OrderAction::Cancel, 3, px("102"), 0, 0)]
+        );
+    }
+
+    #[test]
+    fn book_orders_follow_either_book() {
+        book_orders_follow_the_queue::<VectorOrderBook>();
+        book_orders_follow_the_queue::<BTreeOrderBook>();
+    }
+
+    #[test]
+    fn trades_aggregate_into_candles() {
+        let mut n = Engine::<BTreeOrderBook>::new();
+        // An hour boundary, so that every interval's bar starts here
+        let start = OffsetDateTime::from_unix_timestamp(1_700_002_800);
+        let mut trade = |quantity, price, millis| {
+            let now = start + Duration::milliseconds(millis);
+            n.apply(
+                Command::New(NewOrder::limit(Side::Sell, quantity, px(price))),
+                now,
+            );
+            n.apply(
+                Command::New(NewOrder::limit(Side::Buy, quantity, px(price))),
+                now,
+            );
+        };
+        trade(2, "100", 0);
+        trade(1, "103", 400);
+        trade(3, "99", 900);
+        trade(1, "101", 1_500);
+        trade(4, "102", 61_000);
+        let millis = start.unix_timestamp() * 1000;
+        let bar = |open, high, low, close, volume, timestamp| PriceDatum {
+            open: px(open),
+            close: px(close),
+            high: px(high),
+            low: px(low),
+            volume,
+            timestamp,
+        };
+        let seconds = n.candles.get(Interval::OneSecond, i64::MIN, i64::MAX);
+        assert_eq!(seconds.len(), 3);
+        assert_eq!(seconds[0], bar("100", "103", "99", "99", 6, millis));
+        assert_eq!(
+            n.candles.get(Interval::OneMinute, i64::MIN, i64::MAX),
+            vec![
+                bar("100", "103", "99", "101", 7, millis),
+                bar("102", "102", "102", "102", 4, millis + 60_000),
+            ]
+        );
+        assert_eq!(
+            n.candles.get(Interval::OneHour, i64::MIN, i64::MAX),
+            vec![bar("100", "103", "99", "102", 11, millis)]
+        );
+        assert_eq!(
+            n.candles
+                .get(Interval::OneSecond, millis + 1_000, millis + 60_000)
+                .len(),
+            1
+        );
+
+        // Bars are carried over in a snapshot
+        let mut restored = Engine::<BTreeOrderBook>::new();
+        restored.restore(n.snapshot(0));
+        assert_eq!(restored.candles, n.candles);
+    }
+
+    #[test]
+    fn price_parsing() {
+        assert_eq!(px("100.25").to_string(), "100.25");
+        assert_eq!(px("100.10").to_string(), "100.1");
+        assert_eq!(px(".5"), px("0.5"));
+        assert!("1.123456789".parse::<Price>().is_err());
+        assert!("1e5".parse::<Price>().is_err());
+        assert_eq!(Price::from_f64(0.1 + 0.2), Ok(px("0.3")));
+        assert!(px("99.99") < px("100"));
+        assert_eq!(px("95.5").percent(px("1")), px("0.955"));
+        assert_eq!(px("96.455").ceil_to_tick(px("0.01")), px("96.46"));
+        assert_eq!(px("96.455").floor_to_tick(px("0.01")), px("96.45"));
+    }
+
+    #[test]
+    fn executions_report_each_fill() {
+        let mut n = Engine::<BTreeOrderBook>::new();
+        let apply = |n: &mut Engine<BTreeOrderBook>, request| {
+            let events = n.apply(Command::New(request), OffsetDateTime::now_utc());
+            let output = Output {
+                seq: 0,
+                symbol: n.instrument.symbol.clone(),
+                events,
+            };
+            executions(n, &output)
+                .into_iter()
+                .map(|e| {
+                    (
+                        e.id,
+                        e.exec_type,
+                        e.last_quantity,
+                        e.cum_quantity,
+                        e.leaves_quantity,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut iceberg = NewOrder::limit(Side::Sell, 30, px("100"));
+        iceberg.display_quantity = Some(10);
+        assert!(apply(&mut n, iceberg).is_empty());
+
+        // Each fill of an order in one command leaves it with less
+        let fills = apply(&mut n, NewOrder::limit(Side::Buy, 25, px("100")));
+        assert_eq!(
+            fills.into_iter().filter(|e| e.0 == 1).collect::<Vec<_>>(),
+            vec![
+                (1, ExecType::Trade, 10, 10, 20),
+                (1, ExecType::Trade, 10, 20, 10),
+                (1, ExecType::Trade, 5, 25, 5),
+            ]
+        );
+        let ioc =
+            NewOrder::limit(Side::Buy, 10, px("100")).with_time_in_force(TimeInForce::Ioc, None);
+        assert_eq!(
+            apply(&mut n, ioc),
+            vec![
+                (3, ExecType::Trade, 5, 5, 5),
+                (1, ExecType::Trade, 5, 30, 0),
+                (3, ExecType::Cancelled, 0, 5, 0),
+            ]
+        );
+
+        // A trailing stop's owner hears each time its trigger follows the market
+        let offset = TrailingOffset::Amount(px("2"));
+        let (stop, _) = n
+            .submit_order(NewOrder::trailing_stop(Side::Sell, 10, offset))
+            .unwrap();
+        apply(&mut n, NewOrder::limit(Side::Sell, 1, px("101")));
+        let buy = Command::New(NewOrder::limit(Side::Buy, 1, px("101")));
+        let output = Output {
+            seq: 0,
+            symbol: n.instrument.symbol.clone(),
+            events: n.apply(buy, OffsetDateTime::now_utc()),
+        };
+        assert_eq!(
+            executions(&n, &output)
+                .into_iter()
+                .filter(|e| e.id == stop)
+                .map(|e| (e.exec_type, e.status, e.leaves_quantity, e.stop_price))
+                .collect::<Vec<_>>(),
+            vec![(ExecType::StopMoved, OrderStatus::New, 10, Some(px("99")))]
+        );
+    }
 }
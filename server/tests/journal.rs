@@ -0,0 +1,318 @@
+#[cfg(test)]
+mod tests {
+    use server::core::btree::BTreeOrderBook;
+    use server::core::engine::Engine;
+    use server::core::instrument::InstrumentRegistry;
+    use server::core::journal::{read_journal, FsyncPolicy, Journal, JournalConfig, JournalEntry};
+    use server::core::order_book::{NewOrder, Side, TimeInForce, TrailingOffset};
+    use server::core::price::Price;
+    use server::core::replay::{recover, replay};
+    use server::core::sequencer::{Command, Event};
+    use server::core::shard::EngineShards;
+    use server::core::snapshot::read_snapshot;
+    use server::core::tape::{read_tape, TradeQuery, TradeTape};
+    use server::core::vector::VectorOrderBook;
+    use std::collections::BTreeMap;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn px(price: &str) -> Price {
+        price.parse().unwrap()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("journal-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn entry(seq: u64) -> JournalEntry {
+        JournalEntry {
+            seq,
+            timestamp: 1_700_000_000_000_000_000,
+            command: Command::New(NewOrder::limit(Side::Buy, 10, px("100.25"))),
+            events: vec![Event::Accepted { id: seq }],
+        }
+    }
+
+    #[test]
+    fn journal_round_trip_and_torn_tail() {
+        let path = temp_dir("round-trip").join("XYZ.journal");
+        let mut journal = Journal::open(&path, FsyncPolicy::Every(2)).unwrap();
+        journal.append(&entry(1)).unwrap();
+        journal.append(&entry(2)).unwrap();
+        drop(journal);
+        assert_eq!(read_journal(&path).unwrap(), vec![entry(1), entry(2)]);
+
+        // A record cut short by a crash is ignored, and dropped once the journal reopens
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[40, 0, 0, 0, 1, 2, 3, 4, b'{']).unwrap();
+        assert_eq!(read_journal(&path).unwrap().len(), 2);
+        let mut journal = Journal::open(&path, FsyncPolicy::Always).unwrap();
+        journal.append(&entry(3)).unwrap();
+        assert_eq!(
+            read_journal(&path).unwrap(),
+            vec![entry(1), entry(2), entry(3)]
+        );
+        assert!("0".parse::<FsyncPolicy>().is_err());
+        assert_eq!("never".parse(), Ok(FsyncPolicy::Never));
+    }
+
+    #[tokio::test]
+    async fn shards_journal_every_command() {
+        let config = JournalConfig {
+            dir: temp_dir("shards"),
+            policy: FsyncPolicy::Always,
+        };
+        let mut instruments = InstrumentRegistry::<BTreeOrderBook>::new();
+        instruments.add("XYZ".parse().unwrap()).unwrap();
+        let sequencer = &BTreeMap::new();
+        let shards =
+            EngineShards::spawn(instruments, sequencer, Arc::new(|_, _| {}), Some(&config))
+                .unwrap();
+        let xyz = shards.get("XYZ").unwrap();
+        let sell = Command::New(NewOrder::limit(Side::Sell, 5, px("100")));
+        let buy = Command::New(NewOrder::limit(Side::Buy, 5, px("100")));
+        let outputs = (
+            xyz.submit(sell, |_, output| output.clone()).await.unwrap(),
+            xyz.submit(buy, |_, output| output.clone()).await.unwrap(),
+            xyz.submit(Command::Cancel { id: 1 }, |_, output| output.clone())
+                .await
+                .unwrap(),
+        );
+
+        let entries = read_journal(&config.path("XYZ")).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| entry.command)
+                .collect::<Vec<_>>(),
+            vec![sell, buy, Command::Cancel { id: 1 }]
+        );
+        for (entry, output) in entries.iter().zip([outputs.0, outputs.1, outputs.2]) {
+            assert_eq!((entry.seq, &entry.events), (output.seq, &output.events));
+        }
+        assert!(matches!(entries[2].events[0], Event::Rejected { .. }));
+    }
+
+    // A journal that cannot be written to, as when the disk is full
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn shards_stop_when_the_journal_fails() {
+        let config = JournalConfig {
+            dir: temp_dir("full"),
+            policy: FsyncPolicy::Always,
+        };
+        std::os::unix::fs::symlink("/dev/full", config.path("XYZ")).unwrap();
+        let mut instruments = InstrumentRegistry::<BTreeOrderBook>::new();
+        instruments.add("XYZ".parse().unwrap()).unwrap();
+        let published = Arc::new(AtomicUsize::new(0));
+        let listener = published.clone();
+        let shards = EngineShards::spawn(
+            instruments,
+            &BTreeMap::new(),
+            Arc::new(move |_, _| {
+                listener.fetch_add(1, Ordering::SeqCst);
+            }),
+            Some(&config),
+        )
+        .unwrap();
+        let xyz = shards.get("XYZ").unwrap();
+        let sell = Command::New(NewOrder::limit(Side::Sell, 5, px("100")));
+        assert_eq!(
+            xyz.submit(sell, |_, output| output.clone()).await,
+            Err("Journal write failed")
+        );
+        assert_eq!(
+            xyz.execute(|engine| engine.get_book().1[0].id).await,
+            Err("Engine stopped")
+        );
+        assert_eq!(published.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn trade_tape_pages_by_id_and_time() {
+        let config = JournalConfig {
+            dir: temp_dir("tape"),
+            policy: FsyncPolicy::Never,
+        };
+        let mut instruments = InstrumentRegistry::<BTreeOrderBook>::new();
+        instruments.add("XYZ".parse().unwrap()).unwrap();
+        let shards = EngineShards::spawn(
+            instruments,
+            &BTreeMap::new(),
+            Arc::new(|_, _| {}),
+            Some(&config),
+        )
+        .unwrap();
+        let xyz = shards.get("XYZ").unwrap();
+        for i in 0..5 {
+            let price = px(&(100 + i).to_string());
+            let sell = Command::New(NewOrder::limit(Side::Sell, 2 + i, price));
+            let buy = Command::New(NewOrder::limit(Side::Buy, 2 + i, price));
+            xyz.submit(sell, |_, _| ()).await.unwrap();
+            xyz.submit(buy, |_, _| ()).await.unwrap();
+        }
+
+        let all = xyz.trades(TradeQuery::default()).await.unwrap();
+        assert_eq!(
+            all.iter().map(|trade| trade.id).collect::<Vec<_>>(),
+            [1, 2, 3, 4, 5]
+        );
+        assert_eq!((all[1].bid_id, all[1].ask_id), (4, 3));
+        assert_eq!(all[1].aggressor, Side::Buy);
+        assert_eq!((all[1].quantity, all[1].price), (3, px("101")));
+        let page = TradeQuery {
+            after: 2,
+            limit: 2,
+            ..TradeQuery::default()
+        };
+        assert_eq!(xyz.trades(page).await.unwrap(), all[2..4]);
+        let range = TradeQuery {
+            from: all[1].timestamp,
+            to: all[3].timestamp,
+            ..TradeQuery::default()
+        };
+        assert_eq!(xyz.trades(range).await.unwrap(), all[1..4]);
+        // The engine's own copy agrees with the tape
+        let in_memory = xyz
+            .execute(move |engine| engine.trades.query(&range))
+            .await
+            .unwrap();
+        assert_eq!(in_memory, all[1..4]);
+
+        // A torn record is dropped on opening
+        drop(shards);
+        let path = config.tape_path("XYZ");
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[1, 2, 3]).unwrap();
+        assert_eq!(TradeTape::open(&path).unwrap().last_id(), 5);
+        assert_eq!(read_tape(&path, &TradeQuery::default()).unwrap(), all);
+    }
+
+    #[tokio::test]
+    async fn recovery_rebuilds_engine_state() {
+        let config = JournalConfig {
+            dir: temp_dir("recovery"),
+            policy: FsyncPolicy::Never,
+        };
+        let mut instruments = InstrumentRegistry::<BTreeOrderBook>::new();
+        instruments.add("XYZ".parse().unwrap()).unwrap();
+        let shards = EngineShards::spawn(
+            instruments,
+            &BTreeMap::new(),
+            Arc::new(|_, _| {}),
+            Some(&config),
+        )
+        .unwrap();
+        let xyz = shards.get("XYZ").unwrap();
+        for command in [
+            Command::New(NewOrder::limit(Side::Sell, 5, px("101"))),
+            Command::New(NewOrder::limit(Side::Sell, 5, px("100"))),
+            Command::New(NewOrder::limit(Side::Buy, 8, px("101"))),
+            Command::Amend {
+                id: 1,
+                quantity: 3,
+                price: px("102"),
+            },
+            Command::Cancel { id: 9 },
+        ] {
+            xyz.submit(command, |_, _| ()).await.unwrap();
+        }
+        let live = xyz
+            .execute(|engine| (engine.get_book(), engine.get_trade_history()))
+            .await
+            .unwrap();
+
+        let mut engine = Engine::<BTreeOrderBook>::with_instrument("XYZ".parse().unwrap());
+        let recovery = recover(&mut engine, &config).unwrap();
+        assert_eq!((recovery.commands, recovery.last_seq), (5, 5));
+        assert!(recovery.divergences.is_empty());
+        assert_eq!((engine.get_book(), engine.get_trade_history()), live);
+        let (id, _) = engine
+            .submit_order(NewOrder::limit(Side::Buy, 1, px("90")))
+            .unwrap();
+        assert_eq!(id, 4);
+
+        // Events that no longer match what the engine produces are reported
+        let mut entries = read_journal(&config.path("XYZ")).unwrap();
+        entries[1].events = vec![Event::Accepted { id: 7 }];
+        let mut engine = Engine::<BTreeOrderBook>::with_instrument("XYZ".parse().unwrap());
+        let divergences = replay(&mut engine, &entries);
+        assert_eq!(divergences.len(), 1);
+        assert_eq!(divergences[0].seq, 2);
+        assert_eq!(divergences[0].replayed[0], Event::Accepted { id: 2 });
+    }
+
+    #[tokio::test]
+    async fn snapshots_bound_recovery() {
+        let config = JournalConfig {
+            dir: temp_dir("snapshot"),
+            policy: FsyncPolicy::Never,
+        };
+        let mut instruments = InstrumentRegistry::<BTreeOrderBook>::new();
+        instruments.add("XYZ".parse().unwrap()).unwrap();
+        let shards = EngineShards::spawn(
+            instruments,
+            &BTreeMap::new(),
+            Arc::new(|_, _| {}),
+            Some(&config),
+        )
+        .unwrap();
+        let xyz = shards.get("XYZ").unwrap();
+        let far = 4_000_000_000;
+        let before = [
+            NewOrder::iceberg(Side::Sell, 30, px("101"), 10),
+            NewOrder::limit(Side::Sell, 5, px("101")),
+            NewOrder {
+                hidden: true,
+                ..NewOrder::limit(Side::Buy, 5, px("99"))
+            },
+            NewOrder::limit(Side::Buy, 5, px("98")).with_time_in_force(TimeInForce::Gtd, Some(far)),
+            NewOrder::limit(Side::Buy, 12, px("101")),
+            NewOrder::stop(Side::Buy, 5, px("105")),
+            NewOrder::trailing_stop(Side::Sell, 5, TrailingOffset::Amount(px("2"))),
+        ];
+        for request in before {
+            xyz.submit(Command::New(request), |_, _| ()).await.unwrap();
+        }
+        assert_eq!(xyz.snapshot().await, Ok(7));
+        assert!(read_journal(&config.path("XYZ")).unwrap().is_empty());
+        for command in [
+            Command::New(NewOrder::limit(Side::Buy, 10, px("101"))),
+            Command::Cancel { id: 3 },
+        ] {
+            xyz.submit(command, |_, _| ()).await.unwrap();
+        }
+        let live = xyz.execute(|engine| engine.snapshot(0)).await.unwrap();
+
+        // Only the commands since the snapshot are replayed on top of it
+        let mut engine = Engine::<BTreeOrderBook>::with_instrument("XYZ".parse().unwrap());
+        let recovery = recover(&mut engine, &config).unwrap();
+        assert_eq!(
+            (recovery.snapshot, recovery.commands, recovery.last_seq),
+            (Some(7), 2, 9)
+        );
+        assert!(recovery.divergences.is_empty());
+        assert_eq!(engine.snapshot(0), live);
+
+        // Either book can be seeded from a snapshot
+        let snapshot = read_snapshot(&config.snapshot_path("XYZ"))
+            .unwrap()
+            .unwrap();
+        let mut vector = Engine::<VectorOrderBook>::with_instrument("XYZ".parse().unwrap());
+        vector.restore(snapshot.clone());
+        assert_eq!(vector.snapshot(7), snapshot);
+
+        let mut bytes = std::fs::read(config.snapshot_path("XYZ")).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        std::fs::write(config.snapshot_path("XYZ"), bytes).unwrap();
+        assert!(read_snapshot(&config.snapshot_path("XYZ")).is_err());
+    }
+}
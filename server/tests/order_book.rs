@@ -0,0 +1,214 @@
+#[cfg(test)]
+mod tests {
+    use server::core::btree::BTreeOrderBook;
+    use server::core::depth::{DepthBook, LevelAction, LevelUpdate};
+    use server::core::order_book::{Order, OrderBook, PriceLevel, Side};
+    use server::core::price::Price;
+    use server::core::sequencer::{Event, Output};
+    use server::core::vector::VectorOrderBook;
+
+    fn px(price: &str) -> Price {
+        price.parse().unwrap()
+    }
+
+    fn seeded<T: OrderBook>() -> T {
+        let mut book = T::new();
+        book.add_bid_order(Order::new(1, Side::Buy, 50, px("100")));
+        book.add_bid_order(Order::new(2, Side::Buy, 100, px("99")));
+        book.add_bid_order(Order::new(3, Side::Buy, 25, px("100")));
+        book.add_ask_order(Order::new(4, Side::Sell, 50, px("101")));
+        book.add_ask_order(Order::new(5, Side::Sell, 50, px("102")));
+        book
+    }
+
+    fn price_time_priority<T: OrderBook>() {
+        let book: T = seeded();
+        let bids = book.get_bids();
+        let asks = book.get_asks();
+        assert_eq!([bids[0].id, bids[1].id, bids[2].id], [1, 3, 2]);
+        assert_eq!(bids[3].quantity, 0);
+        assert_eq!([asks[0].id, asks[1].id], [4, 5]);
+        assert_eq!(book.get_market_price(), Ok(px("100.5")));
+    }
+
+    fn remove_and_amend<T: OrderBook>() {
+        let mut book: T = seeded();
+        assert!(book.remove_ask_order(4).is_ok());
+        assert!(book.remove_ask_order(4).is_err());
+        assert!(book.remove_bid_order(5).is_err());
+        assert!(book.amend_bid_order(2, 10, px("100.5")).is_ok());
+        assert!(book.amend_ask_order(2, 10, px("100.5")).is_err());
+        assert_eq!(book.get_bids()[0].id, 2);
+        assert_eq!(book.get_asks()[0].id, 5);
+        assert!(book.get_order_by_id(4).is_err());
+        assert_eq!(book.get_order_by_id(2).unwrap().quantity, 10);
+    }
+
+    fn amend_queue_position<T: OrderBook>() {
+        let mut book: T = seeded();
+        // Upsizing or repricing goes to the back of the level, reducing keeps its place
+        book.amend_bid_order(1, 80, px("100")).unwrap();
+        book.amend_bid_order(3, 20, px("100")).unwrap();
+        book.amend_bid_order(2, 100, px("100")).unwrap();
+        let bids = book.get_bids();
+        assert_eq!([bids[0].id, bids[1].id, bids[2].id], [3, 1, 2]);
+
+        book.add_ask_order(Order::new(6, Side::Sell, 200, px("100")));
+        assert_eq!(book.match_orders(Side::Sell), Ok((3, 6, 20, px("100"))));
+        assert_eq!(book.match_orders(Side::Sell), Ok((1, 6, 80, px("100"))));
+        assert_eq!(book.match_orders(Side::Sell), Ok((2, 6, 100, px("100"))));
+    }
+
+    fn volume_at_limit<T: OrderBook>() {
+        let mut book: T = seeded();
+        assert_eq!(book.get_volume_at_limit(px("100")), 75);
+        assert_eq!(book.get_volume_at_limit(px("101")), 50);
+        book.remove_bid_order(1).unwrap();
+        assert_eq!(book.get_volume_at_limit(px("100")), 25);
+        assert_eq!(book.get_volume_at_limit(px("98")), 0);
+    }
+
+    fn match_crossing_orders<T: OrderBook>() {
+        let mut book: T = seeded();
+        assert!(book.match_orders(Side::Buy).is_err());
+        book.add_bid_order(Order::new(6, Side::Buy, 50, px("101.5")));
+        assert_eq!(book.match_orders(Side::Buy), Ok((6, 4, 50, px("101"))));
+        assert!(book.match_orders(Side::Buy).is_err());
+        assert_eq!(book.get_bids()[0].id, 1);
+        assert_eq!(book.get_asks()[0].id, 5);
+    }
+
+    fn partial_fills<T: OrderBook>() {
+        let mut book: T = seeded();
+        book.add_ask_order(Order::new(6, Side::Sell, 60, px("100")));
+        assert_eq!(book.match_orders(Side::Sell), Ok((1, 6, 50, px("100"))));
+        assert_eq!(book.match_orders(Side::Sell), Ok((3, 6, 10, px("100"))));
+        assert!(book.match_orders(Side::Sell).is_err());
+
+        let residual = book.get_bids()[0];
+        assert_eq!(residual.id, 3);
+        assert_eq!(residual.filled_quantity, 10);
+        assert_eq!(residual.leaves_quantity, 15);
+        assert_eq!(book.get_volume_at_limit(px("100")), 15);
+        assert!(book.get_order_by_id(6).is_err());
+
+        assert!(book.amend_bid_order(3, 10, px("100")).is_err());
+        assert!(book.amend_bid_order(3, 20, px("100")).is_ok());
+        assert_eq!(book.get_order_by_id(3).unwrap().leaves_quantity, 10);
+    }
+
+    fn hidden_orders<T: OrderBook>() {
+        let mut book: T = seeded();
+        let mut hidden = Order::new(6, Side::Buy, 40, px("100.5"));
+        hidden.hidden = true;
+        book.add_bid_order(hidden);
+        assert_eq!(book.get_bids()[0].id, 1);
+        assert_eq!(book.get_bids()[3].quantity, 0);
+        assert_eq!(book.get_volume_at_limit(px("100.5")), 0);
+        assert_eq!(book.get_best_price(Side::Buy), Some(px("100.5")));
+        assert_eq!(book.get_crossing_volume(Side::Sell, px("100.5")), 40);
+
+        book.add_ask_order(Order::new(7, Side::Sell, 10, px("100.5")));
+        assert_eq!(book.match_orders(Side::Sell), Ok((6, 7, 10, px("100.5"))));
+        assert_eq!(book.get_order_by_id(6).unwrap().leaves_quantity, 30);
+        assert!(book.amend_bid_order(6, 20, px("100.5")).is_ok());
+        assert_eq!(book.get_volume_at_limit(px("100.5")), 0);
+        book.remove_bid_order(6).unwrap();
+        assert_eq!(book.get_best_price(Side::Buy), Some(px("100")));
+    }
+
+    fn level(price: &str, volume: u64) -> PriceLevel {
+        PriceLevel {
+            price: px(price),
+            volume,
+        }
+    }
+
+    fn aggregated_levels<T: OrderBook>() {
+        let mut book: T = seeded();
+        let mut hidden = Order::new(6, Side::Buy, 40, px("100"));
+        hidden.hidden = true;
+        book.add_bid_order(hidden);
+        hidden.id = 7;
+        hidden.price = px("100.5");
+        book.add_bid_order(hidden);
+        assert_eq!(
+            book.get_levels(Side::Buy, 10),
+            vec![level("100", 75), level("99", 100)]
+        );
+        assert_eq!(book.get_levels(Side::Sell, 1), vec![level("101", 50)]);
+    }
+
+    fn delta(seq: u64, side: Side, price: &str, volume: u64) -> Output {
+        Output {
+            seq,
+            symbol: "XYZ".to_string(),
+            events: vec![Event::BookDelta {
+                side,
+                price: px(price),
+                volume,
+            }],
+        }
+    }
+
+    #[test]
+    fn depth_book_updates() {
+        let bids = [level("100", 75), level("99", 100), level("98", 10)];
+        let mut book = DepthBook::new(2, 5, &bids, &[level("101", 50)]);
+        assert_eq!(book.levels(Side::Buy), bids[..2].to_vec());
+
+        // Changes the book already reflects, and those below the top, are not reported
+        assert!(book.apply(&delta(5, Side::Buy, "100", 10)).is_empty());
+        assert!(book.apply(&delta(6, Side::Buy, "97", 10)).is_empty());
+        let update = |action, side, price, volume| LevelUpdate {
+            action,
+            side,
+            price: px(price),
+            volume,
+        };
+        assert_eq!(
+            book.apply(&delta(7, Side::Sell, "101", 20)),
+            vec![update(LevelAction::Change, Side::Sell, "101", 20)]
+        );
+        // A level leaving the top brings the next one into view
+        assert_eq!(
+            book.apply(&delta(8, Side::Buy, "100", 0)),
+            vec![
+                update(LevelAction::Delete, Side::Buy, "100", 0),
+                update(LevelAction::Add, Side::Buy, "98", 10),
+            ]
+        );
+        assert_eq!(
+            book.apply(&delta(9, Side::Buy, "99.5", 5)),
+            vec![
+                update(LevelAction::Delete, Side::Buy, "98", 0),
+                update(LevelAction::Add, Side::Buy, "99.5", 5),
+            ]
+        );
+        assert_eq!(book.seq(), 9);
+    }
+
+    #[test]
+    fn vector_order_book() {
+        price_time_priority::<VectorOrderBook>();
+        remove_and_amend::<VectorOrderBook>();
+        amend_queue_position::<VectorOrderBook>();
+        volume_at_limit::<VectorOrderBook>();
+        match_crossing_orders::<VectorOrderBook>();
+        partial_fills::<VectorOrderBook>();
+        hidden_orders::<VectorOrderBook>();
+        aggregated_levels::<VectorOrderBook>();
+    }
+
+    #[test]
+    fn btree_order_book() {
+        price_time_priority::<BTreeOrderBook>();
+        remove_and_amend::<BTreeOrderBook>();
+        amend_queue_position::<BTreeOrderBook>();
+        volume_at_limit::<BTreeOrderBook>();
+        match_crossing_orders::<BTreeOrderBook>();
+        partial_fills::<BTreeOrderBook>();
+        hidden_orders::<BTreeOrderBook>();
+        aggregated_levels::<BTreeOrderBook>();
+    }
+}
@@ -0,0 +1,98 @@
+#[cfg(test)]
+mod tests {
+    use server::core::bus::EventBus;
+    use server::core::instrument::InstrumentRegistry;
+    use server::core::shard::{EngineShards, Listener};
+    use server::core::vector::VectorOrderBook;
+    use server::net::session::{serve_connection, LoggedOn};
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    // Serves one session against a single XYZ engine, returning the client's end of it
+    async fn connect() -> TcpStream {
+        let mut instruments = InstrumentRegistry::<VectorOrderBook>::new();
+        instruments.add("XYZ".parse().unwrap()).unwrap();
+        let bus = Arc::new(EventBus::new());
+        let publisher = bus.clone();
+        let listener: Listener<VectorOrderBook> =
+            Arc::new(move |n, output| publisher.publish(n, output));
+        let shards = EngineShards::spawn(instruments, &BTreeMap::new(), listener, None);
+        let shards = Arc::new(shards.unwrap());
+        let socket = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = socket.accept().await.unwrap();
+            serve_connection(socket, shards, bus, LoggedOn::default()).await;
+        });
+        TcpStream::connect(address).await.unwrap()
+    }
+
+    // Frames are sent the way the server writes them
+    async fn send(client: &mut TcpStream, message: &str) {
+        client
+            .write_u32((message.len() as u32).to_be())
+            .await
+            .unwrap();
+        client.write_all(message.as_bytes()).await.unwrap();
+    }
+
+    async fn receive(client: &mut TcpStream) -> String {
+        let len = client.read_u32().await.unwrap().to_be();
+        let mut buf = vec![0u8; len as usize];
+        client.read_exact(&mut buf).await.unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[tokio::test]
+    async fn amend_fills_are_reported_once() {
+        let mut client = connect().await;
+        send(&mut client, "A|35=AMEND|38=1").await;
+        assert!(receive(&mut client).await.starts_with("A|"));
+        send(&mut client, "N|24=XYZ|2=B|3=10|4=99|38=2").await;
+        assert!(receive(&mut client).await.starts_with("E|24=XYZ|1=1|5=N|"));
+        send(&mut client, "N|24=XYZ|2=S|3=5|4=101|38=3").await;
+        assert!(receive(&mut client).await.starts_with("E|24=XYZ|1=2|5=N|"));
+
+        // The amend's response carries its fill, and only the resting order is pushed one
+        send(&mut client, "O|24=XYZ|1=1|3=10|4=101|38=4").await;
+        let response = receive(&mut client).await;
+        assert!(response.starts_with("E|24=XYZ|1=1|5=P|"));
+        assert!(response.contains("|13=5@101|"));
+        let pushed = receive(&mut client).await;
+        assert!(pushed.starts_with("E|24=XYZ|1=2|39=F|5=F|40=5|41=101|"));
+
+        // Nor is anything pushed after it
+        send(&mut client, "1|37=sync|38=5").await;
+        assert!(receive(&mut client).await.starts_with("0|37=sync|"));
+    }
+
+    #[tokio::test]
+    async fn cancels_are_reported_once() {
+        let mut client = connect().await;
+        send(&mut client, "A|35=CANCEL|38=1").await;
+        assert!(receive(&mut client).await.starts_with("A|"));
+        send(&mut client, "N|24=XYZ|2=B|3=10|4=99|38=2").await;
+        assert!(receive(&mut client).await.starts_with("E|24=XYZ|1=1|5=N|"));
+        send(&mut client, "N|24=XYZ|2=B|3=1|4=98|38=3").await;
+        assert!(receive(&mut client).await.starts_with("E|24=XYZ|1=2|5=N|"));
+        send(&mut client, "C|24=XYZ|1=1|38=4").await;
+        assert!(receive(&mut client).await.starts_with("E|24=XYZ|1=1|5=C|"));
+
+        // Updates arrive in order, so a second report of the cancel would come before the
+        // fill of the other order
+        send(&mut client, "N|24=XYZ|2=S|3=1|4=98|38=5").await;
+        let mut messages = Vec::new();
+        loop {
+            let message = receive(&mut client).await;
+            let filled = message.starts_with("E|24=XYZ|1=2|39=F|");
+            messages.push(message);
+            if filled {
+                break;
+            }
+        }
+        assert!(messages.iter().all(|m| !m.starts_with("E|24=XYZ|1=1|")));
+        assert!(messages.iter().any(|m| m.starts_with("E|24=XYZ|1=3|5=F|")));
+    }
+}